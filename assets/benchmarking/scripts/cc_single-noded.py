@@ -25,7 +25,7 @@ for ((i=0; i<={WARM_UPS}; i+=1)); do
     srun -N {node_number}-{node_number} \
         -n {worker_number} {BASE_DIR}/target/cc/traffic-sim graph-parts \
         -p multi-threaded \
-        -n {vehicle_load} {BASE_DIR}/assets/graph.json
+        -n {vehicle_load} --output-dir {BASE_DIR}/results/{FILE_NAME}/warmup_$i {BASE_DIR}/assets/graph.json
 done
 
 # run
@@ -33,7 +33,7 @@ for ((i=0; i<={RUNS}; i+=1)); do
     srun -N {node_number}-{node_number} \
         -n {worker_number} {BASE_DIR}/target/cc/traffic-sim graph-parts \
         -p multi-threaded \
-        -n {vehicle_load} {BASE_DIR}/assets/graph.json
+        -n {vehicle_load} --output-dir {BASE_DIR}/results/{FILE_NAME}/run_$i {BASE_DIR}/assets/graph.json
 done
         '''
 
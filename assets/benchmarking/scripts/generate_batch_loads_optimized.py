@@ -25,14 +25,14 @@ for vehicle_load in VEHICLES:
 for ((i=0; i<={WARM_UPS}; i+=1)); do
     srun -N {node_number}-{node_number} \
         -n {worker_number} {BASE_DIR}/target/release/traffic-sim graph-parts \
-        -n {vehicle_load} --mpi -p multi-threaded {BASE_DIR}/assets/graph.json
+        -n {vehicle_load} --mpi -p multi-threaded --output-dir {BASE_DIR}/results/{FILE_NAME}/warmup_$i {BASE_DIR}/assets/graph.json
 done
 
 # run
 for ((i=0; i<={RUNS}; i+=1)); do
     srun -N {node_number}-{node_number} \
         -n {worker_number} {BASE_DIR}/target/release/traffic-sim graph-parts \
-        -n {vehicle_load} --mpi -p multi-threaded {BASE_DIR}/assets/graph.json
+        -n {vehicle_load} --mpi -p multi-threaded --output-dir {BASE_DIR}/results/{FILE_NAME}/run_$i {BASE_DIR}/assets/graph.json
 done
         '''
 
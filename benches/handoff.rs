@@ -0,0 +1,87 @@
+// Micro-benchmark of the bytes per vehicle handed over between ranks and of the time to pack
+// and unpack them, comparing the compact handoff format with encoding whole vehicles.
+// The transfer itself is not measured, the packed buffers never go through MPI.
+// Run with `cargo bench --bench handoff`.
+use std::{
+    hint::black_box,
+    mem::size_of,
+    time::{Duration, Instant},
+};
+
+use traffic_sim::{
+    models::handoff::{Handoff, WireVehicle},
+    NodeId, Vehicle, VehicleBuilder,
+};
+
+const VEHICLES: usize = 1_000;
+const ROUNDS: usize = 100;
+const ROUTE_NODES: usize = 200;
+
+// Vehicles halfway along routes of `ROUTE_NODES` nodes, so the handoff only packs
+// the second half of every route
+fn vehicles() -> Vec<Vehicle> {
+    (0..VEHICLES)
+        .map(|i| {
+            let route: Vec<NodeId> = (i..i + ROUTE_NODES).map(NodeId::new).collect();
+            let half = i + ROUTE_NODES / 2;
+            let v = VehicleBuilder::new()
+                .with_speed(10.0)
+                .with_prev_id(NodeId::new(half))
+                .with_next_id(NodeId::new(half + 1))
+                .with_path_ids(route)
+                .build()
+                .unwrap();
+            assert_eq!(v.cursor, ROUTE_NODES / 2);
+            v
+        })
+        .collect()
+}
+
+// Packs and unpacks copies of the vehicles `ROUNDS` times and returns the nanoseconds
+// per vehicle, without the time to copy them
+fn time_per_vehicle(vehicles: &[Vehicle], mut round_trip: impl FnMut(Vec<Vehicle>)) -> f64 {
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let copies = vehicles.to_vec();
+        let start = Instant::now();
+        round_trip(black_box(copies));
+        elapsed += start.elapsed();
+    }
+    elapsed.as_nanos() as f64 / (ROUNDS * VEHICLES) as f64
+}
+
+fn main() {
+    let vehicles = vehicles();
+
+    let encoded = bincode::serialize(&vehicles).unwrap();
+    let whole_time = time_per_vehicle(&vehicles, |copies| {
+        let bytes = bincode::serialize(&copies).unwrap();
+        let decoded: Vec<Vehicle> = bincode::deserialize(&bytes).unwrap();
+        black_box(decoded);
+    });
+
+    let handoff: Handoff = vehicles.iter().cloned().collect();
+    let handoff_bytes = bincode::serialize(&handoff).unwrap().len()
+        + handoff.vehicles.len() * size_of::<WireVehicle>()
+        + handoff.routes.len() * size_of::<u32>();
+    let handoff_time = time_per_vehicle(&vehicles, |copies| {
+        let handoff: Handoff = copies.into_iter().collect();
+        let body = bincode::serialize(&handoff).unwrap();
+        // the vehicles and routes are sent as buffers of their own next to the message
+        let mut decoded: Handoff = bincode::deserialize(&body).unwrap();
+        decoded.vehicles = handoff.vehicles;
+        decoded.routes = handoff.routes;
+        black_box(decoded.into_vehicles().unwrap());
+    });
+
+    println!(
+        "whole vehicles: {:>6.1} bytes per vehicle, {:>7.1} ns to pack and unpack one",
+        encoded.len() as f64 / VEHICLES as f64,
+        whole_time
+    );
+    println!(
+        "handoff format: {:>6.1} bytes per vehicle, {:>7.1} ns to pack and unpack one",
+        handoff_bytes as f64 / VEHICLES as f64,
+        handoff_time
+    );
+}
@@ -0,0 +1,60 @@
+// Micro-benchmark of the route searches of the vehicle generation,
+// comparing plain A* searches with searches guided by landmarks (ALT).
+// Run with `cargo bench --bench routing`.
+use std::{hint::black_box, time::Instant};
+
+use petgraph::algo::astar;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use traffic_sim::{
+    graph::{
+        input::parse_input, landmarks::Landmarks, strongly_connected_components,
+        validation::CleaningOptions,
+    },
+    models::vehicle_class::VehicleClass,
+    NodeId,
+};
+
+const QUERIES: usize = 2_000;
+const LANDMARKS: [usize; 3] = [4, 8, 16];
+
+// Runs all queries and returns the microseconds per query
+fn time_per_query(pairs: &[(NodeId, NodeId)], mut query: impl FnMut(NodeId, NodeId)) -> f64 {
+    let start = Instant::now();
+    for &(from, to) in pairs {
+        query(from, to);
+    }
+    start.elapsed().as_micros() as f64 / pairs.len() as f64
+}
+
+fn main() {
+    let graph = parse_input("assets/graph.json".as_ref(), CleaningOptions::default()).unwrap();
+    let roads = graph.class_graph(&VehicleClass::car());
+    let nodes = strongly_connected_components(&roads).swap_remove(0);
+    let mut rng = StdRng::seed_from_u64(1);
+    let pairs: Vec<(NodeId, NodeId)> = (0..QUERIES)
+        .map(|_| {
+            (
+                nodes[rng.gen_range(0..nodes.len())],
+                nodes[rng.gen_range(0..nodes.len())],
+            )
+        })
+        .collect();
+
+    let plain = time_per_query(&pairs, |from, to| {
+        black_box(astar(&roads, from, |n| n == to, |e| *e.2, |_| 0.0));
+    });
+    println!("plain A*:      {:>8.1} µs per route", plain);
+
+    for count in LANDMARKS {
+        let start = Instant::now();
+        let landmarks = Landmarks::new(&roads, &nodes, count);
+        let preprocessing = start.elapsed().as_millis();
+        let guided = time_per_query(&pairs, |from, to| {
+            black_box(landmarks.route(&roads, from, to));
+        });
+        println!(
+            "{:>2} landmarks: {:>8.1} µs per route after {} ms of preprocessing",
+            count, guided, preprocessing
+        );
+    }
+}
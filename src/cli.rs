@@ -1,6 +1,19 @@
 use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::{
+    config::{
+        DEFAULT_CONGESTION_THRESHOLD, DEFAULT_CONVERGENCE_GAP, DEFAULT_KEEP_RIGHT_BIAS,
+        DEFAULT_LANE_CHANGE_THRESHOLD, DEFAULT_LOGIT_SCALE, DEFAULT_MAX_ATTEMPTS,
+        DEFAULT_MAX_ITERATIONS, DEFAULT_MAX_SPEED, DEFAULT_MAX_TRIP_LENGTH, DEFAULT_MIN_SPEED,
+        DEFAULT_NUM_VEHICLES, DEFAULT_POLITENESS, DEFAULT_REROUTE_INTERVAL,
+        DEFAULT_SAFE_DECELERATION,
+    },
+    graph::osm_graph::Osmid,
+    models::{incident::Event, vehicle_class::VehicleClass},
+};
+
 /// This struct contains all the arguments captured from the command line.
 #[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about=None)]
@@ -11,36 +24,38 @@ pub struct Cli {
 }
 
 #[derive(Clone, Debug, Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
     /// Run the simulation with the partitioned graph
     GraphParts {
         /// Path of the JSON file for the Graph
-        input_file: PathBuf,
+        #[arg(required_unless_present = "config")]
+        input_file: Option<PathBuf>,
+
+        /// Scenario file (TOML, YAML or JSON). Flags given on the command line override its values
+        #[arg(short, long)]
+        config: Option<PathBuf>,
 
         /// Mininum Vehicle Speed in m/s
-        #[arg(long, default_value = "8.5")]
+        #[arg(long, default_value_t = DEFAULT_MIN_SPEED)]
         min_speed: f64,
 
         /// Maximum Vehicle Speed in m/s
-        #[arg(long, default_value = "13.8")]
+        #[arg(long, default_value_t = DEFAULT_MAX_SPEED)]
         max_speed: f64,
 
         /// Whether to run it sequential or parallel
-        #[arg(short, long, default_value_t=Parallelism::SingleThreaded, value_enum)]
+        #[arg(short, long, default_value_t, value_enum)]
         parallelism: Parallelism,
 
         /// Number of Vehicles
-        #[arg(short, long, default_value = "1")]
+        #[arg(short, long, default_value_t = DEFAULT_NUM_VEHICLES)]
         num_vehicles: usize,
 
         /// Logging level
         #[arg(short, long, default_value_t=LoggingLevel::Info, value_enum)]
         logging_level: LoggingLevel,
 
-        /// Whether to use Tokio or Rust Threads
-        #[arg(short, long, default_value_t=ThreadRuntime::RustThreads, value_enum)]
-        thread_runtime: ThreadRuntime,
-
         /// Use MPI. Requires MPI to be installed
         #[arg(short, long, default_value = "false")]
         mpi: bool,
@@ -48,10 +63,181 @@ pub enum Commands {
         /// Error rate
         #[arg(short, long, default_value = "0.0")]
         error_rate: f64,
+
+        /// Restrict the graph to its largest strongly connected component
+        #[arg(long, default_value = "false")]
+        largest_component: bool,
+
+        /// Strongly connected component routes are sampled from, 0 being the largest
+        #[arg(long, default_value = "0")]
+        component: usize,
+
+        /// Minimum trip length in meters
+        #[arg(long, default_value = "0.0")]
+        min_trip_length: f64,
+
+        /// Maximum trip length in meters
+        #[arg(long, default_value_t = DEFAULT_MAX_TRIP_LENGTH)]
+        max_trip_length: f64,
+
+        /// Number of attempts to find a trip before vehicle generation fails
+        #[arg(long, default_value_t = DEFAULT_MAX_ATTEMPTS)]
+        max_attempts: usize,
+
+        /// Number of landmarks guiding the route searches of the vehicle generation, plain A* searches if 0
+        #[arg(long, default_value = "0")]
+        landmarks: usize,
+
+        /// Directory the landmarks are cached in. They are computed on every run if omitted
+        #[arg(long)]
+        landmark_cache: Option<PathBuf>,
+
+        /// Let every MPI leaf generate the vehicles starting in its partition, instead of the root generating all of them
+        #[arg(long, default_value = "false")]
+        generate_on_leafs: bool,
+
+        /// How the graph is split between the MPI ranks
+        #[arg(long, default_value_t, value_enum)]
+        partitioner: Partitioner,
+
+        /// Seed for the random number generator. A random seed is used if omitted
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Maximum number of steps a vehicle drives before it is taken off the road
+        #[arg(long)]
+        duration: Option<u64>,
+
+        /// Directory the effective configuration of the run is written to
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+
+        /// Distribution vehicle speeds are drawn from, truncated to the minimum and maximum speed
+        #[arg(long, default_value_t, value_enum)]
+        speed_distribution: SpeedDistributionKind,
+
+        /// Mean vehicle speed in m/s for the normal and log-normal distribution
+        #[arg(long)]
+        speed_mean: Option<f64>,
+
+        /// Standard deviation of the vehicle speed in m/s for the normal and log-normal distribution
+        #[arg(long)]
+        speed_std_dev: Option<f64>,
+
+        /// CSV file with `lower,upper,weight` rows for the empirical distribution
+        #[arg(long)]
+        speed_histogram: Option<PathBuf>,
+
+        /// Shares of the vehicle classes, e.g. `car=0.8,truck=0.15,bicycle=0.05`
+        #[arg(long)]
+        class_mix: Option<String>,
+
+        /// TOML, YAML or JSON file with intersection controllers, added to those derived from OSM tags
+        #[arg(long)]
+        intersections: Option<PathBuf>,
+
+        /// Share of informed drivers, who re-plan their routes by the current travel times
+        #[arg(long, default_value = "0.0")]
+        informed_share: f64,
+
+        /// Seconds between two periodic re-plans of the informed drivers
+        #[arg(long, default_value_t = DEFAULT_REROUTE_INTERVAL)]
+        reroute_interval: u64,
+
+        /// Ratio of current to free-flow travel time of the next edge above which informed drivers re-plan
+        #[arg(long, default_value_t = DEFAULT_CONGESTION_THRESHOLD)]
+        congestion_threshold: f64,
+
+        /// Vehicle classes defined in the scenario file
+        #[arg(skip)]
+        vehicle_classes: Vec<VehicleClass>,
+
+        /// TOML, YAML or JSON file with events closing edges and nodes or reducing speeds and capacities
+        #[arg(long)]
+        event_file: Option<PathBuf>,
+
+        /// Events defined in the scenario file
+        #[arg(skip)]
+        events: Vec<Event<Osmid>>,
+
+        /// TOML, YAML or JSON file with the parking spaces at the destinations, unlimited if omitted
+        #[arg(long)]
+        parking: Option<PathBuf>,
+
+        /// TOML, YAML or JSON file with activity chains, making vehicles drive from activity to activity
+        #[arg(long)]
+        trip_chains: Option<PathBuf>,
+
+        /// TOML, YAML or JSON feed of transit lines with their routes, stops and timetables
+        #[arg(long)]
+        transit: Option<PathBuf>,
+
+        /// Repeat the run, letting drivers choose their routes from the travel times of the previous run
+        #[arg(long, value_enum)]
+        assign: Option<AssignmentMethod>,
+
+        /// Largest number of runs of the traffic assignment
+        #[arg(long, default_value_t = DEFAULT_MAX_ITERATIONS)]
+        max_iterations: usize,
+
+        /// Relative gap at or below which the traffic assignment stops
+        #[arg(long, default_value_t = DEFAULT_CONVERGENCE_GAP)]
+        convergence_gap: f64,
+
+        /// Sensitivity of the logit route choice to travel time differences in 1/s
+        #[arg(long, default_value_t = DEFAULT_LOGIT_SCALE)]
+        logit_scale: f64,
+
+        /// Weight of the advantage or disadvantage of the surrounding vehicles in lane changes, from 0 to 1
+        #[arg(long, default_value_t = DEFAULT_POLITENESS)]
+        politeness: f64,
+
+        /// Acceleration gain in m/s² a lane change has to bring
+        #[arg(long, default_value_t = DEFAULT_LANE_CHANGE_THRESHOLD)]
+        lane_change_threshold: f64,
+
+        /// Strongest deceleration in m/s² a lane change may impose on the new follower
+        #[arg(long, default_value_t = DEFAULT_SAFE_DECELERATION)]
+        safe_deceleration: f64,
+
+        /// Acceleration gain in m/s² granted to lane changes to the right
+        #[arg(long, default_value_t = DEFAULT_KEEP_RIGHT_BIAS)]
+        keep_right_bias: f64,
+    },
+    /// Convert a JSON graph into the binary graph cache
+    Convert {
+        /// Path of the JSON file for the Graph
+        input_file: PathBuf,
+
+        /// Path of the binary cache. Defaults to the input file with a `.bin` extension
+        #[arg(short, long)]
+        output_file: Option<PathBuf>,
+
+        /// Logging level
+        #[arg(short, long, default_value_t=LoggingLevel::Info, value_enum)]
+        logging_level: LoggingLevel,
+    },
+    /// Validate a graph and report its defects
+    Validate {
+        /// Path of the JSON file or graph cache for the Graph
+        input_file: PathBuf,
+
+        /// Restrict the graph to its largest strongly connected component
+        #[arg(long, default_value = "false")]
+        largest_component: bool,
+
+        /// Write the cleaned graph as JSON to this path
+        #[arg(short, long)]
+        output_file: Option<PathBuf>,
+
+        /// Logging level
+        #[arg(short, long, default_value_t=LoggingLevel::Info, value_enum)]
+        logging_level: LoggingLevel,
     },
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum LoggingLevel {
     /// Debug logging
     Debug,
@@ -63,18 +249,51 @@ pub enum LoggingLevel {
     Error,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum Parallelism {
     /// Run in a single threaded
+    #[default]
     SingleThreaded,
     /// Run in multiple threads on a single node
     MultiThreaded,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-pub enum ThreadRuntime {
-    /// Run with Tokio
-    Tokio,
-    /// Run with Rust Threads
-    RustThreads,
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeedDistributionKind {
+    /// Uniform between the minimum and maximum speed
+    #[default]
+    Uniform,
+    /// Normal distribution with the given mean and standard deviation
+    Normal,
+    /// Log-normal distribution with the given mean and standard deviation
+    LogNormal,
+    /// Histogram read from a CSV file
+    Empirical,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssignmentMethod {
+    /// Method of successive averages: a shrinking share of the drivers switches to the fastest route
+    Msa,
+    /// Logit model: every driver draws one of the routes found so far, favouring fast ones
+    Logit,
+}
+
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Partitioner {
+    /// Split the graph into stripes along the x-axis
+    #[default]
+    VerticalStripes,
+    /// Split the graph into stripes along the y-axis
+    HorizontalStripes,
 }
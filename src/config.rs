@@ -0,0 +1,496 @@
+//! Scenario files
+
+use std::path::{Path, PathBuf};
+
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    cli::{
+        AssignmentMethod, Cli, Commands, LoggingLevel, Parallelism, Partitioner,
+        SpeedDistributionKind,
+    },
+    graph::osm_graph::Osmid,
+    models::{incident::Event, speed::SpeedDistribution, vehicle_class::VehicleClass},
+    prelude::*,
+};
+
+// Name of the file the effective configuration is written to
+pub const EFFECTIVE_CONFIG_FILE: &str = "scenario.toml";
+
+// Defaults of the values a scenario and the command line leave out,
+// shared by the command line and `SimulationBuilder::from_scenario`.
+// Values not listed here default to zero, false or the first variant.
+pub const DEFAULT_NUM_VEHICLES: usize = 1;
+pub const DEFAULT_MIN_SPEED: f64 = 8.5;
+pub const DEFAULT_MAX_SPEED: f64 = 13.8;
+pub const DEFAULT_MAX_TRIP_LENGTH: f64 = f64::INFINITY;
+pub const DEFAULT_MAX_ATTEMPTS: usize = 1000;
+pub const DEFAULT_REROUTE_INTERVAL: u64 = 60;
+pub const DEFAULT_CONGESTION_THRESHOLD: f64 = 2.0;
+pub const DEFAULT_MAX_ITERATIONS: usize = 20;
+pub const DEFAULT_CONVERGENCE_GAP: f64 = 0.01;
+pub const DEFAULT_LOGIT_SCALE: f64 = 0.1;
+pub const DEFAULT_POLITENESS: f64 = 0.3;
+pub const DEFAULT_LANE_CHANGE_THRESHOLD: f64 = 0.1;
+pub const DEFAULT_SAFE_DECELERATION: f64 = 4.0;
+pub const DEFAULT_KEEP_RIGHT_BIAS: f64 = 0.2;
+
+/// Contents of a scenario file.
+/// Every value is optional, values missing from the file keep their command line defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    /// Path of the JSON file or graph cache for the Graph.
+    pub input_file: Option<PathBuf>,
+    /// Number of vehicles.
+    pub num_vehicles: Option<usize>,
+    /// Minimum vehicle speed in m/s.
+    pub min_speed: Option<f64>,
+    /// Maximum vehicle speed in m/s.
+    pub max_speed: Option<f64>,
+    /// Whether to run sequential or parallel.
+    pub parallelism: Option<Parallelism>,
+    /// Whether to use MPI.
+    pub mpi: Option<bool>,
+    /// Error rate.
+    pub error_rate: Option<f64>,
+    /// Logging level.
+    pub logging_level: Option<LoggingLevel>,
+    /// Restrict the graph to its largest strongly connected component.
+    pub largest_component: Option<bool>,
+    /// Strongly connected component routes are sampled from.
+    pub component: Option<usize>,
+    /// Minimum trip length in meters.
+    pub min_trip_length: Option<f64>,
+    /// Maximum trip length in meters.
+    pub max_trip_length: Option<f64>,
+    /// Number of attempts to find a trip.
+    pub max_attempts: Option<usize>,
+    /// Number of landmarks guiding the route searches.
+    pub landmarks: Option<usize>,
+    /// Directory the landmarks are cached in.
+    pub landmark_cache: Option<PathBuf>,
+    /// Whether the MPI leafs generate the vehicles.
+    pub generate_on_leafs: Option<bool>,
+    /// How the graph is split between the MPI ranks.
+    pub partitioner: Option<Partitioner>,
+    /// Seed for the random number generator.
+    pub seed: Option<u64>,
+    /// Maximum number of steps a vehicle drives.
+    pub duration: Option<u64>,
+    /// Directory the outputs of the run are written to.
+    pub output_dir: Option<PathBuf>,
+    /// Distribution vehicle speeds are drawn from.
+    pub speed_distribution: Option<SpeedDistributionKind>,
+    /// Mean vehicle speed in m/s.
+    pub speed_mean: Option<f64>,
+    /// Standard deviation of the vehicle speed in m/s.
+    pub speed_std_dev: Option<f64>,
+    /// CSV histogram of vehicle speeds.
+    pub speed_histogram: Option<PathBuf>,
+    /// Shares of the vehicle classes.
+    pub class_mix: Option<String>,
+    /// File with intersection controllers.
+    pub intersections: Option<PathBuf>,
+    /// Share of informed drivers.
+    pub informed_share: Option<f64>,
+    /// Seconds between two periodic re-plans of the informed drivers.
+    pub reroute_interval: Option<u64>,
+    /// Congestion ratio above which informed drivers re-plan.
+    pub congestion_threshold: Option<f64>,
+    /// Custom vehicle classes, usable in the class mix next to the built-in ones.
+    pub vehicle_classes: Option<Vec<VehicleClass>>,
+    /// File with events changing the road network.
+    pub event_file: Option<PathBuf>,
+    /// Events changing the road network, in addition to those of the event file.
+    pub events: Option<Vec<Event<Osmid>>>,
+    /// File with the parking spaces at the destinations.
+    pub parking: Option<PathBuf>,
+    /// File with the activity chains of the vehicles.
+    pub trip_chains: Option<PathBuf>,
+    /// Feed of the transit lines.
+    pub transit: Option<PathBuf>,
+    /// Route choice model of the traffic assignment.
+    pub assign: Option<AssignmentMethod>,
+    /// Largest number of runs of the traffic assignment.
+    pub max_iterations: Option<usize>,
+    /// Relative gap at or below which the traffic assignment stops.
+    pub convergence_gap: Option<f64>,
+    /// Sensitivity of the logit route choice in 1/s.
+    pub logit_scale: Option<f64>,
+    /// Weight of the surrounding vehicles in lane changes.
+    pub politeness: Option<f64>,
+    /// Acceleration gain in m/s² a lane change has to bring.
+    pub lane_change_threshold: Option<f64>,
+    /// Strongest deceleration in m/s² a lane change may impose on the new follower.
+    pub safe_deceleration: Option<f64>,
+    /// Acceleration gain in m/s² granted to changes to the right.
+    pub keep_right_bias: Option<f64>,
+}
+
+// Overrides a command value with the scenario value, unless it was given on the command line
+macro_rules! merge {
+    ($matches:ident, $scenario:ident, $($field:ident),+ $(,)?) => {
+        $(
+            if let Some(value) = $scenario.$field {
+                if $matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine) {
+                    *$field = value;
+                }
+            }
+        )+
+    };
+}
+
+// Same as `merge`, for command values that are optional themselves
+macro_rules! merge_optional {
+    ($matches:ident, $scenario:ident, $($field:ident),+ $(,)?) => {
+        $(
+            if $scenario.$field.is_some()
+                && $matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine)
+            {
+                *$field = $scenario.$field;
+            }
+        )+
+    };
+}
+
+// Reads a TOML, YAML or JSON file, chosen by the file extension.
+// Errors name the file they occur in.
+pub(crate) fn read_structured<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&content)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err))),
+        Some("yaml" | "yml") => serde_yaml::from_str(&content)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err))),
+        Some("json") => serde_json::from_str(&content)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err))),
+        _ => Err(Error::Config(format!(
+            "{}: files must end in .toml, .yaml, .yml or .json",
+            path.display()
+        ))),
+    }
+}
+
+impl Scenario {
+    /// Reads a scenario from a TOML, YAML or JSON file, chosen by the file extension.
+    /// Relative paths inside the file are resolved against the directory of the file.
+    pub fn from_file(path: &Path) -> Result<Scenario> {
+        let mut scenario: Scenario = read_structured(path)?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        scenario.input_file = scenario.input_file.map(|p| base.join(p));
+        scenario.output_dir = scenario.output_dir.map(|p| base.join(p));
+        scenario.landmark_cache = scenario.landmark_cache.map(|p| base.join(p));
+        scenario.speed_histogram = scenario.speed_histogram.map(|p| base.join(p));
+        scenario.intersections = scenario.intersections.map(|p| base.join(p));
+        scenario.event_file = scenario.event_file.map(|p| base.join(p));
+        scenario.parking = scenario.parking.map(|p| base.join(p));
+        scenario.trip_chains = scenario.trip_chains.map(|p| base.join(p));
+        scenario.transit = scenario.transit.map(|p| base.join(p));
+        for class in scenario.vehicle_classes.iter_mut().flatten() {
+            if let Some(SpeedDistribution::Empirical { histogram }) = &mut class.speed {
+                *histogram = base.join(&histogram);
+            }
+        }
+        Ok(scenario)
+    }
+
+    /// Captures the effective configuration of a command.
+    pub fn from_command(command: &Commands) -> Option<Scenario> {
+        match command.clone() {
+            Commands::GraphParts {
+                input_file,
+                config: _,
+                min_speed,
+                max_speed,
+                parallelism,
+                num_vehicles,
+                logging_level,
+                mpi,
+                error_rate,
+                largest_component,
+                component,
+                min_trip_length,
+                max_trip_length,
+                max_attempts,
+                landmarks,
+                landmark_cache,
+                generate_on_leafs,
+                partitioner,
+                seed,
+                duration,
+                output_dir,
+                speed_distribution,
+                speed_mean,
+                speed_std_dev,
+                speed_histogram,
+                class_mix,
+                intersections,
+                informed_share,
+                reroute_interval,
+                congestion_threshold,
+                vehicle_classes,
+                event_file,
+                events,
+                parking,
+                trip_chains,
+                transit,
+                assign,
+                max_iterations,
+                convergence_gap,
+                logit_scale,
+                politeness,
+                lane_change_threshold,
+                safe_deceleration,
+                keep_right_bias,
+            } => Some(Scenario {
+                input_file,
+                num_vehicles: Some(num_vehicles),
+                min_speed: Some(min_speed),
+                max_speed: Some(max_speed),
+                parallelism: Some(parallelism),
+                mpi: Some(mpi),
+                error_rate: Some(error_rate),
+                logging_level: Some(logging_level),
+                largest_component: Some(largest_component),
+                component: Some(component),
+                min_trip_length: Some(min_trip_length),
+                max_trip_length: Some(max_trip_length),
+                max_attempts: Some(max_attempts),
+                landmarks: Some(landmarks),
+                landmark_cache,
+                generate_on_leafs: Some(generate_on_leafs),
+                partitioner: Some(partitioner),
+                seed,
+                duration,
+                output_dir,
+                speed_distribution: Some(speed_distribution),
+                speed_mean,
+                speed_std_dev,
+                speed_histogram,
+                class_mix,
+                intersections,
+                informed_share: Some(informed_share),
+                reroute_interval: Some(reroute_interval),
+                congestion_threshold: Some(congestion_threshold),
+                vehicle_classes: Some(vehicle_classes).filter(|c| !c.is_empty()),
+                event_file,
+                events: Some(events).filter(|e| !e.is_empty()),
+                parking,
+                trip_chains,
+                transit,
+                assign,
+                max_iterations: Some(max_iterations),
+                convergence_gap: Some(convergence_gap),
+                logit_scale: Some(logit_scale),
+                politeness: Some(politeness),
+                lane_change_threshold: Some(lane_change_threshold),
+                safe_deceleration: Some(safe_deceleration),
+                keep_right_bias: Some(keep_right_bias),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Writes the scenario as TOML into the given directory.
+    pub fn write_to(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let content = toml::to_string_pretty(self).map_err(|err| Error::Config(err.to_string()))?;
+        let path = dir.join(EFFECTIVE_CONFIG_FILE);
+        std::fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    // Applies the scenario to a command. Values given on the command line take precedence.
+    fn apply(self, command: &mut Commands, matches: &ArgMatches) {
+        if let Commands::GraphParts {
+            input_file,
+            config: _,
+            min_speed,
+            max_speed,
+            parallelism,
+            num_vehicles,
+            logging_level,
+            mpi,
+            error_rate,
+            largest_component,
+            component,
+            min_trip_length,
+            max_trip_length,
+            max_attempts,
+            landmarks,
+            landmark_cache,
+            generate_on_leafs,
+            partitioner,
+            seed,
+            duration,
+            output_dir,
+            speed_distribution,
+            speed_mean,
+            speed_std_dev,
+            speed_histogram,
+            class_mix,
+            intersections,
+            informed_share,
+            reroute_interval,
+            congestion_threshold,
+            vehicle_classes,
+            event_file,
+            events,
+            parking,
+            trip_chains,
+            transit,
+            assign,
+            max_iterations,
+            convergence_gap,
+            logit_scale,
+            politeness,
+            lane_change_threshold,
+            safe_deceleration,
+            keep_right_bias,
+        } = command
+        {
+            let scenario = self;
+            merge!(
+                matches,
+                scenario,
+                min_speed,
+                max_speed,
+                parallelism,
+                num_vehicles,
+                logging_level,
+                mpi,
+                error_rate,
+                largest_component,
+                component,
+                min_trip_length,
+                max_trip_length,
+                max_attempts,
+                landmarks,
+                generate_on_leafs,
+                partitioner,
+                speed_distribution,
+                informed_share,
+                reroute_interval,
+                congestion_threshold,
+                max_iterations,
+                convergence_gap,
+                logit_scale,
+                politeness,
+                lane_change_threshold,
+                safe_deceleration,
+                keep_right_bias,
+            );
+            merge_optional!(
+                matches,
+                scenario,
+                input_file,
+                seed,
+                landmark_cache,
+                duration,
+                output_dir,
+                speed_mean,
+                speed_std_dev,
+                speed_histogram,
+                class_mix,
+                intersections,
+                event_file,
+                parking,
+                trip_chains,
+                transit,
+                assign,
+            );
+            if let Some(classes) = scenario.vehicle_classes {
+                *vehicle_classes = classes;
+            }
+            if let Some(scheduled) = scenario.events {
+                *events = scheduled;
+            }
+        }
+    }
+}
+
+// Parses the command line and merges the scenario file given with `--config` into it
+pub fn load_cli() -> Result<Cli> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    let config = match &cli.command {
+        Commands::GraphParts { config, .. } => config.clone(),
+        _ => None,
+    };
+
+    if let (Some(path), Some((_, sub_matches))) = (config, matches.subcommand()) {
+        let scenario = Scenario::from_file(&path)?;
+        scenario.apply(&mut cli.command, sub_matches);
+    }
+
+    Ok(cli)
+}
+
+// Unit tests for scenario files
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::temp_path;
+
+    fn load(args: &[&str], scenario: Scenario) -> Commands {
+        let matches = Cli::command().get_matches_from(args);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        scenario.apply(&mut cli.command, sub_matches);
+        cli.command
+    }
+
+    #[test]
+    fn test_command_line_overrides_scenario() {
+        let scenario: Scenario = toml::from_str(
+            r#"
+            input_file = "graph.json"
+            num_vehicles = 50
+            min_speed = 5.0
+            parallelism = "multi-threaded"
+            seed = 7
+            "#,
+        )
+        .unwrap();
+
+        let command = load(
+            &["traffic-sim", "graph-parts", "-c", "s.toml", "-n", "10"],
+            scenario,
+        );
+        let effective = Scenario::from_command(&command).unwrap();
+
+        assert_eq!(effective.input_file, Some(PathBuf::from("graph.json")));
+        assert_eq!(effective.num_vehicles, Some(10));
+        assert_eq!(effective.min_speed, Some(5.0));
+        assert_eq!(effective.max_speed, Some(13.8));
+        assert_eq!(effective.parallelism, Some(Parallelism::MultiThreaded));
+        assert_eq!(effective.seed, Some(7));
+    }
+
+    #[test]
+    fn test_yaml_scenarios_are_read() {
+        let dir = temp_path("yaml");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scenario.yaml");
+        std::fs::write(
+            &path,
+            "input_file: graph.json\nnum_vehicles: 50\nparallelism: multi-threaded\n",
+        )
+        .unwrap();
+
+        let scenario = Scenario::from_file(&path).unwrap();
+        assert_eq!(scenario.input_file, Some(dir.join("graph.json")));
+        assert_eq!(scenario.num_vehicles, Some(50));
+        assert_eq!(scenario.parallelism, Some(Parallelism::MultiThreaded));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        assert!(toml::from_str::<Scenario>("num_vehicle = 5").is_err());
+    }
+}
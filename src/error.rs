@@ -1,17 +1,49 @@
 //! Main Crate Error
 
+/// Errors of the simulator, grouped by the stage they happen in.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    /// For starter, to remove as code matures.
-    #[error("Generic error: {0}")]
-    Generic(String),
-
+    /// A file could not be read or written.
     #[error(transparent)]
     IO(#[from] std::io::Error),
 
-    #[error("Vector is empty")]
-    EmptyVector(String),
+    /// The input file does not contain a graph.
+    #[error("input error: {0}")]
+    Input(String),
 
+    /// A graph cache, landmark cache or message between the ranks could not be encoded or decoded.
     #[error("bincode error: {0}")]
     Bincode(#[from] bincode::Error),
+
+    /// A JSON graph, scenario or metrics file could not be parsed or written.
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// A graph or landmark cache is corrupted or was written by another version or for another graph.
+    #[error("graph cache error: {0}")]
+    Cache(String),
+
+    /// The graph cannot carry the simulation, e.g. it has no vertices or too few connected ones.
+    #[error("graph error: {0}")]
+    Graph(String),
+
+    /// A vehicle has no valid route, e.g. no trip satisfies the constraints or the route breaks off.
+    #[error("routing error: {0}")]
+    Routing(String),
+
+    /// The graph could not be split among the ranks.
+    #[error("partitioning error: {0}")]
+    Partition(String),
+
+    /// A message between the ranks was malformed or unexpected.
+    #[error("MPI protocol error: {0}")]
+    Protocol(String),
+
+    /// MPI could not be set up.
+    #[error("MPI error: {0}")]
+    Mpi(String),
+
+    /// The command line, a scenario or one of its files has an invalid or inconsistent setting.
+    #[error("configuration error: {0}")]
+    Config(String),
 }
@@ -0,0 +1,47 @@
+// Fixtures shared by the unit tests
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    graph::osm_graph::{OSMGraph, Osmid},
+    models::{graph_input::Edge, parking::Parking},
+};
+
+// An edge of the given length with a speed limit of 50 km/h and no road type
+pub(crate) fn edge(from: Osmid, to: Osmid, length: f64) -> Edge {
+    Edge {
+        from,
+        to,
+        length,
+        max_speed: String::from("50"),
+        name: String::new(),
+        osm_id: String::new(),
+        highway: None,
+        lanes: None,
+        lanes_forward: None,
+        lanes_backward: None,
+        reversed: false,
+    }
+}
+
+// A path in the temporary directory, unique to this process
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("traffic-sim-{}-{}", std::process::id(), name))
+}
+
+// Loads the parking spaces of a parking file with the given content
+pub(crate) fn load_parking(graph: &OSMGraph, content: &str) -> Parking {
+    // tests run in parallel, so every file gets a name of its own
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    let path = temp_path(&format!(
+        "parking-{}.toml",
+        FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, content).unwrap();
+    let parking = Parking::load(graph, Some(&path));
+    std::fs::remove_file(&path).unwrap();
+    parking.unwrap()
+}
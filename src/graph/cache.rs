@@ -0,0 +1,225 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use bincode::{deserialize_from, serialize_into};
+use serde::{Deserialize, Serialize};
+
+use crate::{models::graph_input::GraphInput, prelude::*};
+
+// Magic bytes at the start of every binary graph cache
+pub const CACHE_MAGIC: &[u8; 8] = b"TSIMGRPH";
+
+// Version of the binary layout. Bump this whenever `GraphInput` changes its shape.
+pub const CACHE_VERSION: u32 = 6;
+
+// File extension used for caches placed next to their JSON source
+pub const CACHE_EXTENSION: &str = "bin";
+
+/// Size and modification time of a file, which tell whether it changed without reading it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SourceStamp {
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// The modification time in nanoseconds since the Unix epoch.
+    pub modified: u64,
+}
+
+impl SourceStamp {
+    /// Reads the stamp of a file from its metadata.
+    pub fn of(path: &Path) -> Result<SourceStamp> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Ok(SourceStamp {
+            len: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Header written in front of the serialized graph.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CacheHeader {
+    /// The layout version the cache was written with.
+    pub version: u32,
+    /// Size and modification time of the JSON file the cache was created from.
+    pub source_stamp: SourceStamp,
+    /// Checksum of the JSON file the cache was created from.
+    pub source_checksum: u64,
+    /// Checksum of the serialized graph following the header.
+    pub payload_checksum: u64,
+}
+
+// FNV-1a hash, stable across platforms and compiler versions
+pub fn checksum(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Default location of the cache belonging to a JSON input file
+pub fn cache_path_for(input_file: &Path) -> PathBuf {
+    input_file.with_extension(CACHE_EXTENSION)
+}
+
+// Checks whether the given file starts with the cache magic bytes
+pub fn is_cache_file(path: &Path) -> Result<bool> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 8];
+    match file.read_exact(&mut magic) {
+        Ok(_) => Ok(&magic == CACHE_MAGIC),
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Writes the graph into a binary cache, tagged with the stamp and checksum of its JSON source
+pub fn write_cache(
+    model: &GraphInput,
+    source_stamp: SourceStamp,
+    source_checksum: u64,
+    target: &Path,
+) -> Result<CacheHeader> {
+    let payload = bincode::serialize(model)?;
+    let header = CacheHeader {
+        version: CACHE_VERSION,
+        source_stamp,
+        source_checksum,
+        payload_checksum: checksum(&payload),
+    };
+
+    let mut writer = BufWriter::new(File::create(target)?);
+    writer.write_all(CACHE_MAGIC)?;
+    serialize_into(&mut writer, &header)?;
+    writer.write_all(&payload)?;
+    writer.flush()?;
+    Ok(header)
+}
+
+// Reads only the header of a binary cache
+pub fn read_header(path: &Path) -> Result<CacheHeader> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_magic_and_header(&mut reader)
+}
+
+// Reads a binary cache, verifying its version and payload checksum
+pub fn read_cache(path: &Path) -> Result<(CacheHeader, GraphInput)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = read_magic_and_header(&mut reader)?;
+
+    let mut payload = Vec::new();
+    reader.read_to_end(&mut payload)?;
+    if checksum(&payload) != header.payload_checksum {
+        return Err(Error::Cache(format!(
+            "{} is corrupted (payload checksum mismatch)",
+            path.display()
+        )));
+    }
+
+    let model: GraphInput = deserialize_from(&payload[..])?;
+    Ok((header, model))
+}
+
+fn read_magic_and_header<R: Read>(reader: &mut R) -> Result<CacheHeader> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != CACHE_MAGIC {
+        return Err(Error::Cache(String::from("Not a graph cache file")));
+    }
+
+    let header: CacheHeader = deserialize_from(reader)?;
+    if header.version != CACHE_VERSION {
+        return Err(Error::Cache(format!(
+            "Unsupported cache version {}, expected {}",
+            header.version, CACHE_VERSION
+        )));
+    }
+    Ok(header)
+}
+
+// Unit tests for the graph cache
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{edge, temp_path},
+        models::graph_input::{Edge, Graph, Vertex},
+    };
+
+    fn sample_input() -> GraphInput {
+        GraphInput {
+            filename: String::from("sample.osm"),
+            size: 2,
+            graph: Graph {
+                vertices: vec![
+                    Vertex {
+                        x: 1.0,
+                        y: 1.0,
+                        osm_id: 1,
+                        highway: None,
+                    },
+                    Vertex {
+                        x: 2.0,
+                        y: 2.0,
+                        osm_id: 2,
+                        highway: None,
+                    },
+                ],
+                edges: vec![Edge {
+                    name: String::from("Main Street"),
+                    osm_id: String::from("42"),
+                    highway: Some(String::from("residential")),
+                    ..edge(1, 2, 12.5)
+                }],
+            },
+        }
+    }
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let path = temp_path("roundtrip.bin");
+        let model = sample_input();
+
+        let stamp = SourceStamp {
+            len: 100,
+            modified: 5,
+        };
+        let written = write_cache(&model, stamp, 7, &path).unwrap();
+        assert!(is_cache_file(&path).unwrap());
+
+        let (header, read) = read_cache(&path).unwrap();
+        assert_eq!(header, written);
+        assert_eq!((header.source_stamp, header.source_checksum), (stamp, 7));
+        assert_eq!(read.graph.vertices.len(), 2);
+        assert_eq!(read.graph.edges[0].length, 12.5);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_cache_is_rejected() {
+        let path = temp_path("corrupted.bin");
+        let stamp = SourceStamp {
+            len: 100,
+            modified: 5,
+        };
+        write_cache(&sample_input(), stamp, 7, &path).unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        assert!(matches!(read_cache(&path), Err(Error::Cache(_))));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! Reading road graphs from JSON files and binary graph caches.
+
+use std::path::Path;
+
+use crate::{
+    graph::{
+        cache,
+        osm_graph::OSMGraph,
+        validation::{self, CleaningOptions},
+    },
+    models::graph_input::GraphInput,
+    prelude::*,
+};
+
+/// Reads the graph input from the given path.
+/// Binary graph caches are detected by their magic bytes. For JSON files a cache
+/// next to the file is used instead, as long as it was created from the same JSON content.
+/// The content is only read and hashed if the size or modification time of the file changed.
+pub fn get_gi_from_input_file(input_file: &Path) -> Result<GraphInput> {
+    let is_cache = cache::is_cache_file(input_file)
+        .map_err(|err| Error::Input(format!("{}: {}", input_file.display(), err)))?;
+    if is_cache {
+        log::debug!("Loading graph cache {}", input_file.display());
+        let (_, model) = cache::read_cache(input_file)?;
+        return Ok(model);
+    }
+
+    // the input data, once it had to be read to validate the cache
+    let mut data = None;
+
+    let cache_file = cache::cache_path_for(input_file);
+    if cache_file.exists() {
+        match cache::read_header(&cache_file) {
+            Ok(header) => {
+                let stamp = cache::SourceStamp::of(input_file).ok();
+                let fresh = if stamp == Some(header.source_stamp) {
+                    true
+                } else {
+                    let read = read_input(input_file)?;
+                    let same = header.source_checksum == cache::checksum(&read);
+                    data = Some(read);
+                    same
+                };
+                if fresh {
+                    match cache::read_cache(&cache_file) {
+                        Ok((_, model)) => {
+                            log::debug!("Loading graph cache {}", cache_file.display());
+                            return Ok(model);
+                        }
+                        Err(err) => {
+                            log::warn!("Ignoring graph cache {}: {}", cache_file.display(), err)
+                        }
+                    }
+                } else {
+                    log::warn!(
+                        "Graph cache {} is stale, reading {} instead. Run `convert` to refresh it.",
+                        cache_file.display(),
+                        input_file.display()
+                    );
+                }
+            }
+            Err(err) => log::warn!("Ignoring graph cache {}: {}", cache_file.display(), err),
+        }
+    }
+
+    let data = match data {
+        Some(data) => data,
+        None => read_input(input_file)?,
+    };
+    parse_graph_input(input_file, &data)
+}
+
+// Reads the input file, naming it in the error
+pub(crate) fn read_input(input_file: &Path) -> Result<Vec<u8>> {
+    std::fs::read(input_file)
+        .map_err(|err| Error::Input(format!("{}: {}", input_file.display(), err)))
+}
+
+// Parses the JSON graph read from the input file, naming it in the error
+pub(crate) fn parse_graph_input(input_file: &Path, data: &[u8]) -> Result<GraphInput> {
+    serde_json::from_slice(data)
+        .map_err(|err| Error::Input(format!("{}: {}", input_file.display(), err)))
+}
+
+/// Parses the input file into a [`OSMGraph`], cleaning the graph on the way.
+pub fn parse_input(input_file: &Path, options: CleaningOptions) -> Result<OSMGraph> {
+    let model = get_gi_from_input_file(input_file)?;
+    let (graph, report) = validation::clean(model.graph, options);
+    if report.is_clean() {
+        log::debug!("{}", report);
+    } else {
+        log::warn!("Removed defects from the input graph\n{}", report);
+    }
+    // bootstrap the root graph
+    OSMGraph::new(graph)
+}
+
+// Unit tests for reading the graph input
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::{
+        fixtures::{edge, temp_path},
+        models::graph_input::Graph,
+    };
+
+    // A graph input with a single edge of the given length
+    fn json(length: f64) -> String {
+        let model = GraphInput {
+            filename: String::from("sample.osm"),
+            size: 0,
+            graph: Graph {
+                vertices: Vec::new(),
+                edges: vec![edge(1, 2, length)],
+            },
+        };
+        serde_json::to_string(&model).unwrap()
+    }
+
+    // Writes the content and sets the modification time of the file
+    fn write(path: &Path, content: &str, modified: SystemTime) {
+        std::fs::write(path, content).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_cache_is_validated_by_metadata_first() {
+        let input_file = temp_path("input.json");
+        let cache_file = cache::cache_path_for(&input_file);
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let length =
+            |input_file: &Path| get_gi_from_input_file(input_file).unwrap().graph.edges[0].length;
+
+        let original = json(12.5);
+        write(&input_file, &original, modified);
+        let model = parse_graph_input(&input_file, original.as_bytes()).unwrap();
+        let stamp = cache::SourceStamp::of(&input_file).unwrap();
+        cache::write_cache(
+            &model,
+            stamp,
+            cache::checksum(original.as_bytes()),
+            &cache_file,
+        )
+        .unwrap();
+
+        // same size and modification time: the cache is used without looking at the content
+        let changed = json(13.5);
+        assert_eq!(changed.len(), original.len());
+        write(&input_file, &changed, modified);
+        assert_eq!(length(&input_file), 12.5);
+
+        // another modification time: the content is hashed and no longer matches the cache
+        write(&input_file, &changed, modified + Duration::from_secs(1));
+        assert_eq!(length(&input_file), 13.5);
+
+        // a touched file with the original content still matches the cache
+        write(&input_file, &original, modified + Duration::from_secs(2));
+        assert_eq!(length(&input_file), 12.5);
+
+        std::fs::remove_file(input_file).unwrap();
+        std::fs::remove_file(cache_file).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use bincode::{deserialize_from, serialize_into};
+use petgraph::{
+    algo::{astar, dijkstra},
+    prelude::{DiGraphMap, GraphMap},
+    Directed,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph::{cache::checksum, osm_graph::NodeId},
+    prelude::*,
+};
+
+// Magic bytes at the start of every landmark cache
+pub const LANDMARK_MAGIC: &[u8; 8] = b"TSIMLMRK";
+
+// Version of the landmark cache layout. Bump this whenever `Landmarks` changes its shape.
+pub const LANDMARK_VERSION: u32 = 2;
+
+/// Shortest-path costs from and to a few landmark nodes of a graph.
+/// By the triangle inequality they bound the cost between any two nodes from below,
+/// which guides A* searches towards their target (ALT).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Landmarks {
+    /// The landmark nodes.
+    pub nodes: Vec<NodeId>,
+    // Cost from every landmark to every node by index, infinite if unreachable
+    from: Vec<Vec<f64>>,
+    // Cost from every node by index to every landmark, infinite if unreachable
+    to: Vec<Vec<f64>>,
+}
+
+impl Landmarks {
+    /// Picks up to `count` landmarks among the candidates and computes their costs.
+    /// The first landmark is the candidate with the lowest index, every further one is
+    /// the candidate farthest from the landmarks picked so far.
+    pub fn new(
+        graph: &GraphMap<NodeId, f64, Directed>,
+        candidates: &[NodeId],
+        count: usize,
+    ) -> Landmarks {
+        let size = graph.nodes().max().map_or(0, |n| n.index() + 1);
+        let reversed: GraphMap<NodeId, f64, Directed> =
+            DiGraphMap::from_edges(graph.all_edges().map(|(from, to, cost)| (to, from, *cost)));
+        let costs = |graph: &GraphMap<NodeId, f64, Directed>, start: NodeId| {
+            let mut costs = vec![f64::INFINITY; size];
+            for (node, cost) in dijkstra(graph, start, None, |e| *e.2) {
+                costs[node.index()] = cost;
+            }
+            costs
+        };
+
+        let mut landmarks = Landmarks {
+            nodes: Vec::new(),
+            from: Vec::new(),
+            to: Vec::new(),
+        };
+        // cost from the nearest landmark to every candidate
+        let mut nearest = vec![f64::INFINITY; size];
+        let mut next = candidates
+            .iter()
+            .copied()
+            .filter(|n| graph.contains_node(*n))
+            .min();
+        while let Some(node) = next.filter(|_| landmarks.nodes.len() < count) {
+            let from = costs(graph, node);
+            for c in candidates.iter().map(|c| c.index()) {
+                if c < size {
+                    nearest[c] = nearest[c].min(from[c]);
+                }
+            }
+            landmarks.nodes.push(node);
+            landmarks.from.push(from);
+            landmarks.to.push(costs(&reversed, node));
+
+            next = candidates
+                .iter()
+                .copied()
+                .filter(|c| {
+                    let c = c.index();
+                    c < size && nearest[c].is_finite() && nearest[c] > 0.0
+                })
+                .max_by(|a, b| nearest[a.index()].total_cmp(&nearest[b.index()]));
+        }
+        landmarks
+    }
+
+    /// Reads the landmarks of a graph from the cache in `dir`, or computes them and
+    /// writes the cache. Caches are told apart by the edges, candidates and `count`.
+    pub fn cached(
+        dir: &Path,
+        graph: &GraphMap<NodeId, f64, Directed>,
+        candidates: &[NodeId],
+        count: usize,
+    ) -> Result<Landmarks> {
+        let edges: Vec<(NodeId, NodeId, f64)> = graph
+            .all_edges()
+            .map(|(from, to, cost)| (from, to, *cost))
+            .collect();
+        let key = checksum(&bincode::serialize(&(edges, candidates, count))?);
+        let path = dir.join(format!("landmarks-{:016x}.bin", key));
+
+        if path.exists() {
+            match read_landmarks(&path, key) {
+                Ok(landmarks) => {
+                    log::debug!("Read landmarks from {}", path.display());
+                    return Ok(landmarks);
+                }
+                Err(err) => log::warn!("Rebuilding landmarks: {}", err),
+            }
+        }
+
+        let landmarks = Landmarks::new(graph, candidates, count);
+        std::fs::create_dir_all(dir)?;
+        write_landmarks(&path, key, &landmarks)?;
+        log::info!("Wrote landmarks to {}", path.display());
+        Ok(landmarks)
+    }
+
+    /// Returns a lower bound of the cost from one node to another.
+    pub fn lower_bound(&self, from: NodeId, to: NodeId) -> f64 {
+        self.from
+            .iter()
+            .zip(&self.to)
+            .filter_map(|(l_from, l_to)| {
+                let ahead = l_from.get(to.index())? - l_from.get(from.index())?;
+                let behind = l_to.get(from.index())? - l_to.get(to.index())?;
+                Some(ahead.max(behind))
+            })
+            .filter(|bound| bound.is_finite())
+            .fold(0.0, f64::max)
+    }
+
+    /// Finds the cheapest route between two nodes with an A* search guided by the landmarks.
+    /// Returns its cost and nodes.
+    pub fn route(
+        &self,
+        graph: &GraphMap<NodeId, f64, Directed>,
+        from: NodeId,
+        to: NodeId,
+    ) -> Option<(f64, Vec<NodeId>)> {
+        astar(
+            graph,
+            from,
+            |finish| finish == to,
+            |e| *e.2,
+            |node| self.lower_bound(node, to),
+        )
+    }
+}
+
+// Writes landmarks into a cache, tagged with the key of the graph they belong to.
+// Processes building the same cache at once each write a file of their own and move it
+// into place, so no process reads a cache another one is still writing.
+fn write_landmarks(path: &Path, key: u64, landmarks: &Landmarks) -> Result<()> {
+    let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    writer.write_all(LANDMARK_MAGIC)?;
+    serialize_into(&mut writer, &(LANDMARK_VERSION, key))?;
+    serialize_into(&mut writer, landmarks)?;
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+// Reads landmarks from a cache, verifying its version and key
+fn read_landmarks(path: &Path, key: u64) -> Result<Landmarks> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != LANDMARK_MAGIC {
+        return Err(Error::Cache(format!(
+            "{} is not a landmark cache",
+            path.display()
+        )));
+    }
+    let (version, cached_key): (u32, u64) = deserialize_from(&mut reader)?;
+    if version != LANDMARK_VERSION || cached_key != key {
+        return Err(Error::Cache(format!(
+            "{} belongs to another graph or version",
+            path.display()
+        )));
+    }
+    Ok(deserialize_from(&mut reader)?)
+}
+
+// Unit tests for the landmarks
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::temp_path;
+
+    // A grid of 6 x 6 nodes with edges in both directions, costs varying by position
+    fn grid() -> GraphMap<NodeId, f64, Directed> {
+        let mut edges = Vec::new();
+        for y in 0..6 {
+            for x in 0..6 {
+                let node = NodeId(y * 6 + x);
+                let cost = 1.0 + ((x * 7 + y * 3) % 5) as f64;
+                if x < 5 {
+                    let right = NodeId(node.0 + 1);
+                    edges.push((node, right, cost));
+                    edges.push((right, node, cost + 1.0));
+                }
+                if y < 5 {
+                    let below = NodeId(node.0 + 6);
+                    edges.push((node, below, cost + 0.5));
+                    edges.push((below, node, cost));
+                }
+            }
+        }
+        DiGraphMap::from_edges(edges)
+    }
+
+    #[test]
+    fn test_routes_are_as_cheap_as_plain_astar() {
+        let graph = grid();
+        let nodes: Vec<NodeId> = graph.nodes().collect();
+        let landmarks = Landmarks::new(&graph, &nodes, 4);
+        assert_eq!(landmarks.nodes.len(), 4);
+        assert_eq!(landmarks.nodes[0], NodeId(0));
+
+        for from in [0, 5, 17, 30].map(NodeId) {
+            for to in [35, 12, 3, 30].map(NodeId) {
+                let plain = astar(&graph, from, |n| n == to, |e| *e.2, |_| 0.0).unwrap();
+                let (cost, route) = landmarks.route(&graph, from, to).unwrap();
+                assert!((cost - plain.0).abs() < 1e-9);
+                assert!(landmarks.lower_bound(from, to) <= cost + 1e-9);
+                assert_eq!((route[0], route[route.len() - 1]), (from, to));
+            }
+        }
+    }
+
+    #[test]
+    fn test_landmarks_are_cached() {
+        let dir = temp_path("landmarks");
+        let graph = grid();
+        let nodes: Vec<NodeId> = graph.nodes().collect();
+
+        let built = Landmarks::cached(&dir, &graph, &nodes, 3).unwrap();
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(Landmarks::cached(&dir, &graph, &nodes, 3).unwrap(), built);
+
+        // another number of landmarks gets a cache of its own
+        Landmarks::cached(&dir, &graph, &nodes, 2).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,46 +1,144 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+};
 
-use crate::{graph::rect::Point, models::graph_input::Graph as GI, prelude::Result};
-use petgraph::{prelude::DiGraphMap, Directed};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::Partitioner,
+    graph::rect::Point,
+    models::{
+        graph_input::{Edge, Graph as GI, Vertex},
+        vehicle_class::VehicleClass,
+    },
+    prelude::*,
+};
+use petgraph::{
+    graph::{DiGraph, NodeIndex},
+    prelude::{DiGraphMap, GraphMap},
+    Directed, Direction,
+};
 use rayon::prelude::*;
 
 use super::rect::Rect;
 
+/// The OpenStreetMap (OSM) id of a node, as used in the input and output files.
 pub type Osmid = usize;
 
+/// The dense index of a node in the graph, used everywhere within the simulation.
+/// `OSMGraph::node` and `OSMGraph::osm_id` convert between indices and OSM ids.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct NodeId(pub u32);
+
+impl NodeId {
+    /// Returns the node with the given index.
+    pub fn new(index: usize) -> NodeId {
+        NodeId(index as u32)
+    }
+
+    /// Returns the index of the node, e.g. into vectors holding a value per node.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 /// Define a structure called `OSMGraph` that represents an OpenStreetMap (OSM) graph.
 /// It contains the OSM data and a directed graph for representing connections between nodes.
+/// Nodes are stored by their index, a partition keeps the nodes of the whole graph
+/// and marks the ones belonging to it.
 #[derive(Debug, Default, Clone)]
 pub struct OSMGraph {
     osm: GI,
-    pub graph: petgraph::prelude::GraphMap<Osmid, f64, Directed>,
+    // The nodes of the vertices and of the ends of the edges of the OSM data, in their order.
+    vertex_nodes: Arc<Vec<NodeId>>,
+    edge_nodes: Arc<Vec<(NodeId, NodeId)>>,
+    graph: DiGraph<(), f64, u32>,
+    // Whether a node belongs to the graph, by index.
+    members: Vec<bool>,
+    // Number of nodes belonging to the graph.
+    node_count: usize,
+    // OSM ids of the nodes by index, in ascending order. Empty if the nodes are indexed
+    // by their OSM ids.
+    osm_ids: Arc<Vec<Osmid>>,
+    // Edges leading into the graph that are not part of it, with their lengths.
+    // Vehicles crossing into a partition drive these before reaching its nodes.
+    pub boundary: HashMap<(NodeId, NodeId), f64>,
+    // Number of lanes of the edges with more than one lane.
+    lanes: HashMap<(NodeId, NodeId), usize>,
 }
 
 // Define a trait called `GPartition` for graph partitioning.
 pub trait GPartition {
-    // Method for partitioning a graph into `n` partitions and returning one of them.
-    fn partition(&self, n: usize, i: usize) -> Result<OSMGraph>;
+    // Method for partitioning a graph into `n` partitions with the given strategy
+    // and returning one of them.
+    fn partition(&self, partitioner: Partitioner, n: usize, i: usize) -> Result<OSMGraph>;
 }
 
 // Helper function to determine the rectangular region for a given graph partition.
-fn determine_rects(target_graph: &OSMGraph, n: usize, i: usize) -> Result<OSMGraph> {
+fn determine_rects(
+    target_graph: &OSMGraph,
+    partitioner: Partitioner,
+    n: usize,
+    i: usize,
+) -> Result<OSMGraph> {
     // Clone the list of vertices from the target graph.
-    let vtx_lst = target_graph.osm.vertices.clone();
+    let vtx_lst: Vec<Vertex> = target_graph
+        .input_vertices()
+        .map(|(_, v)| v.clone())
+        .collect();
+    let nodes: HashMap<Osmid, NodeId> = target_graph
+        .input_vertices()
+        .map(|(node, v)| (v.osm_id, node))
+        .collect();
     // Create a rectangle (`Rect`) that encloses all vertices in the target graph.
     let rect = Rect::new(vtx_lst.clone())?;
-    // Calculate the width of each sub-rectangle based on the number of partitions (`n`).
-    let x_delta: f64 = (rect.top_right.x - rect.bottom_left.x) / n as f64;
-    // Calculate the x-offset for the current partition.
-    let x_offset: f64 = x_delta * i as f64;
-
-    // Create a new rectangle with the calculated offset.
-    let offset_bottom_left = Point {
-        x: rect.bottom_left.x + x_offset,
-        y: rect.bottom_left.y,
-    };
-    let offset_top_right = Point {
-        x: offset_bottom_left.x + x_delta,
-        y: rect.top_right.y,
+
+    let (offset_bottom_left, offset_top_right) = match partitioner {
+        Partitioner::VerticalStripes => {
+            // Calculate the width of each sub-rectangle based on the number of partitions (`n`).
+            let x_delta: f64 = (rect.top_right.x - rect.bottom_left.x) / n as f64;
+            // Calculate the x-offset for the current partition.
+            let x_offset: f64 = x_delta * i as f64;
+
+            // Create a new rectangle with the calculated offset.
+            let offset_bottom_left = Point {
+                x: rect.bottom_left.x + x_offset,
+                y: rect.bottom_left.y,
+            };
+            let offset_top_right = Point {
+                x: offset_bottom_left.x + x_delta,
+                y: rect.top_right.y,
+            };
+            (offset_bottom_left, offset_top_right)
+        }
+        Partitioner::HorizontalStripes => {
+            // Calculate the height of each sub-rectangle based on the number of partitions (`n`).
+            let y_delta: f64 = (rect.top_right.y - rect.bottom_left.y) / n as f64;
+            // Calculate the y-offset for the current partition.
+            let y_offset: f64 = y_delta * i as f64;
+
+            // Create a new rectangle with the calculated offset.
+            let offset_bottom_left = Point {
+                x: rect.bottom_left.x,
+                y: rect.bottom_left.y + y_offset,
+            };
+            let offset_top_right = Point {
+                x: rect.top_right.x,
+                y: offset_bottom_left.y + y_delta,
+            };
+            (offset_bottom_left, offset_top_right)
+        }
     };
 
     // Create a new `Rect` instance representing the sub-rectangle.
@@ -52,7 +150,10 @@ fn determine_rects(target_graph: &OSMGraph, n: usize, i: usize) -> Result<OSMGra
 
     // Filter for vertices that fall within the target rectangle.
     let mut t_vrtx = target_rect.vertices.clone();
-    t_vrtx.retain(|x| target_rect.in_rect(*x));
+    t_vrtx.retain(|x| match partitioner {
+        Partitioner::VerticalStripes => target_rect.in_rect(x),
+        Partitioner::HorizontalStripes => target_rect.in_rect_y(x),
+    });
     target_rect.vertices = t_vrtx;
 
     // Extract a set of vertices within the target rectangle.
@@ -60,58 +161,64 @@ fn determine_rects(target_graph: &OSMGraph, n: usize, i: usize) -> Result<OSMGra
         .vertices
         .clone()
         .into_iter()
-        .map(|v| v.osm_id)
-        .collect::<HashSet<Osmid>>();
+        .map(|v| nodes[&v.osm_id])
+        .collect::<HashSet<NodeId>>();
     // Filter edges in the original graph that connect vertices within the target rectangle.
     let inside_edges = target_graph
-        .graph
-        .all_edges()
-        .filter(|e: &(Osmid, Osmid, &f64)| verticies.contains(&e.0) && verticies.contains(&e.1));
+        .edges()
+        .filter(|e: &(NodeId, NodeId, f64)| verticies.contains(&e.0) && verticies.contains(&e.1));
 
     // Create a child graph based on the filtered edges.
-    let child_graph: petgraph::prelude::GraphMap<Osmid, f64, Directed> =
-        DiGraphMap::from_edges(inside_edges);
-
-    let osm_g = OSMGraph {
-        graph: child_graph,
+    let mut osm_g = OSMGraph {
+        graph: DiGraph::with_capacity(target_graph.members.len(), 0),
+        members: vec![false; target_graph.members.len()],
+        node_count: 0,
+        osm_ids: target_graph.osm_ids.clone(),
         osm: target_graph.osm.clone(),
+        vertex_nodes: target_graph.vertex_nodes.clone(),
+        edge_nodes: target_graph.edge_nodes.clone(),
+        boundary: HashMap::new(),
+        lanes: target_graph.lanes.clone(),
     };
+    for _ in 0..target_graph.members.len() {
+        osm_g.graph.add_node(());
+    }
+    for (from, to, length) in inside_edges {
+        osm_g.add_edge(from, to, length);
+    }
 
     Ok(osm_g)
 }
 
 impl GPartition for OSMGraph {
-    fn partition(&self, n: usize, i: usize) -> Result<OSMGraph> {
+    fn partition(&self, partitioner: Partitioner, n: usize, i: usize) -> Result<OSMGraph> {
         // Create a vector to store all possible sub-graphs (rectangles).
         let mut graphs = Vec::<OSMGraph>::new();
 
         // Create all possible sub-graphs based on the specified number of partitions.
         for j in 0..n {
-            let osm_g = determine_rects(self, n, j)?;
+            let osm_g = determine_rects(self, partitioner, n, j)?;
             graphs.push(osm_g);
         }
 
         // Create a set of all vertices present in all sub-graphs.
-        let mut vertex_set = HashSet::<Osmid>::new();
+        let mut vertex_set = HashSet::<NodeId>::new();
         for g in &graphs {
-            let gg = g.graph.clone();
-            let v = gg.nodes();
-            vertex_set.extend(v);
+            vertex_set.extend(g.nodes());
         }
 
         // Create a vector with the difference of all vertices and the set.
-        let mut diff = Vec::<Osmid>::new();
-        let graph = self.graph.clone();
-        for v in graph.nodes() {
+        let mut diff = Vec::<NodeId>::new();
+        for v in self.nodes() {
             if !vertex_set.contains(&v) {
                 diff.push(v);
             }
         }
 
         // Post-Partition Validation
-        let mut all_nodes: HashSet<Osmid> = self.graph.nodes().collect();
+        let mut all_nodes: HashSet<NodeId> = self.nodes().collect();
         for g in &graphs {
-            for node in g.graph.nodes() {
+            for node in g.nodes() {
                 all_nodes.remove(&node);
             }
         }
@@ -122,32 +229,322 @@ impl GPartition for OSMGraph {
             // TODO: Adjust this to add to the nearest partition based on the x-coordinate.
             let first_partition = &mut graphs[0];
             for missing_node in all_nodes {
-                first_partition.graph.add_node(missing_node);
+                first_partition.add_node(missing_node);
             }
         }
 
+        // Collect the edges leading into the specified partition from outside.
+        let mut partition = graphs.swap_remove(i);
+        partition.boundary = self
+            .edges()
+            .filter(|e| partition.contains_node(e.1) && !partition.contains_edge(e.0, e.1))
+            .map(|e| ((e.0, e.1), e.2))
+            .collect();
+
         // Return the specified partition.
-        Ok(graphs[i].clone())
+        Ok(partition)
     }
 }
 
 impl OSMGraph {
-    // Constructor for creating an `OSMGraph` instance from an OSM graph input.
+    // Returns the number of nodes of the graph.
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    // Returns the number of edges of the graph.
+    pub fn edge_count(&self) -> usize {
+        self.graph.edge_count()
+    }
+
+    // Returns the nodes of the graph in ascending order.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.members
+            .iter()
+            .enumerate()
+            .filter(|(_, member)| **member)
+            .map(|(node, _)| NodeId::new(node))
+    }
+
+    // Returns the edges of the graph with their lengths.
+    pub fn edges(&self) -> impl Iterator<Item = (NodeId, NodeId, f64)> + '_ {
+        self.graph.raw_edges().iter().map(|e| {
+            let (from, to) = (e.source().index(), e.target().index());
+            (NodeId::new(from), NodeId::new(to), e.weight)
+        })
+    }
+
+    // Returns whether the node belongs to the graph.
+    pub fn contains_node(&self, node: NodeId) -> bool {
+        self.members.get(node.index()).copied().unwrap_or(false)
+    }
+
+    // Returns whether the edge belongs to the graph.
+    pub fn contains_edge(&self, from: NodeId, to: NodeId) -> bool {
+        self.find_edge(from, to).is_some()
+    }
+
+    // Returns the nodes reachable from the node over one edge.
+    pub fn successors(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.neighbors(node, Direction::Outgoing)
+    }
+
+    // Returns the nodes leading to the node over one edge.
+    pub fn predecessors(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.neighbors(node, Direction::Incoming)
+    }
+
+    // Returns the length of an edge of the graph or of its boundary.
+    pub fn edge_length(&self, from: NodeId, to: NodeId) -> Option<f64> {
+        match self.find_edge(from, to) {
+            Some(length) => Some(length),
+            None => self.boundary.get(&(from, to)).copied(),
+        }
+    }
+
+    // Returns the number of lanes of an edge of the graph or of its boundary.
+    pub fn edge_lanes(&self, from: NodeId, to: NodeId) -> usize {
+        self.lanes.get(&(from, to)).copied().unwrap_or(1)
+    }
+
+    // Builds the graph a vehicle class may drive on, weighted by its routing preference.
+    pub fn class_graph(&self, class: &VehicleClass) -> GraphMap<NodeId, f64, Directed> {
+        let edges = self
+            .input_edges()
+            .filter(|(from, to, e)| self.contains_edge(*from, *to) && class.allows(e))
+            .map(|(from, to, e)| (from, to, class.edge_cost(e)));
+        DiGraphMap::from_edges(edges)
+    }
+
+    // Returns the OSM data the graph was built from, with the nodes given by their OSM ids.
+    pub fn input(&self) -> &GI {
+        &self.osm
+    }
+
+    // Returns the vertices of the OSM data with their nodes.
+    pub fn input_vertices(&self) -> impl Iterator<Item = (NodeId, &Vertex)> + '_ {
+        self.vertex_nodes.iter().copied().zip(&self.osm.vertices)
+    }
+
+    // Returns the edges of the OSM data with the nodes they lead from and to.
+    pub fn input_edges(&self) -> impl Iterator<Item = (NodeId, NodeId, &Edge)> + '_ {
+        self.edge_nodes
+            .iter()
+            .zip(&self.osm.edges)
+            .map(|((from, to), e)| (*from, *to, e))
+    }
+
+    // Returns the OSM id of a node.
+    pub fn osm_id(&self, node: NodeId) -> Osmid {
+        self.osm_ids[node.index()]
+    }
+
+    // Returns the node with the given OSM id, if the whole graph has one.
+    pub fn node(&self, osm_id: Osmid) -> Option<NodeId> {
+        self.osm_ids.binary_search(&osm_id).ok().map(NodeId::new)
+    }
+
+    // Builds the graph after replacing the OSM ids of the input by dense indices,
+    // assigned in ascending order of the OSM ids. The OSM ids are kept for input and output.
     pub fn new(osm_graph: GI) -> Result<OSMGraph> {
-        // Extract edge data from the OSM graph input.
-        let e_lst: Vec<(Osmid, Osmid, f64)> = osm_graph
+        let mut osm_ids: Vec<Osmid> = osm_graph
+            .vertices
+            .iter()
+            .map(|v| v.osm_id)
+            .chain(osm_graph.edges.iter().flat_map(|e| [e.from, e.to]))
+            .collect();
+        osm_ids.par_sort_unstable();
+        osm_ids.dedup();
+        OSMGraph::build(osm_graph, osm_ids)
+    }
+
+    // Builds the graph with the nodes indexed by their position in `osm_ids`.
+    fn build(osm_graph: GI, osm_ids: Vec<Osmid>) -> Result<OSMGraph> {
+        let size = osm_ids.len();
+        if size > u32::MAX as usize {
+            return Err(Error::Graph(format!(
+                "The graph has {} nodes, at most {} are supported",
+                size,
+                u32::MAX
+            )));
+        }
+
+        let index = |osm_id: Osmid| NodeId::new(osm_ids.binary_search(&osm_id).unwrap_or_default());
+        let vertex_nodes: Vec<NodeId> =
+            osm_graph.vertices.iter().map(|v| index(v.osm_id)).collect();
+        let edge_nodes: Vec<(NodeId, NodeId)> = osm_graph
             .edges
             .par_iter()
-            .map(|edge| (edge.from, edge.to, edge.length))
-            .collect::<Vec<(Osmid, Osmid, f64)>>();
+            .map(|e| (index(e.from), index(e.to)))
+            .collect();
 
-        // Create a directed graph from the extracted edge data.
-        let digraphmap: petgraph::prelude::GraphMap<Osmid, f64, Directed> =
-            DiGraphMap::from_edges(&e_lst);
+        // Keep the lane counts of multi-lane edges, sharing the lanes of two-way roads.
+        let keys: HashSet<(NodeId, NodeId)> = edge_nodes.iter().copied().collect();
+        let lanes = edge_nodes
+            .iter()
+            .zip(&osm_graph.edges)
+            .map(|((from, to), e)| ((*from, *to), e.lane_count(keys.contains(&(*to, *from)))))
+            .filter(|(_, lanes)| *lanes > 1)
+            .collect();
 
-        Ok(Self {
-            graph: digraphmap,
+        // Create a directed graph from the extracted edge data.
+        let mut graph = Self {
+            graph: DiGraph::with_capacity(size, edge_nodes.len()),
+            members: vec![false; size],
+            node_count: 0,
+            osm_ids: Arc::new(osm_ids),
+            vertex_nodes: Arc::new(vertex_nodes),
+            edge_nodes: Arc::new(edge_nodes),
             osm: osm_graph,
+            boundary: HashMap::new(),
+            lanes,
+        };
+        for _ in 0..size {
+            graph.graph.add_node(());
+        }
+        for i in 0..graph.edge_nodes.len() {
+            let (from, to) = graph.edge_nodes[i];
+            let length = graph.osm.edges[i].length;
+            graph.add_edge(from, to, length);
+        }
+        Ok(graph)
+    }
+
+    // Adds a node to the graph.
+    fn add_node(&mut self, node: NodeId) {
+        if !self.members[node.index()] {
+            self.members[node.index()] = true;
+            self.node_count += 1;
+        }
+    }
+
+    // Adds an edge with its end nodes to the graph, replacing the length of an existing edge.
+    fn add_edge(&mut self, from: NodeId, to: NodeId, length: f64) {
+        self.add_node(from);
+        self.add_node(to);
+        self.graph.update_edge(
+            NodeIndex::new(from.index()),
+            NodeIndex::new(to.index()),
+            length,
+        );
+    }
+
+    // Returns the length of an edge of the graph.
+    fn find_edge(&self, from: NodeId, to: NodeId) -> Option<f64> {
+        if !self.contains_node(from) {
+            return None;
+        }
+        let edge = self
+            .graph
+            .find_edge(NodeIndex::new(from.index()), NodeIndex::new(to.index()))?;
+        self.graph.edge_weight(edge).copied()
+    }
+
+    // Returns the nodes adjacent to the node in the given direction.
+    fn neighbors(&self, node: NodeId, direction: Direction) -> impl Iterator<Item = NodeId> + '_ {
+        let node = self
+            .contains_node(node)
+            .then(|| NodeIndex::new(node.index()));
+        node.into_iter()
+            .flat_map(move |node| self.graph.neighbors_directed(node, direction))
+            .map(|n| NodeId::new(n.index()))
+    }
+}
+
+// Unit tests for the node indices
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::edge,
+        models::graph_input::{Edge, Vertex},
+    };
+
+    #[test]
+    fn test_osm_ids_are_remapped_to_dense_indices() {
+        let vertices = [9_000_000_001, 4_000_000_000, 7_000_000_000]
+            .into_iter()
+            .map(|osm_id| Vertex {
+                x: 0.0,
+                y: 0.0,
+                osm_id,
+                highway: None,
+            })
+            .collect();
+        let edges = vec![
+            edge(9_000_000_001, 4_000_000_000, 10.0),
+            edge(4_000_000_000, 7_000_000_000, 10.0),
+        ];
+        let graph = OSMGraph::new(GI { vertices, edges }).unwrap();
+
+        let [a, b, c] = [0, 1, 2].map(NodeId);
+        assert_eq!(graph.nodes().collect::<Vec<_>>(), vec![a, b, c]);
+        assert_eq!(graph.osm_id(a), 4_000_000_000);
+        assert_eq!(graph.node(9_000_000_001), Some(c));
+        assert_eq!(graph.node(1), None);
+        assert_eq!(graph.edge_length(c, a), Some(10.0));
+        assert_eq!(graph.successors(a).collect::<Vec<_>>(), vec![b]);
+        assert_eq!(graph.predecessors(a).collect::<Vec<_>>(), vec![c]);
+        // the input keeps the OSM ids
+        assert_eq!(graph.input().edges[0].from, 9_000_000_001);
+        assert!(graph
+            .input_edges()
+            .all(|(from, to, _)| from < NodeId(3) && to < NodeId(3)));
+    }
+
+    #[test]
+    fn test_two_way_roads_share_their_lanes() {
+        let lanes = |from, to, lanes: &str| Edge {
+            lanes: Some(String::from(lanes)),
+            ..edge(from, to, 10.0)
+        };
+        let edges = vec![
+            lanes(1, 2, "3"),
+            lanes(2, 1, "3"),
+            lanes(2, 3, "3"),
+            Edge {
+                lanes_forward: Some(String::from("3")),
+                ..lanes(3, 4, "4")
+            },
+            Edge {
+                lanes_backward: Some(String::from("1")),
+                reversed: true,
+                ..lanes(4, 3, "4")
+            },
+        ];
+        let graph = OSMGraph::new(GI {
+            vertices: Vec::new(),
+            edges,
         })
+        .unwrap();
+
+        let [n1, n2, n3, n4] = [1, 2, 3, 4].map(|osm_id| graph.node(osm_id).unwrap());
+        assert_eq!(graph.edge_lanes(n1, n2), 2);
+        assert_eq!(graph.edge_lanes(n2, n1), 2);
+        assert_eq!(graph.edge_lanes(n2, n3), 3);
+        assert_eq!(graph.edge_lanes(n3, n4), 3);
+        assert_eq!(graph.edge_lanes(n4, n3), 1);
+    }
+
+    #[test]
+    fn test_partitions_share_the_indices() {
+        let vertices = (1..=4)
+            .map(|i| Vertex {
+                x: i as f64,
+                y: 0.0,
+                osm_id: i * 100,
+                highway: None,
+            })
+            .collect();
+        let edges = (1..4).map(|i| edge(i * 100, (i + 1) * 100, 10.0)).collect();
+        let graph = OSMGraph::new(GI { vertices, edges }).unwrap();
+        let parts: Vec<OSMGraph> = (0..2)
+            .map(|i| graph.partition(Partitioner::VerticalStripes, 2, i).unwrap())
+            .collect();
+
+        assert_eq!(parts.iter().map(|p| p.node_count()).sum::<usize>(), 4);
+        let second = parts.iter().find(|p| p.contains_node(NodeId(3))).unwrap();
+        assert_eq!(second.osm_id(NodeId(3)), 400);
+        assert_eq!(second.boundary.get(&(NodeId(1), NodeId(2))), Some(&10.0));
     }
 }
@@ -3,14 +3,14 @@ use crate::prelude::*;
 
 // Define a Point struct to represent a 2D point with x and y coordinates
 #[derive(Debug, Clone)]
-pub(crate) struct Point {
+pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
 // Define a Rect struct to represent a rectangle, which has two points (top_right and bottom_left) and a list of vertices
 #[derive(Debug, Clone)]
-pub(crate) struct Rect {
+pub struct Rect {
     pub top_right: Point,
     pub bottom_left: Point,
     pub vertices: Vec<Vertex>,
@@ -20,7 +20,7 @@ pub(crate) struct Rect {
 impl Rect {
     pub fn new(vertices: Vec<Vertex>) -> Result<Rect> {
         if vertices.is_empty() {
-            return Err(Error::EmptyVector(String::from(
+            return Err(Error::Graph(String::from(
                 "Vertices vector is empty. Cannot create a rectangle without vertices.",
             )));
         }
@@ -37,12 +37,19 @@ impl Rect {
     }
 
     // Determines if a vertex is inside the rectangle based on its x value only
-    pub fn in_rect(&self, v: Vertex) -> bool {
+    pub fn in_rect(&self, v: &Vertex) -> bool {
         let x = v.x;
         let buffer = 1e-9;
         self.bottom_left.x - buffer <= x && x < self.top_right.x + buffer
     }
 
+    // Determines if a vertex is inside the rectangle based on its y value only
+    pub fn in_rect_y(&self, v: &Vertex) -> bool {
+        let y = v.y;
+        let buffer = 1e-9;
+        self.bottom_left.y - buffer <= y && y < self.top_right.y + buffer
+    }
+
     // Function to set the top_right and bottom_left points based on the vertices
     pub fn set_top_right_bottom_left(mut self) -> Self {
         let vtx_lst = self.vertices.clone();
@@ -90,31 +97,36 @@ mod tests {
                 x: 1.0,
                 y: 1.0,
                 osm_id: 1,
+                highway: None,
             },
             Vertex {
                 x: 2.0,
                 y: 2.0,
                 osm_id: 2,
+                highway: None,
             },
             Vertex {
                 x: 3.0,
                 y: 3.0,
                 osm_id: 3,
+                highway: None,
             },
         ])
         .unwrap();
 
         // Test whether a vertex is inside the rectangle
-        assert!(rect.in_rect(Vertex {
+        assert!(rect.in_rect(&Vertex {
             x: 1.0,
             y: 1.0,
-            osm_id: 1
+            osm_id: 1,
+            highway: None,
         }));
         // Test whether a vertex is outside the rectangle
-        assert!(!rect.in_rect(Vertex {
+        assert!(!rect.in_rect(&Vertex {
             x: 4.0,
             y: 4.0,
-            osm_id: 4
+            osm_id: 4,
+            highway: None,
         }));
     }
 
@@ -126,16 +138,19 @@ mod tests {
                 x: 1.0,
                 y: 1.0,
                 osm_id: 1,
+                highway: None,
             },
             Vertex {
                 x: 2.0,
                 y: 2.0,
                 osm_id: 2,
+                highway: None,
             },
             Vertex {
                 x: 3.0,
                 y: 3.0,
                 osm_id: 3,
+                highway: None,
             },
         ])
         .unwrap();
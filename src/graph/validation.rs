@@ -0,0 +1,260 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use petgraph::prelude::DiGraphMap;
+
+use crate::{
+    graph::{osm_graph::Osmid, strongly_connected_components},
+    models::graph_input::{Graph, Vertex},
+};
+
+/// Options controlling how an input graph is cleaned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CleaningOptions {
+    /// Restrict the graph to its largest strongly connected component.
+    pub largest_component: bool,
+}
+
+/// Findings of the validation pass over an input graph.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// Number of vertex entries removed because their OSM ID was already present.
+    pub duplicate_vertices: usize,
+    /// OSM IDs that appeared several times with different coordinates.
+    pub conflicting_vertices: Vec<Osmid>,
+    /// OSM IDs of vertices without usable coordinates.
+    pub vertices_without_coordinates: Vec<Osmid>,
+    /// Edges referencing a vertex that is not part of the vertex list.
+    pub unknown_vertex_edges: Vec<(Osmid, Osmid)>,
+    /// Edges with a zero, negative or non-finite length.
+    pub non_positive_lengths: Vec<(Osmid, Osmid)>,
+    /// Edges starting and ending at the same vertex.
+    pub self_loops: Vec<Osmid>,
+    /// Number of strongly connected components of the remaining graph.
+    pub components: usize,
+    /// Number of vertices in the largest strongly connected component.
+    pub largest_component: usize,
+    /// Number of vertices removed because they lie outside the largest component.
+    pub removed_outside_component: usize,
+}
+
+impl ValidationReport {
+    /// Returns whether the graph was free of defects.
+    /// Repeated vertices with identical coordinates and being split into several
+    /// components are not considered defects.
+    pub fn is_clean(&self) -> bool {
+        self.conflicting_vertices.is_empty()
+            && self.vertices_without_coordinates.is_empty()
+            && self.unknown_vertex_edges.is_empty()
+            && self.non_positive_lengths.is_empty()
+            && self.self_loops.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Graph validation report")?;
+        writeln!(
+            f,
+            "  duplicate vertices:            {} ({} with conflicting coordinates)",
+            self.duplicate_vertices,
+            self.conflicting_vertices.len()
+        )?;
+        writeln!(
+            f,
+            "  vertices without coordinates:  {}",
+            self.vertices_without_coordinates.len()
+        )?;
+        writeln!(
+            f,
+            "  edges with unknown vertices:   {}",
+            self.unknown_vertex_edges.len()
+        )?;
+        writeln!(
+            f,
+            "  edges with length <= 0:        {}",
+            self.non_positive_lengths.len()
+        )?;
+        writeln!(
+            f,
+            "  self-loops:                    {}",
+            self.self_loops.len()
+        )?;
+        writeln!(f, "  strongly connected components: {}", self.components)?;
+        writeln!(
+            f,
+            "  largest component:             {} vertices",
+            self.largest_component
+        )?;
+        write!(
+            f,
+            "  removed outside component:     {} vertices",
+            self.removed_outside_component
+        )
+    }
+}
+
+// Cleans the graph, removing everything the simulation cannot drive on,
+// and reports what was found.
+// 1. Deduplicates vertices by OSM ID, keeping the first occurrence
+// 2. Removes vertices without coordinates
+// 3. Removes edges referencing unknown vertices, with a length <= 0 or forming a self-loop
+// 4. Optionally restricts the graph to its largest strongly connected component
+pub fn clean(graph: Graph, options: CleaningOptions) -> (Graph, ValidationReport) {
+    let mut report = ValidationReport::default();
+
+    // Deduplicate vertices
+    let mut seen = HashMap::<Osmid, Vertex>::new();
+    let mut vertices = Vec::<Vertex>::new();
+    for vertex in graph.vertices {
+        match seen.get(&vertex.osm_id) {
+            Some(first) => {
+                report.duplicate_vertices += 1;
+                if (first.x != vertex.x || first.y != vertex.y)
+                    && !report.conflicting_vertices.contains(&vertex.osm_id)
+                {
+                    report.conflicting_vertices.push(vertex.osm_id);
+                }
+            }
+            None => {
+                seen.insert(vertex.osm_id, vertex.clone());
+                vertices.push(vertex);
+            }
+        }
+    }
+
+    // Remove vertices without coordinates
+    vertices.retain(|v| {
+        if !v.has_coordinates() {
+            report.vertices_without_coordinates.push(v.osm_id);
+            return false;
+        }
+        true
+    });
+    let known = vertices
+        .iter()
+        .map(|v| v.osm_id)
+        .collect::<HashSet<Osmid>>();
+
+    // Remove defective edges
+    let mut edges = graph.edges;
+    edges.retain(|e| {
+        if !known.contains(&e.from) || !known.contains(&e.to) {
+            report.unknown_vertex_edges.push((e.from, e.to));
+            false
+        } else if e.from == e.to {
+            report.self_loops.push(e.from);
+            false
+        } else if !(e.length > 0.0 && e.length.is_finite()) {
+            report.non_positive_lengths.push((e.from, e.to));
+            false
+        } else {
+            true
+        }
+    });
+
+    // Determine the strongly connected components
+    let digraph: DiGraphMap<Osmid, f64> =
+        DiGraphMap::from_edges(edges.iter().map(|e| (e.from, e.to, e.length)));
+    let components = strongly_connected_components(&digraph);
+    report.components = components.len();
+    report.largest_component = components.first().map_or(0, |c| c.len());
+
+    if options.largest_component {
+        let core = components
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<HashSet<Osmid>>();
+        let before = vertices.len();
+        vertices.retain(|v| core.contains(&v.osm_id));
+        edges.retain(|e| core.contains(&e.from) && core.contains(&e.to));
+        report.removed_outside_component = before - vertices.len();
+    }
+
+    (Graph { vertices, edges }, report)
+}
+
+// Unit tests for the validation pass
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::edge;
+
+    fn vertex(osm_id: Osmid, x: f64) -> Vertex {
+        Vertex {
+            x,
+            y: 1.0,
+            osm_id,
+            highway: None,
+        }
+    }
+
+    #[test]
+    fn test_clean_removes_defects() {
+        let graph = Graph {
+            vertices: vec![
+                vertex(1, 1.0),
+                vertex(2, 2.0),
+                vertex(1, 1.0),
+                vertex(2, 5.0),
+                vertex(3, f64::NAN),
+            ],
+            edges: vec![
+                edge(1, 2, 10.0),
+                edge(2, 1, 10.0),
+                edge(1, 3, 10.0),
+                edge(2, 4, 10.0),
+                edge(1, 1, 10.0),
+                edge(2, 1, 0.0),
+            ],
+        };
+
+        let (cleaned, report) = clean(graph, CleaningOptions::default());
+
+        assert_eq!(report.duplicate_vertices, 2);
+        assert_eq!(report.conflicting_vertices, vec![2]);
+        assert_eq!(report.vertices_without_coordinates, vec![3]);
+        assert_eq!(report.unknown_vertex_edges, vec![(1, 3), (2, 4)]);
+        assert_eq!(report.self_loops, vec![1]);
+        assert_eq!(report.non_positive_lengths, vec![(2, 1)]);
+        assert!(!report.is_clean());
+        assert_eq!(cleaned.vertices.len(), 2);
+        assert_eq!(cleaned.edges.len(), 2);
+    }
+
+    #[test]
+    fn test_clean_restricts_to_largest_component() {
+        let graph = Graph {
+            vertices: vec![
+                vertex(1, 1.0),
+                vertex(2, 2.0),
+                vertex(3, 3.0),
+                vertex(4, 4.0),
+            ],
+            edges: vec![
+                edge(1, 2, 10.0),
+                edge(2, 3, 10.0),
+                edge(3, 1, 10.0),
+                edge(3, 4, 10.0),
+            ],
+        };
+
+        let (cleaned, report) = clean(
+            graph,
+            CleaningOptions {
+                largest_component: true,
+            },
+        );
+
+        assert!(report.is_clean());
+        assert_eq!(report.components, 2);
+        assert_eq!(report.largest_component, 3);
+        assert_eq!(report.removed_outside_component, 1);
+        assert_eq!(cleaned.edges.len(), 3);
+        assert!(cleaned.vertices.iter().all(|v| v.osm_id != 4));
+    }
+}
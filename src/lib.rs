@@ -0,0 +1,48 @@
+//! A microscopic traffic simulation on OpenStreetMap road networks.
+//!
+//! Vehicles follow the intelligent driver model on the edges of an [`OSMGraph`]. A
+//! [`Simulation`] runs them on a single process; the `traffic-sim` binary additionally
+//! distributes the partitions of the graph ([`GPartition`]) over MPI ranks.
+//!
+//! ```no_run
+//! use traffic_sim::{
+//!     graph::{input::parse_input, validation::CleaningOptions},
+//!     Simulation,
+//! };
+//!
+//! let graph = parse_input("assets/graph.json".as_ref(), CleaningOptions::default())?;
+//! let report = Simulation::builder(&graph)
+//!     .with_vehicles(100)
+//!     .with_seed(Some(3))
+//!     .build()?
+//!     .run()?;
+//! println!("Simulated 100 vehicles for {} steps", report.time);
+//! # Ok::<(), traffic_sim::Error>(())
+//! ```
+
+#[doc(hidden)]
+pub mod cli;
+pub mod config;
+pub mod error;
+#[cfg(test)]
+mod fixtures;
+pub mod graph;
+pub mod metrics;
+pub mod models;
+pub mod prelude;
+pub mod simulation;
+pub mod utils;
+mod vmpi;
+#[doc(hidden)]
+pub mod world;
+
+pub use crate::{
+    graph::osm_graph::{GPartition, NodeId, OSMGraph, Osmid},
+    models::{
+        observer::Observer,
+        vehicle::{Moveable, Vehicle},
+        vehicle_builder::VehicleBuilder,
+    },
+    prelude::{Error, Result},
+    simulation::{Report, Simulation, SimulationBuilder},
+};
@@ -1,24 +1,15 @@
-#[allow(clippy::all)]
-extern crate mpi;
-extern crate rand;
-
-use cli::Cli;
-
-use crate::prelude::*;
-use clap::Parser;
-
-mod cli;
-mod error;
-mod graph;
-mod models;
-mod prelude;
-mod utils;
-mod vmpi;
-mod world;
+use traffic_sim::{config, prelude::*, world};
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
-    world::run(cli).await.unwrap();
-    Ok(())
+async fn main() {
+    if let Err(err) = run().await {
+        eprintln!("traffic-sim: {}", err);
+        std::process::exit(1);
+    }
+}
+
+// Parses the command line and runs the chosen command
+async fn run() -> Result<()> {
+    let cli = config::load_cli()?;
+    world::run(cli).await
 }
@@ -0,0 +1,285 @@
+//! Metrics of a whole run, written to the output directory at its end.
+//! The JSON file is meant for scripts, the table for people.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{car_following::TIME_STEP, vehicle_class::ClassStatistics},
+    prelude::*,
+};
+
+// Name of the file the metrics are written to as JSON
+pub const METRICS_FILE: &str = "metrics.json";
+
+// Name of the file the metrics are written to as a table
+pub const METRICS_TABLE_FILE: &str = "metrics.txt";
+
+/// Results of the vehicles of a run, not counting the buses of transit lines.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VehicleMetrics {
+    /// Number of generated vehicles.
+    pub spawned: usize,
+    /// Number of vehicles that reached their destination.
+    pub finished: usize,
+    /// Number of vehicles that no rank could take over.
+    pub lost: usize,
+    /// Time driven by all vehicles in seconds.
+    pub travel_time: f64,
+    /// Time driven per vehicle in seconds.
+    pub mean_travel_time: f64,
+    /// Distance driven by all vehicles in meters.
+    pub distance: f64,
+    /// Distance driven per vehicle in meters.
+    pub mean_distance: f64,
+    /// Mean speed of all vehicles while on the road in m/s.
+    pub mean_speed: f64,
+}
+
+impl VehicleMetrics {
+    /// Sums up the statistics of all classes. The means are taken over the vehicles
+    /// that finished or used up their steps.
+    pub fn new(
+        spawned: usize,
+        lost: usize,
+        classes: &HashMap<String, ClassStatistics>,
+    ) -> VehicleMetrics {
+        let vehicles: usize = classes.values().map(|c| c.vehicles).sum();
+        let travel_time = classes.values().map(|c| c.steps).sum::<u64>() as f64 * TIME_STEP;
+        let distance: f64 = classes.values().map(|c| c.distance).sum();
+        let mean = |total: f64| match vehicles {
+            0 => 0.0,
+            n => total / n as f64,
+        };
+        VehicleMetrics {
+            spawned,
+            finished: classes.values().map(|c| c.finished).sum(),
+            lost,
+            travel_time,
+            mean_travel_time: mean(travel_time),
+            distance,
+            mean_distance: mean(distance),
+            mean_speed: if travel_time > 0.0 {
+                distance / travel_time
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Messages a rank exchanged with the other ranks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageMetrics {
+    /// Number of messages sent per kind. All kinds share one MPI tag.
+    pub sent: BTreeMap<String, u64>,
+    /// Number of bytes sent, including the handed over vehicles.
+    pub bytes_sent: u64,
+    /// Number of bytes received, including the handed over vehicles.
+    pub bytes_received: u64,
+    /// Time spent waiting for messages in seconds.
+    pub waiting: f64,
+}
+
+impl MessageMetrics {
+    /// Counts a sent message of the given kind and size.
+    pub fn record_sent(&mut self, kind: &str, bytes: usize) {
+        *self.sent.entry(String::from(kind)).or_default() += 1;
+        self.bytes_sent += bytes as u64;
+    }
+
+    /// Counts a received message of the given size and the time spent waiting for it.
+    pub fn record_received(&mut self, bytes: usize, waiting: Duration) {
+        self.bytes_received += bytes as u64;
+        self.waiting += waiting.as_secs_f64();
+    }
+}
+
+/// Metrics of one rank.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RankMetrics {
+    /// The rank, 0 for the root and for runs without MPI.
+    pub rank: i32,
+    /// Number of nodes of the graph or partition the rank simulates.
+    pub nodes: usize,
+    /// Number of edges of the graph or partition the rank simulates.
+    pub edges: usize,
+    /// Time spent working in seconds.
+    pub busy_time: f64,
+    /// Time spent waiting for the other ranks in seconds.
+    pub idle_time: f64,
+    /// Messages exchanged with the other ranks.
+    pub messages: MessageMetrics,
+}
+
+impl RankMetrics {
+    /// Splits the wall time of a rank into the time spent waiting for messages
+    /// and the time spent working.
+    pub fn new(
+        rank: i32,
+        nodes: usize,
+        edges: usize,
+        wall_time: Duration,
+        messages: MessageMetrics,
+    ) -> RankMetrics {
+        let idle_time = messages.waiting.min(wall_time.as_secs_f64());
+        RankMetrics {
+            rank,
+            nodes,
+            edges,
+            busy_time: wall_time.as_secs_f64() - idle_time,
+            idle_time,
+            messages,
+        }
+    }
+}
+
+/// Metrics of a whole run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunMetrics {
+    /// Time spent loading the graph in seconds.
+    pub graph_load_time: f64,
+    /// Time spent simulating in seconds.
+    pub wall_time: f64,
+    /// Number of steps the simulation took.
+    pub steps: u64,
+    /// Results of the vehicles.
+    pub vehicles: VehicleMetrics,
+    /// Metrics of every rank, ordered by rank.
+    pub ranks: Vec<RankMetrics>,
+}
+
+impl RunMetrics {
+    /// Returns the metrics as a table.
+    pub fn to_table(&self) -> String {
+        let v = &self.vehicles;
+        let mut table = String::new();
+        table.push_str(&format!(
+            "Graph load time   {:>12.3} s\n",
+            self.graph_load_time
+        ));
+        table.push_str(&format!("Wall time         {:>12.3} s\n", self.wall_time));
+        table.push_str(&format!("Steps             {:>12}\n", self.steps));
+        table.push_str(&format!("Vehicles spawned  {:>12}\n", v.spawned));
+        table.push_str(&format!("Vehicles finished {:>12}\n", v.finished));
+        table.push_str(&format!("Vehicles lost     {:>12}\n", v.lost));
+        table.push_str(&format!(
+            "Travel time       {:>12.1} s, {:.1} s per vehicle\n",
+            v.travel_time, v.mean_travel_time
+        ));
+        table.push_str(&format!(
+            "Distance          {:>12.1} m, {:.1} m per vehicle\n",
+            v.distance, v.mean_distance
+        ));
+        table.push_str(&format!("Mean speed        {:>12.2} m/s\n", v.mean_speed));
+        table.push('\n');
+        table.push_str(&format!(
+            "{:>4} {:>8} {:>8} {:>10} {:>10} {:>9} {:>12} {:>12}\n",
+            "rank", "nodes", "edges", "busy [s]", "idle [s]", "messages", "sent [B]", "recv [B]"
+        ));
+        for r in &self.ranks {
+            table.push_str(&format!(
+                "{:>4} {:>8} {:>8} {:>10.3} {:>10.3} {:>9} {:>12} {:>12}\n",
+                r.rank,
+                r.nodes,
+                r.edges,
+                r.busy_time,
+                r.idle_time,
+                r.messages.sent.values().sum::<u64>(),
+                r.messages.bytes_sent,
+                r.messages.bytes_received
+            ));
+        }
+        table
+    }
+
+    /// Writes the metrics as JSON and as a table into the given directory.
+    /// Returns the path of the JSON file.
+    pub fn write_to(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(METRICS_FILE);
+        let writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        std::fs::write(dir.join(METRICS_TABLE_FILE), self.to_table())?;
+        Ok(path)
+    }
+}
+
+// Unit tests for the run metrics
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::temp_path;
+
+    #[test]
+    fn test_vehicle_metrics_sum_up_the_classes() {
+        let mut classes = HashMap::new();
+        classes.insert(
+            String::from("car"),
+            ClassStatistics {
+                vehicles: 3,
+                finished: 2,
+                steps: 300,
+                distance: 2400.0,
+                ..ClassStatistics::default()
+            },
+        );
+        classes.insert(
+            String::from("bicycle"),
+            ClassStatistics {
+                vehicles: 1,
+                finished: 1,
+                steps: 100,
+                distance: 400.0,
+                ..ClassStatistics::default()
+            },
+        );
+
+        let metrics = VehicleMetrics::new(5, 1, &classes);
+        assert_eq!((metrics.spawned, metrics.finished, metrics.lost), (5, 3, 1));
+        assert_eq!(metrics.travel_time, 400.0);
+        assert_eq!(metrics.mean_travel_time, 100.0);
+        assert_eq!(metrics.mean_distance, 700.0);
+        assert_eq!(metrics.mean_speed, 7.0);
+        assert_eq!(VehicleMetrics::new(0, 0, &HashMap::new()).mean_speed, 0.0);
+    }
+
+    #[test]
+    fn test_metrics_are_written_as_json_and_table() {
+        let dir = temp_path("metrics");
+        let mut messages = MessageMetrics::default();
+        messages.record_sent("TickUpdate", 100);
+        messages.record_sent("TickUpdate", 50);
+        messages.record_received(80, Duration::from_millis(500));
+        let metrics = RunMetrics {
+            graph_load_time: 0.25,
+            wall_time: 2.0,
+            steps: 42,
+            vehicles: VehicleMetrics::default(),
+            ranks: vec![RankMetrics::new(
+                1,
+                10,
+                12,
+                Duration::from_secs(2),
+                messages,
+            )],
+        };
+        assert_eq!(metrics.ranks[0].idle_time, 0.5);
+        assert_eq!(metrics.ranks[0].busy_time, 1.5);
+        assert_eq!(metrics.ranks[0].messages.sent["TickUpdate"], 2);
+        assert_eq!(metrics.ranks[0].messages.bytes_sent, 150);
+
+        let path = metrics.write_to(&dir).unwrap();
+        let read: RunMetrics = serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap();
+        assert_eq!(read, metrics);
+        let table = std::fs::read_to_string(dir.join(METRICS_TABLE_FILE)).unwrap();
+        assert!(table.contains("Steps"));
+        assert!(table.lines().last().unwrap().trim_start().starts_with('1'));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
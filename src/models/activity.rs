@@ -0,0 +1,190 @@
+use std::{collections::HashMap, path::Path};
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use rand_distr::Normal;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::read_structured, graph::osm_graph::NodeId, prelude::*};
+
+// Name of the activity taking place at the origin of a trip chain
+pub const HOME: &str = "home";
+
+/// A stop of a trip chain.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    /// The node the activity takes place at.
+    pub node: NodeId,
+    /// Seconds the vehicle stays there before it leaves for the next activity.
+    pub dwell: f64,
+}
+
+/// A kind of activity, as listed in a trip-chain file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActivityType {
+    /// The name of the activity, used in the chains.
+    pub name: String,
+    /// Mean time spent at the activity in seconds.
+    pub dwell: f64,
+    /// Standard deviation of the time spent at the activity in seconds.
+    #[serde(default)]
+    pub dwell_std_dev: f64,
+}
+
+/// A sequence of activities, as listed in a trip-chain file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainPattern {
+    /// The names of the activities after leaving home, in order.
+    pub activities: Vec<String>,
+    /// The relative share of vehicles following this chain.
+    #[serde(default = "default_share")]
+    pub share: f64,
+}
+
+// Chains without a share are as likely as a chain with share 1
+fn default_share() -> f64 {
+    1.0
+}
+
+// Contents of a trip-chain file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TripChainFile {
+    #[serde(default)]
+    activity: Vec<ActivityType>,
+    #[serde(default)]
+    chain: Vec<ChainPattern>,
+}
+
+/// The activity chains generated vehicles follow, such as home → work → shop → home.
+#[derive(Debug, Clone)]
+pub struct TripChains {
+    /// The kinds of activities by name.
+    activities: HashMap<String, ActivityType>,
+    /// The chains to choose from.
+    chains: Vec<ChainPattern>,
+    /// The distribution the chain of a vehicle is drawn from.
+    mix: WeightedIndex<f64>,
+}
+
+impl TripChains {
+    /// Reads the activities and chains from a TOML, YAML or JSON file.
+    pub fn load(path: &Path) -> Result<TripChains> {
+        let parsed: TripChainFile = read_structured(path)?;
+        TripChains::new(parsed.activity, parsed.chain)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Creates trip chains from the given activities and chains.
+    /// The activity `home` is always known and takes place at the origin of the chain.
+    pub fn new(activities: Vec<ActivityType>, chains: Vec<ChainPattern>) -> Result<TripChains> {
+        let mut known: HashMap<String, ActivityType> = HashMap::from([(
+            String::from(HOME),
+            ActivityType {
+                name: String::from(HOME),
+                dwell: 0.0,
+                dwell_std_dev: 0.0,
+            },
+        )]);
+        for activity in activities {
+            let valid = activity.dwell >= 0.0 && activity.dwell_std_dev >= 0.0;
+            if !valid {
+                return Err(Error::Config(format!(
+                    "Dwell time of activity {} must not be negative",
+                    activity.name
+                )));
+            }
+            known.insert(activity.name.clone(), activity);
+        }
+        for chain in &chains {
+            if chain.activities.is_empty() {
+                return Err(Error::Config(String::from(
+                    "Chains need at least one activity",
+                )));
+            }
+            if let Some(unknown) = chain.activities.iter().find(|a| !known.contains_key(*a)) {
+                return Err(Error::Config(format!("Unknown activity {}", unknown)));
+            }
+        }
+        let mix = WeightedIndex::new(chains.iter().map(|c| c.share))
+            .map_err(|err| Error::Config(format!("Invalid chain shares: {}", err)))?;
+        Ok(TripChains {
+            activities: known,
+            chains,
+            mix,
+        })
+    }
+
+    /// Draws the activities of a vehicle living at `home`. Activities other than `home`
+    /// take place at nodes drawn with `draw_node`; the first one is the given destination.
+    pub fn sample<R: Rng>(
+        &self,
+        rng: &mut R,
+        home: NodeId,
+        destination: NodeId,
+        mut draw_node: impl FnMut(&mut R) -> NodeId,
+    ) -> Vec<Activity> {
+        let chain = &self.chains[self.mix.sample(rng)];
+        chain
+            .activities
+            .iter()
+            .enumerate()
+            .map(|(k, name)| {
+                let node = match name.as_str() {
+                    _ if k == 0 => destination,
+                    HOME => home,
+                    _ => draw_node(rng),
+                };
+                let kind = &self.activities[name];
+                let dwell = match Normal::new(kind.dwell, kind.dwell_std_dev) {
+                    Ok(normal) if kind.dwell_std_dev > 0.0 => normal.sample(rng).max(0.0),
+                    _ => kind.dwell,
+                };
+                Activity { node, dwell }
+            })
+            .collect()
+    }
+}
+
+// Unit tests for trip chains
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rng_from_seed;
+
+    #[test]
+    fn test_sample_chain() {
+        let file: TripChainFile = toml::from_str(
+            r#"
+            [[activity]]
+            name = "work"
+            dwell = 28800.0
+
+            [[activity]]
+            name = "shop"
+            dwell = 1800.0
+            dwell_std_dev = 600.0
+
+            [[chain]]
+            activities = ["work", "shop", "home"]
+            "#,
+        )
+        .unwrap();
+        let chains = TripChains::new(file.activity, file.chain).unwrap();
+        let mut rng = rng_from_seed(Some(1));
+
+        let activities = chains.sample(&mut rng, NodeId(1), NodeId(2), |_| NodeId(3));
+        let nodes: Vec<_> = activities.iter().map(|a| a.node).collect();
+        assert_eq!(nodes, [2, 3, 1].map(NodeId));
+        assert_eq!(activities[0].dwell, 28800.0);
+        assert!(activities[1].dwell >= 0.0);
+        assert_eq!(activities[2].dwell, 0.0);
+
+        let unknown = ChainPattern {
+            activities: vec![String::from("gym")],
+            share: 1.0,
+        };
+        assert!(TripChains::new(Vec::new(), vec![unknown]).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{
+    cli::AssignmentMethod,
+    graph::osm_graph::{NodeId, OSMGraph},
+    models::{car_following::TIME_STEP, incident::Conditions, rerouting::Router, vehicle::Vehicle},
+    utils::rng_from_seed,
+};
+
+type EdgeKey = (NodeId, NodeId);
+
+/// Settings of the iterative traffic assignment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AssignmentOptions {
+    /// How drivers choose their routes between two runs.
+    pub method: AssignmentMethod,
+    /// Largest number of runs.
+    pub max_iterations: usize,
+    /// Relative gap at or below which the assignment has converged.
+    pub convergence_gap: f64,
+    /// Sensitivity of the logit route choice to travel time differences in 1/s.
+    pub logit_scale: f64,
+}
+
+/// Time the vehicles spent on every edge during a run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkTimes {
+    /// Steps spent on the edge by all vehicles, and the number of vehicles that left it.
+    records: HashMap<EdgeKey, (u64, u64)>,
+}
+
+impl LinkTimes {
+    /// Records the vehicles on an edge during one step.
+    pub fn record_occupancy(&mut self, edge: EdgeKey, vehicles: usize) {
+        self.records.entry(edge).or_default().0 += vehicles as u64;
+    }
+
+    /// Records a vehicle leaving an edge.
+    pub fn record_exit(&mut self, edge: EdgeKey) {
+        self.records.entry(edge).or_default().1 += 1;
+    }
+
+    /// Returns the mean travel time of an edge in seconds, if any vehicle left it.
+    pub fn travel_time(&self, edge: EdgeKey) -> Option<f64> {
+        self.records
+            .get(&edge)
+            .filter(|r| r.1 > 0)
+            .map(|r| r.0 as f64 * TIME_STEP / r.1 as f64)
+    }
+
+    /// Returns the mean speed in m/s on every edge a vehicle left.
+    pub fn speeds(&self, graph: &OSMGraph) -> HashMap<EdgeKey, f64> {
+        self.records
+            .keys()
+            .filter_map(|edge| {
+                let time = self.travel_time(*edge)?;
+                let length = graph.edge_length(edge.0, edge.1)?;
+                Some((*edge, length / time.max(TIME_STEP)))
+            })
+            .collect()
+    }
+}
+
+/// Repeats the route choice of all drivers from the travel times they experienced,
+/// until no driver could save much time on another route (dynamic user equilibrium).
+#[derive(Debug)]
+pub struct Assignment<'a> {
+    /// Finds the fastest routes at the experienced travel times.
+    router: Router<'a>,
+    /// Settings of the assignment.
+    options: AssignmentOptions,
+    /// The routes every driver has chosen from so far, used by the logit model.
+    choices: Vec<Vec<Vec<NodeId>>>,
+    /// The speeds per edge averaged over all runs, used by the logit model.
+    speeds: HashMap<EdgeKey, f64>,
+    /// The number of finished runs.
+    iteration: usize,
+    /// Draws the route choices.
+    rng: StdRng,
+}
+
+impl<'a> Assignment<'a> {
+    /// Creates an assignment routing on the roads of the router's vehicle classes.
+    pub fn new(router: Router<'a>, options: AssignmentOptions) -> Assignment<'a> {
+        Assignment {
+            router,
+            options,
+            choices: Vec::new(),
+            speeds: HashMap::new(),
+            iteration: 0,
+            rng: rng_from_seed(None),
+        }
+    }
+
+    /// Sets the seed of the route choices.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Assignment<'a> {
+        self.rng = rng_from_seed(seed);
+        self
+    }
+
+    /// Takes the travel times of a run of the given vehicles and returns the relative gap:
+    /// the share of the total travel time the drivers would save on their fastest routes.
+    /// Unless the gap is small enough, the drivers choose new routes for the next run.
+    pub fn iterate(&mut self, demand: &mut [Vehicle], times: &LinkTimes) -> f64 {
+        self.iteration += 1;
+        let speeds = times.speeds(self.router.graph());
+        if self.choices.is_empty() {
+            self.choices = demand.iter().map(|v| vec![v.path_ids.clone()]).collect();
+        }
+        for (edge, speed) in &speeds {
+            let average = self.speeds.entry(*edge).or_insert(*speed);
+            *average += (speed - *average) / self.iteration as f64;
+        }
+
+        let mut current = 0.0;
+        let mut best = 0.0;
+        let mut fastest = Vec::with_capacity(demand.len());
+        for v in demand.iter() {
+            let cost = self.cost(&v.path_ids, v.speed, &speeds);
+            let route = self
+                .router
+                .route(
+                    &v.class,
+                    v.path_ids[0],
+                    v.path_ids[v.path_ids.len() - 1],
+                    v.speed,
+                    &Conditions::default(),
+                    &speeds,
+                )
+                .filter(|route| route.len() > 1);
+            let best_cost = route
+                .as_ref()
+                .map_or(cost, |r| self.cost(r, v.speed, &speeds).min(cost));
+            current += cost;
+            best += best_cost;
+            fastest.push(route);
+        }
+        let gap = if current > 0.0 {
+            (current - best) / current
+        } else {
+            0.0
+        };
+        if gap <= self.options.convergence_gap {
+            return gap;
+        }
+
+        // a share of the drivers switches to the fastest route, shrinking with every run
+        let switch = 1.0 / (self.iteration + 1) as f64;
+        for (i, route) in fastest.into_iter().enumerate() {
+            let route = match route {
+                Some(route) => route,
+                None => continue,
+            };
+            if !self.choices[i].contains(&route) {
+                self.choices[i].push(route.clone());
+            }
+            let chosen = match self.options.method {
+                AssignmentMethod::Msa => self.rng.gen_bool(switch).then_some(route),
+                AssignmentMethod::Logit => Some(self.logit_choice(i, demand[i].speed)),
+            };
+            if let Some(chosen) = chosen {
+                demand[i].start_route(chosen);
+            }
+        }
+        gap
+    }
+
+    // Travel time of a route in seconds
+    fn cost(&self, route: &[NodeId], desired_speed: f64, speeds: &HashMap<EdgeKey, f64>) -> f64 {
+        route
+            .windows(2)
+            .map(|w| {
+                self.router
+                    .travel_time((w[0], w[1]), desired_speed, &Conditions::default(), speeds)
+            })
+            .sum()
+    }
+
+    // Draws one of the known routes of a driver, favouring routes that were fast on average
+    fn logit_choice(&mut self, i: usize, desired_speed: f64) -> Vec<NodeId> {
+        let costs: Vec<f64> = self.choices[i]
+            .iter()
+            .map(|r| self.cost(r, desired_speed, &self.speeds))
+            .collect();
+        let fastest = costs.iter().copied().fold(f64::INFINITY, f64::min);
+        let weights: Vec<f64> = costs
+            .iter()
+            .map(|c| (-self.options.logit_scale * (c - fastest)).exp())
+            .collect();
+        let mut draw = self.rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for (k, weight) in weights.iter().enumerate() {
+            if draw < *weight {
+                return self.choices[i][k].clone();
+            }
+            draw -= weight;
+        }
+        self.choices[i][weights.len() - 1].clone()
+    }
+}
+
+// Unit tests for the traffic assignment
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::edge,
+        models::{
+            graph_input::Graph, rerouting::ReroutingOptions, vehicle_builder::VehicleBuilder,
+            vehicle_class::VehicleClass,
+        },
+    };
+
+    #[test]
+    fn test_link_times() {
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges: vec![edge(1, 2, 100.0)],
+        })
+        .unwrap();
+        let [n1, n2] = [1, 2].map(|osm_id| graph.node(osm_id).unwrap());
+        let mut times = LinkTimes::default();
+        for _ in 0..10 {
+            times.record_occupancy((n1, n2), 2);
+        }
+        assert_eq!(times.travel_time((n1, n2)), None);
+        times.record_exit((n1, n2));
+        times.record_exit((n1, n2));
+
+        assert_eq!(times.travel_time((n1, n2)), Some(10.0));
+        assert_eq!(times.speeds(&graph), HashMap::from([((n1, n2), 10.0)]));
+    }
+
+    #[test]
+    fn test_drivers_leave_slow_route() {
+        // two equally long routes from 1 to 4, over 2 and over 3
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges: vec![
+                edge(1, 2, 100.0),
+                edge(2, 4, 100.0),
+                edge(1, 3, 100.0),
+                edge(3, 4, 100.0),
+            ],
+        })
+        .unwrap();
+        let [n1, n2, n3, n4] = [1, 2, 3, 4].map(|osm_id| graph.node(osm_id).unwrap());
+        let car = VehicleClass::car();
+        let rerouting = ReroutingOptions {
+            interval: 60,
+            congestion_threshold: 2.0,
+        };
+        let vehicle = || {
+            VehicleBuilder::new()
+                .with_speed(10.0)
+                .with_prev_id(n1)
+                .with_next_id(n2)
+                .with_path_ids(vec![n1, n2, n4])
+                .build()
+                .unwrap()
+        };
+        // everybody took the route over 2, which was jammed
+        let mut times = LinkTimes::default();
+        for edge in [(n1, n2), (n2, n4)] {
+            times.record_occupancy(edge, 400);
+            for _ in 0..10 {
+                times.record_exit(edge);
+            }
+        }
+
+        for method in [AssignmentMethod::Msa, AssignmentMethod::Logit] {
+            let options = AssignmentOptions {
+                method,
+                max_iterations: 10,
+                convergence_gap: 0.01,
+                logit_scale: 0.1,
+            };
+            let mut assignment =
+                Assignment::new(Router::new(&graph, [&car], rerouting), options).with_seed(Some(1));
+            let mut demand: Vec<Vehicle> = (0..10).map(|_| vehicle()).collect();
+
+            let gap = assignment.iterate(&mut demand, &times);
+            assert!((gap - 0.75).abs() < 1e-9);
+            let switched = demand.iter().filter(|v| v.path_ids == [n1, n3, n4]).count();
+            match method {
+                // about half of the drivers switch after the first run
+                AssignmentMethod::Msa => assert!(switched > 0 && switched < 10),
+                // the fast route is far more likely
+                AssignmentMethod::Logit => assert!(switched >= 9),
+            }
+            assert!(demand.iter().all(|v| v.next_id == v.path_ids[1]));
+        }
+    }
+}
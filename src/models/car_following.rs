@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+// Duration of one simulation step in seconds
+pub const TIME_STEP: f64 = 1.0;
+
+// Smallest gap used in the interaction term, avoiding a division by zero
+const MIN_GAP: f64 = 0.01;
+
+/// Parameters of the Intelligent Driver Model (IDM).
+/// The maximum acceleration and the desired speed are taken from the vehicle.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IdmParameters {
+    /// Desired time headway to the leader in seconds.
+    pub time_headway: f64,
+    /// Minimum bumper-to-bumper distance to the leader in meters.
+    pub min_gap: f64,
+    /// Comfortable deceleration in m/s².
+    pub comfortable_deceleration: f64,
+    /// Exponent of the free-road acceleration term.
+    pub exponent: f64,
+}
+
+impl Default for IdmParameters {
+    fn default() -> Self {
+        IdmParameters {
+            time_headway: 1.5,
+            min_gap: 2.0,
+            comfortable_deceleration: 2.0,
+            exponent: 4.0,
+        }
+    }
+}
+
+/// Restrictions of the edge a vehicle drives on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoadLimits {
+    /// Share of its desired speed a vehicle may drive at.
+    pub speed_factor: f64,
+    /// Share of the usual capacity, vehicles keep a longer time headway on reduced capacity.
+    pub capacity_factor: f64,
+}
+
+impl Default for RoadLimits {
+    fn default() -> Self {
+        RoadLimits {
+            speed_factor: 1.0,
+            capacity_factor: 1.0,
+        }
+    }
+}
+
+/// The vehicle ahead, as seen by its follower.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Leader {
+    /// Distance between the rear of the leader and the front of the follower in meters.
+    pub gap: f64,
+    /// Speed of the leader in m/s.
+    pub speed: f64,
+}
+
+// Computes the IDM acceleration of a vehicle driving at `speed` towards `desired_speed`,
+// with `max_acceleration` and an optional leader.
+pub fn idm_acceleration(
+    params: &IdmParameters,
+    speed: f64,
+    desired_speed: f64,
+    max_acceleration: f64,
+    leader: Option<Leader>,
+) -> f64 {
+    let free_road = 1.0 - (speed / desired_speed).powf(params.exponent);
+    let interaction = match leader {
+        Some(leader) => {
+            let approach = speed * (speed - leader.speed)
+                / (2.0 * (max_acceleration * params.comfortable_deceleration).sqrt());
+            let desired_gap = params.min_gap + (speed * params.time_headway + approach).max(0.0);
+            (desired_gap / leader.gap.max(MIN_GAP)).powi(2)
+        }
+        None => 0.0,
+    };
+    max_acceleration * (free_road - interaction)
+}
+
+// Advances a speed by one time step with the given acceleration.
+// Returns the new speed and the travelled distance, never driving backwards.
+pub fn integrate(speed: f64, acceleration: f64, dt: f64) -> (f64, f64) {
+    let new_speed = speed + acceleration * dt;
+    if new_speed < 0.0 {
+        // the vehicle stops within the step
+        (0.0, -speed * speed / (2.0 * acceleration))
+    } else {
+        (new_speed, (speed + new_speed) / 2.0 * dt)
+    }
+}
+
+// Unit tests for the car-following model
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_road_approaches_desired_speed() {
+        let params = IdmParameters::default();
+        let mut speed = 0.0;
+        for _ in 0..120 {
+            let acceleration = idm_acceleration(&params, speed, 13.0, 2.6, None);
+            speed = integrate(speed, acceleration, TIME_STEP).0;
+        }
+        assert!((speed - 13.0).abs() < 0.5);
+        assert!(idm_acceleration(&params, 13.0, 13.0, 2.6, None).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_follower_stops_behind_standing_leader() {
+        let params = IdmParameters::default();
+        // follower at 0m driving 13 m/s, a standing vehicle 100m ahead
+        let (mut position, mut speed) = (0.0, 13.0);
+        for _ in 0..200 {
+            let leader = Leader {
+                gap: 100.0 - position,
+                speed: 0.0,
+            };
+            let acceleration = idm_acceleration(&params, speed, 13.0, 2.6, Some(leader));
+            let (new_speed, distance) = integrate(speed, acceleration, TIME_STEP);
+            speed = new_speed;
+            position += distance.min(leader.gap);
+        }
+        assert!(speed < 0.1);
+        assert!(position < 100.0 && position > 100.0 - 2.0 * params.min_gap);
+    }
+
+    #[test]
+    fn test_integrate_never_reverses() {
+        let (speed, distance) = integrate(2.0, -9.0, TIME_STEP);
+        assert_eq!(speed, 0.0);
+        assert!((distance - 4.0 / 18.0).abs() < 1e-9);
+    }
+}
@@ -39,17 +39,94 @@ pub struct Edge {
     pub name: String,
     /// The OpenStreetMap (OSM) ID of the edge.
     pub osm_id: String,
+    /// The OpenStreetMap (OSM) `highway` type of the edge, if known.
+    #[serde(default)]
+    pub highway: Option<String>,
+    /// The OpenStreetMap (OSM) `lanes` tag of the edge, if known.
+    #[serde(default)]
+    pub lanes: Option<String>,
+    /// The OpenStreetMap (OSM) `lanes:forward` tag of the edge, if known.
+    #[serde(default, rename = "lanes:forward")]
+    pub lanes_forward: Option<String>,
+    /// The OpenStreetMap (OSM) `lanes:backward` tag of the edge, if known.
+    #[serde(default, rename = "lanes:backward")]
+    pub lanes_backward: Option<String>,
+    /// Whether the edge runs against the direction of its OSM way, so `lanes:backward` applies.
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+impl Edge {
+    /// Parses the number of lanes in the direction of the edge, which is at least one.
+    /// `lanes:forward` or `lanes:backward` are used if present. Otherwise the `lanes` of the way
+    /// are shared with the reverse edge if it exists, with the odd lane counted for both.
+    /// For several values the lowest one is used, edges without a valid value have one lane.
+    pub fn lane_count(&self, has_reverse: bool) -> usize {
+        let directed = if self.reversed {
+            &self.lanes_backward
+        } else {
+            &self.lanes_forward
+        };
+        match (parse_lanes(directed), parse_lanes(&self.lanes)) {
+            (Some(lanes), _) => lanes,
+            (None, Some(lanes)) if has_reverse => lanes.div_ceil(2),
+            (None, Some(lanes)) => lanes,
+            (None, None) => 1,
+        }
+    }
+
+    /// Parses the maximum speed into m/s.
+    /// Values are read as km/h unless given in mph; for several values the lowest one is used.
+    pub fn max_speed_mps(&self) -> Option<f64> {
+        let factor = if self.max_speed.contains("mph") {
+            1.609344 / 3.6
+        } else {
+            1.0 / 3.6
+        };
+        self.max_speed
+            .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .filter_map(|v| v.parse::<f64>().ok())
+            .filter(|v| *v > 0.0)
+            .reduce(f64::min)
+            .map(|v| v * factor)
+    }
+}
+
+/// Parses the lowest positive number of a lane tag.
+fn parse_lanes(tag: &Option<String>) -> Option<usize> {
+    tag.iter()
+        .flat_map(|lanes| lanes.split(|c: char| !c.is_ascii_digit()))
+        .filter_map(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .min()
 }
 
 /// Represents a vertex in the graph.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Vertex {
-    /// The X-coordinate of the vertex.
+    /// The X-coordinate of the vertex. Missing coordinates are read as `NaN`.
+    #[serde(default = "missing_coordinate")]
     pub x: f64,
-    /// The Y-coordinate of the vertex.
+    /// The Y-coordinate of the vertex. Missing coordinates are read as `NaN`.
+    #[serde(default = "missing_coordinate")]
     pub y: f64,
     /// The OpenStreetMap (OSM) ID of the vertex.
     pub osm_id: usize,
+    /// The OpenStreetMap (OSM) `highway` tag of the vertex, e.g. `traffic_signals`, if any.
+    #[serde(default)]
+    pub highway: Option<String>,
+}
+
+/// Placeholder for coordinates absent from the input.
+fn missing_coordinate() -> f64 {
+    f64::NAN
+}
+
+impl Vertex {
+    /// Returns whether the vertex has usable coordinates.
+    pub fn has_coordinates(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
 }
 
 impl std::cmp::PartialEq for Vertex {
@@ -0,0 +1,361 @@
+use mpi::traits::Equivalence;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph::osm_graph::NodeId,
+    models::{activity::Activity, transit::TransitTrip, vehicle::Vehicle},
+    prelude::*,
+};
+
+// Marks a missing optional value in a `WireVehicle`
+const NONE: u64 = u64::MAX;
+const NO_EXTRAS: u32 = u32::MAX;
+
+// Flags of a `WireVehicle`
+const PARKED: u8 = 1;
+const MARKED_FOR_DELETION: u8 = 1 << 1;
+const INFORMED: u8 = 1 << 2;
+
+/// Fixed-size part of a vehicle handed over between ranks, sent as an MPI datatype.
+/// The parts of varying size live in the tables of its `Handoff`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Equivalence)]
+pub struct WireVehicle {
+    /// The unique identifier of the vehicle.
+    pub id: u64,
+    /// The start node of the current edge.
+    pub prev_id: u32,
+    /// The end node of the current edge.
+    pub next_id: u32,
+    /// The length of the vehicle in meters.
+    pub length: f64,
+    /// The maximum acceleration of the vehicle in m/s².
+    pub acceleration: f64,
+    /// The desired speed of the vehicle in m/s.
+    pub speed: f64,
+    /// The current speed of the vehicle in m/s.
+    pub velocity: f64,
+    /// The distance of the vehicle's front from the start of its current edge in meters.
+    pub position: f64,
+    /// The number of steps the vehicle has taken.
+    pub steps: u64,
+    /// The distance the vehicle has driven in meters.
+    pub distance: f64,
+    /// The maximum number of steps of the vehicle, `u64::MAX` if unlimited.
+    pub max_steps: u64,
+    /// The step the current search for parking started in, `u64::MAX` if not searching.
+    pub search_start: u64,
+    /// The number of steps spent on earlier searches for parking.
+    pub search_steps: u64,
+    /// The lane of the vehicle on its current edge.
+    pub lane: u32,
+    /// The number of times the route was re-planned.
+    pub reroutes: u32,
+    /// The number of trips that ended at a parking space.
+    pub trips: u32,
+    /// The number of nodes of the remaining route in the route table.
+    pub route_len: u32,
+    /// The index of the activities and transit trip of the vehicle, `u32::MAX` if it has none.
+    pub extras: u32,
+    /// The index of the name of the vehicle's class.
+    pub class: u16,
+    /// Whether the vehicle is parked, marked for deletion and informed.
+    pub flags: u8,
+}
+
+// Parts of a vehicle most vehicles lack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Extras {
+    activities: Vec<Activity>,
+    transit: Option<TransitTrip>,
+}
+
+/// Vehicles handed over between ranks in a compact form.
+/// Every vehicle keeps the part of its route starting at its cursor only,
+/// so the restored vehicles start over with the cursor at the start of their routes.
+/// The fixed-size parts and the routes are sent as MPI datatypes next to the message
+/// carrying the handoff, which encodes the class names and extras only.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Handoff {
+    /// The fixed-size part of every vehicle.
+    #[serde(skip)]
+    pub vehicles: Vec<WireVehicle>,
+    /// The remaining routes of the vehicles, one after another.
+    #[serde(skip)]
+    pub routes: Vec<u32>,
+    /// The names of the classes of the vehicles.
+    classes: Vec<String>,
+    /// The activities and transit trips of the vehicles having any.
+    extras: Vec<Extras>,
+}
+
+impl Handoff {
+    /// Returns the number of vehicles.
+    pub fn len(&self) -> usize {
+        self.vehicles.len()
+    }
+
+    /// Returns whether there are no vehicles.
+    pub fn is_empty(&self) -> bool {
+        self.vehicles.is_empty()
+    }
+
+    /// Adds a vehicle, dropping the part of its route before its cursor.
+    pub fn push(&mut self, mut v: Vehicle) {
+        if let Some(trip) = &mut v.transit {
+            trip.cut_route(v.cursor);
+        }
+        let route = v.path_ids.get(v.cursor..).unwrap_or(&[]);
+        self.routes.extend(route.iter().map(|n| n.0));
+
+        let class = match self.classes.iter().position(|c| *c == v.class) {
+            Some(class) => class,
+            None => {
+                self.classes.push(v.class);
+                self.classes.len() - 1
+            }
+        };
+        let extras = if v.activities.is_empty() && v.transit.is_none() {
+            NO_EXTRAS
+        } else {
+            self.extras.push(Extras {
+                activities: v.activities,
+                transit: v.transit,
+            });
+            (self.extras.len() - 1) as u32
+        };
+        let mut flags = 0;
+        if v.is_parked {
+            flags |= PARKED;
+        }
+        if v.marked_for_deletion {
+            flags |= MARKED_FOR_DELETION;
+        }
+        if v.informed {
+            flags |= INFORMED;
+        }
+
+        self.vehicles.push(WireVehicle {
+            id: v.id,
+            prev_id: v.prev_id.0,
+            next_id: v.next_id.0,
+            length: v.length,
+            acceleration: v.acceleration,
+            speed: v.speed,
+            velocity: v.velocity,
+            position: v.position,
+            steps: v.steps,
+            distance: v.distance,
+            max_steps: v.max_steps.unwrap_or(NONE),
+            search_start: v.search_start.unwrap_or(NONE),
+            search_steps: v.search_steps,
+            lane: v.lane as u32,
+            reroutes: v.reroutes,
+            trips: v.trips,
+            route_len: route.len() as u32,
+            extras,
+            class: class as u16,
+            flags,
+        });
+    }
+
+    /// Restores the vehicles.
+    /// Fails if a vehicle refers to a route, class or extras missing from the tables.
+    pub fn into_vehicles(self) -> Result<Vec<Vehicle>> {
+        let mut extras: Vec<Option<Extras>> = self.extras.into_iter().map(Some).collect();
+        let mut routes = self.routes.into_iter();
+        let mut vehicles = Vec::with_capacity(self.vehicles.len());
+        for w in self.vehicles {
+            let path_ids: Vec<NodeId> = routes
+                .by_ref()
+                .take(w.route_len as usize)
+                .map(NodeId)
+                .collect();
+            if path_ids.len() != w.route_len as usize {
+                return Err(Error::Protocol(format!(
+                    "Route of vehicle {} is cut off",
+                    w.id
+                )));
+            }
+            let class = self
+                .classes
+                .get(w.class as usize)
+                .ok_or_else(|| Error::Protocol(format!("Vehicle {} has an unknown class", w.id)))?;
+            let Extras {
+                activities,
+                transit,
+            } = match w.extras {
+                NO_EXTRAS => Extras {
+                    activities: Vec::new(),
+                    transit: None,
+                },
+                i => extras
+                    .get_mut(i as usize)
+                    .and_then(Option::take)
+                    .ok_or_else(|| {
+                        Error::Protocol(format!("Vehicle {} has unknown extras", w.id))
+                    })?,
+            };
+
+            vehicles.push(Vehicle {
+                id: w.id,
+                class: class.clone(),
+                length: w.length,
+                acceleration: w.acceleration,
+                path_ids,
+                cursor: 0,
+                speed: w.speed,
+                velocity: w.velocity,
+                position: w.position,
+                lane: w.lane as usize,
+                next_id: NodeId(w.next_id),
+                prev_id: NodeId(w.prev_id),
+                is_parked: w.flags & PARKED != 0,
+                marked_for_deletion: w.flags & MARKED_FOR_DELETION != 0,
+                steps: w.steps,
+                distance: w.distance,
+                max_steps: (w.max_steps != NONE).then_some(w.max_steps),
+                informed: w.flags & INFORMED != 0,
+                reroutes: w.reroutes,
+                search_start: (w.search_start != NONE).then_some(w.search_start),
+                search_steps: w.search_steps,
+                activities,
+                trips: w.trips,
+                transit,
+            });
+        }
+        Ok(vehicles)
+    }
+}
+
+impl FromIterator<Vehicle> for Handoff {
+    fn from_iter<I: IntoIterator<Item = Vehicle>>(iter: I) -> Handoff {
+        let mut handoff = Handoff::default();
+        for v in iter {
+            handoff.push(v);
+        }
+        handoff
+    }
+}
+
+// Unit tests for the handoff format
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        transit::{TransitTrip, TripStop},
+        vehicle_builder::VehicleBuilder,
+    };
+
+    #[test]
+    fn test_vehicles_survive_handoff() {
+        let mut driving = VehicleBuilder::new()
+            .with_speed(10.0)
+            .with_prev_id(NodeId(3))
+            .with_next_id(NodeId(4))
+            .with_path_ids([1, 2, 3, 4, 5].map(NodeId).to_vec())
+            .with_max_steps(Some(100))
+            .build()
+            .unwrap();
+        driving.position = 12.5;
+        driving.steps = 7;
+        driving.distance = 112.5;
+        let planned = VehicleBuilder::new()
+            .with_speed(5.0)
+            .with_prev_id(NodeId(1))
+            .with_next_id(NodeId(2))
+            .with_path_ids([1, 2, 3].map(NodeId).to_vec())
+            .with_activities(vec![Activity {
+                node: NodeId(1),
+                dwell: 60.0,
+            }])
+            .build()
+            .unwrap();
+
+        let handoff: Handoff = vec![driving.clone(), planned.clone()].into_iter().collect();
+        assert_eq!(handoff.len(), 2);
+        assert_eq!(handoff.classes.len(), 1);
+        let restored = handoff.into_vehicles().unwrap();
+
+        // the driven part of the route is dropped
+        assert_eq!(restored[0].path_ids, [3, 4, 5].map(NodeId));
+        assert_eq!(restored[0].id, driving.id);
+        assert_eq!(restored[0].position, 12.5);
+        assert_eq!(restored[0].steps, 7);
+        assert_eq!(restored[0].distance, 112.5);
+        assert_eq!(restored[0].max_steps, Some(100));
+        assert_eq!(restored[0].search_start, None);
+        assert_eq!(restored[1].path_ids, planned.path_ids);
+        assert_eq!(restored[1].activities.len(), 1);
+    }
+
+    #[test]
+    fn test_route_is_cut_at_the_cursor() {
+        // the route passes the current edge twice, the vehicle drives it the second time
+        let mut v = VehicleBuilder::new()
+            .with_speed(10.0)
+            .with_prev_id(NodeId(2))
+            .with_next_id(NodeId(3))
+            .with_path_ids([1, 2, 3, 4, 2, 3, 5].map(NodeId).to_vec())
+            .build()
+            .unwrap();
+        assert_eq!(v.cursor, 1);
+        v.cursor = 4;
+
+        let restored = std::iter::once(v)
+            .collect::<Handoff>()
+            .into_vehicles()
+            .unwrap();
+        assert_eq!(restored[0].path_ids, [2, 3, 5].map(NodeId));
+        assert_eq!(restored[0].cursor, 0);
+        assert_eq!(restored[0].remaining_route(), &[NodeId(3), NodeId(5)]);
+    }
+
+    #[test]
+    fn test_bus_keeps_its_stops() {
+        let mut bus = VehicleBuilder::new()
+            .with_speed(10.0)
+            .with_prev_id(NodeId(1))
+            .with_next_id(NodeId(2))
+            .with_path_ids((1..=10).map(NodeId).collect())
+            .with_transit(TransitTrip {
+                line: String::from("1"),
+                departure: 0.0,
+                stops: vec![TripStop {
+                    node: NodeId(7),
+                    time: 60.0,
+                    index: 6,
+                }],
+                dwell: 20.0,
+                dwell_until: None,
+                arrivals: Vec::new(),
+            })
+            .build()
+            .unwrap();
+        bus.cursor = 3;
+        bus.prev_id = NodeId(4);
+        bus.next_id = NodeId(5);
+
+        let restored = std::iter::once(bus)
+            .collect::<Handoff>()
+            .into_vehicles()
+            .unwrap();
+        let v = &restored[0];
+        assert_eq!(v.path_ids, (4..=10).map(NodeId).collect::<Vec<_>>());
+        let stop = v.transit.as_ref().unwrap().stops[0];
+        assert_eq!(v.path_ids[stop.index], NodeId(7));
+    }
+
+    #[test]
+    fn test_cut_off_handoff_is_rejected() {
+        let v = VehicleBuilder::new()
+            .with_speed(10.0)
+            .with_prev_id(NodeId(1))
+            .with_next_id(NodeId(2))
+            .with_path_ids([1, 2, 3].map(NodeId).to_vec())
+            .build()
+            .unwrap();
+        let mut handoff: Handoff = std::iter::once(v).collect();
+        handoff.routes.pop();
+        assert!(matches!(handoff.into_vehicles(), Err(Error::Protocol(_))));
+    }
+}
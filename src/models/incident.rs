@@ -0,0 +1,307 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::read_structured,
+    graph::osm_graph::{NodeId, OSMGraph, Osmid},
+    models::car_following::RoadLimits,
+    prelude::*,
+};
+
+type EdgeKey = (NodeId, NodeId);
+
+/// A change of the road network.
+/// Event files and scenarios give the nodes by their OSM ids, `Effect<Osmid>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Effect<N = NodeId> {
+    /// No vehicle may enter the edge.
+    CloseEdge { from: N, to: N },
+    /// No vehicle may drive through the node.
+    CloseNode { node: N },
+    /// Vehicles drive at most `factor` times their desired speed on the edge.
+    ReduceSpeed { from: N, to: N, factor: f64 },
+    /// Vehicles keep `1 / factor` times their usual time headway on the edge,
+    /// reducing its capacity to roughly `factor` times the usual one.
+    ReduceCapacity { from: N, to: N, factor: f64 },
+}
+
+/// A change of the road network during a time window.
+/// Event files and scenarios give the nodes by their OSM ids, `Event<Osmid>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event<N = NodeId> {
+    /// Seconds of simulated time at which the event starts.
+    pub start: f64,
+    /// Seconds of simulated time at which the event ends. Without an end, the event lasts
+    /// until the end of the run.
+    #[serde(default)]
+    pub end: Option<f64>,
+    /// What the event does to the network.
+    #[serde(flatten)]
+    pub effect: Effect<N>,
+}
+
+impl Event<Osmid> {
+    // Replaces the OSM ids of the nodes by their indices
+    fn indexed(self, graph: &OSMGraph) -> Result<Event> {
+        self.map_nodes(|osm_id| {
+            graph.node(osm_id).ok_or_else(|| {
+                Error::Config(format!("Event node {} is not part of the graph", osm_id))
+            })
+        })
+    }
+}
+
+impl<N> Event<N> {
+    /// Returns whether the event is in effect at the given time in seconds.
+    pub fn is_active(&self, time: f64) -> bool {
+        self.start <= time && self.end.is_none_or(|end| time < end)
+    }
+
+    // Replaces the nodes of the event, failing if one cannot be replaced
+    fn map_nodes<M>(self, mut index: impl FnMut(N) -> Result<M>) -> Result<Event<M>> {
+        let effect = match self.effect {
+            Effect::CloseEdge { from, to } => Effect::CloseEdge {
+                from: index(from)?,
+                to: index(to)?,
+            },
+            Effect::CloseNode { node } => Effect::CloseNode { node: index(node)? },
+            Effect::ReduceSpeed { from, to, factor } => Effect::ReduceSpeed {
+                from: index(from)?,
+                to: index(to)?,
+                factor,
+            },
+            Effect::ReduceCapacity { from, to, factor } => Effect::ReduceCapacity {
+                from: index(from)?,
+                to: index(to)?,
+                factor,
+            },
+        };
+        Ok(Event {
+            start: self.start,
+            end: self.end,
+            effect,
+        })
+    }
+}
+
+impl Event {
+    fn check(&self, graph: &OSMGraph) -> Result<()> {
+        if self.end.is_some_and(|end| end <= self.start) {
+            return Err(Error::Config(format!(
+                "Event starting at {}s ends before it starts",
+                self.start
+            )));
+        }
+        match self.effect {
+            Effect::CloseEdge { from, to }
+            | Effect::ReduceSpeed { from, to, .. }
+            | Effect::ReduceCapacity { from, to, .. }
+                if !graph.contains_edge(from, to) =>
+            {
+                Err(Error::Config(format!(
+                    "Event edge {} -> {} is not part of the graph",
+                    graph.osm_id(from),
+                    graph.osm_id(to)
+                )))
+            }
+            Effect::CloseNode { node } if !graph.contains_node(node) => Err(Error::Config(
+                format!("Event node {} is not part of the graph", graph.osm_id(node)),
+            )),
+            Effect::ReduceSpeed { factor, .. } | Effect::ReduceCapacity { factor, .. }
+                if !(factor > 0.0 && factor <= 1.0) =>
+            {
+                Err(Error::Config(format!(
+                    "Event factor must be in (0, 1], but is {}",
+                    factor
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Contents of an event file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventFile {
+    #[serde(default)]
+    event: Vec<Event<Osmid>>,
+}
+
+/// Events changing the road network during the run.
+/// Every rank holds the same schedule and applies it at the simulation time kept by the root rank.
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    /// The scheduled events.
+    events: Vec<Event>,
+}
+
+impl Schedule {
+    /// Creates a schedule from the given events and those listed in an event file,
+    /// both referring to the nodes by their OSM ids.
+    pub fn load(
+        graph: &OSMGraph,
+        file: Option<&Path>,
+        mut events: Vec<Event<Osmid>>,
+    ) -> Result<Schedule> {
+        if let Some(path) = file {
+            let parsed: EventFile = read_structured(path)?;
+            events.extend(parsed.event);
+        }
+
+        let schedule = Schedule {
+            events: events
+                .into_iter()
+                .map(|event| event.indexed(graph))
+                .collect::<Result<_>>()?,
+        };
+        for event in &schedule.events {
+            event.check(graph)?;
+        }
+        Ok(schedule)
+    }
+
+    /// Returns the number of scheduled events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns whether no event is scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns whether an edge is closed from the given time in seconds until the end of the run.
+    pub fn is_closed_for_good(&self, from: NodeId, to: NodeId, time: f64) -> bool {
+        self.events
+            .iter()
+            .filter(|e| e.start <= time && e.end.is_none())
+            .any(|e| match e.effect {
+                Effect::CloseEdge { from: f, to: t } => (f, t) == (from, to),
+                Effect::CloseNode { node } => node == from || node == to,
+                _ => false,
+            })
+    }
+
+    /// Returns the state of the network at the given time in seconds.
+    pub fn conditions(&self, time: f64) -> Conditions {
+        let mut conditions = Conditions::default();
+        for event in self.events.iter().filter(|e| e.is_active(time)) {
+            match event.effect {
+                Effect::CloseEdge { from, to } => {
+                    conditions.closed_edges.insert((from, to));
+                }
+                Effect::CloseNode { node } => {
+                    conditions.closed_nodes.insert(node);
+                }
+                Effect::ReduceSpeed { from, to, factor } => {
+                    let entry = conditions.speed_factors.entry((from, to)).or_insert(1.0);
+                    *entry = entry.min(factor);
+                }
+                Effect::ReduceCapacity { from, to, factor } => {
+                    let entry = conditions.capacity_factors.entry((from, to)).or_insert(1.0);
+                    *entry = entry.min(factor);
+                }
+            }
+        }
+        conditions
+    }
+}
+
+/// The state of the road network at one point in time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conditions {
+    /// Edges no vehicle may enter.
+    closed_edges: HashSet<EdgeKey>,
+    /// Nodes no vehicle may drive through.
+    closed_nodes: HashSet<NodeId>,
+    /// Reduced speeds per edge, as a share of the desired speed.
+    speed_factors: HashMap<EdgeKey, f64>,
+    /// Reduced capacities per edge, as a share of the usual capacity.
+    capacity_factors: HashMap<EdgeKey, f64>,
+}
+
+impl Conditions {
+    /// Returns whether vehicles may not enter the edge.
+    pub fn is_closed(&self, from: NodeId, to: NodeId) -> bool {
+        self.closed_edges.contains(&(from, to))
+            || self.closed_nodes.contains(&from)
+            || self.closed_nodes.contains(&to)
+    }
+
+    /// Returns whether a route leads over a closed edge.
+    pub fn blocks(&self, route: &[NodeId]) -> bool {
+        route.windows(2).any(|w| self.is_closed(w[0], w[1]))
+    }
+
+    /// Returns the restrictions of an edge.
+    pub fn limits(&self, from: NodeId, to: NodeId) -> RoadLimits {
+        RoadLimits {
+            speed_factor: self.speed_factors.get(&(from, to)).copied().unwrap_or(1.0),
+            capacity_factor: self
+                .capacity_factors
+                .get(&(from, to))
+                .copied()
+                .unwrap_or(1.0),
+        }
+    }
+}
+
+// Unit tests for scheduled events
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_apply_in_their_time_window() {
+        let file: EventFile = toml::from_str(
+            r#"
+            [[event]]
+            kind = "close-edge"
+            from = 1
+            to = 2
+            start = 60.0
+            end = 120.0
+
+            [[event]]
+            kind = "close-node"
+            node = 5
+            start = 100.0
+
+            [[event]]
+            kind = "reduce-speed"
+            from = 2
+            to = 3
+            factor = 0.5
+            start = 0.0
+            "#,
+        )
+        .unwrap();
+        let events = file
+            .event
+            .into_iter()
+            .map(|e| e.map_nodes(|n| Ok(NodeId::new(n))))
+            .collect::<Result<_>>()
+            .unwrap();
+        let schedule = Schedule { events };
+        let [n0, n1, n2, n3, n4, n5, n6] = [0, 1, 2, 3, 4, 5, 6].map(NodeId);
+
+        let early = schedule.conditions(10.0);
+        assert!(!early.is_closed(n1, n2));
+        assert_eq!(early.limits(n2, n3).speed_factor, 0.5);
+        assert_eq!(early.limits(n1, n2), RoadLimits::default());
+
+        let closed = schedule.conditions(60.0);
+        assert!(closed.is_closed(n1, n2) && !closed.is_closed(n2, n1));
+        assert!(closed.blocks(&[n0, n1, n2, n3]));
+
+        let late = schedule.conditions(120.0);
+        assert!(!late.is_closed(n1, n2));
+        assert!(late.is_closed(n4, n5) && late.is_closed(n5, n6));
+    }
+}
@@ -0,0 +1,429 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::read_structured,
+    graph::osm_graph::{NodeId, OSMGraph, Osmid},
+    models::vehicle_class::MOTOR_ROADS,
+    prelude::*,
+};
+
+// Green time of each phase of signal plans derived from OSM tags in seconds
+const DEFAULT_GREEN: f64 = 27.0;
+
+// Time a yielding vehicle needs to enter in front of priority traffic in seconds
+pub const CRITICAL_GAP: f64 = 4.0;
+
+// Time between two vehicles released by an all-way stop in seconds
+pub const STOP_HEADWAY: f64 = 3.0;
+
+fn default_clearance() -> f64 {
+    3.0
+}
+
+/// One phase of a fixed-time signal plan.
+/// Intersection files give the approaches by their OSM ids, `Phase<Osmid>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Phase<N = NodeId> {
+    /// The nodes of the approaches that have green.
+    pub approaches: Vec<N>,
+    /// The duration of the green in seconds.
+    pub green: f64,
+}
+
+/// Decides which vehicles may enter an intersection.
+/// Intersection files give the approaches by their OSM ids, `Controller<Osmid>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Controller<N = NodeId> {
+    /// Fixed-time signal plan. The phases follow each other, separated by an all-red clearance time.
+    Signal {
+        phases: Vec<Phase<N>>,
+        #[serde(default = "default_clearance")]
+        clearance: f64,
+        #[serde(default)]
+        offset: f64,
+    },
+    /// Vehicles from the priority approaches have the right of way, all others yield.
+    Yield { priority: Vec<N> },
+    /// Every vehicle stops, then the vehicles enter one at a time in the order they arrived.
+    AllWayStop,
+}
+
+impl Controller {
+    /// Returns whether a vehicle coming from `from` has green `time` seconds into the simulation.
+    /// Controllers other than signals are always green.
+    pub fn is_green(&self, from: NodeId, time: f64) -> bool {
+        let (phases, clearance, offset) = match self {
+            Controller::Signal {
+                phases,
+                clearance,
+                offset,
+            } => (phases, *clearance, *offset),
+            _ => return true,
+        };
+        let cycle: f64 = phases.iter().map(|p| p.green + clearance).sum();
+        let mut t = (time + offset).rem_euclid(cycle);
+        for phase in phases {
+            if t < phase.green {
+                return phase.approaches.contains(&from);
+            }
+            t -= phase.green + clearance;
+            if t < 0.0 {
+                // all-red
+                return false;
+            }
+        }
+        false
+    }
+}
+
+impl Controller<Osmid> {
+    // Replaces the OSM ids of the approaches by the indices of their nodes
+    fn indexed(self, graph: &OSMGraph) -> Result<Controller> {
+        let index = |nodes: Vec<Osmid>| {
+            nodes
+                .into_iter()
+                .map(|osm_id| {
+                    graph.node(osm_id).ok_or_else(|| {
+                        Error::Config(format!("Approach {} is not part of the graph", osm_id))
+                    })
+                })
+                .collect::<Result<Vec<NodeId>>>()
+        };
+        Ok(match self {
+            Controller::Signal {
+                phases,
+                clearance,
+                offset,
+            } => Controller::Signal {
+                phases: phases
+                    .into_iter()
+                    .map(|p| {
+                        Ok(Phase {
+                            approaches: index(p.approaches)?,
+                            green: p.green,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+                clearance,
+                offset,
+            },
+            Controller::Yield { priority } => Controller::Yield {
+                priority: index(priority)?,
+            },
+            Controller::AllWayStop => Controller::AllWayStop,
+        })
+    }
+
+    fn check(&self, node: Osmid) -> Result<()> {
+        if let Controller::Signal {
+            phases, clearance, ..
+        } = self
+        {
+            if phases.is_empty() || phases.iter().any(|p| p.green <= 0.0) || *clearance < 0.0 {
+                return Err(Error::Config(format!(
+                    "Signal at node {} needs phases with a positive green and a non-negative clearance",
+                    node
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A controller placed on a node, as listed in an intersection file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Intersection {
+    /// The OSM id of the controlled node.
+    pub node: Osmid,
+    /// The controller of the node.
+    #[serde(flatten)]
+    pub controller: Controller<Osmid>,
+}
+
+// Contents of an intersection file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IntersectionFile {
+    #[serde(default)]
+    intersection: Vec<Intersection>,
+}
+
+/// Controllers of the intersections of a graph.
+#[derive(Debug, Clone, Default)]
+pub struct Intersections {
+    /// The controller per node.
+    controllers: HashMap<NodeId, Controller>,
+    /// The nodes the edges into each controlled node come from.
+    approaches: HashMap<NodeId, Vec<NodeId>>,
+}
+
+impl Intersections {
+    /// Derives controllers from the OSM `highway` tags of the vertices
+    /// (`traffic_signals`, `stop`, `give_way`) and adds those listed in an intersection file.
+    /// Controllers from the file replace the derived ones.
+    pub fn load(graph: &OSMGraph, file: Option<&Path>) -> Result<Intersections> {
+        let mut intersections = Intersections::default();
+
+        // The coordinates of the nodes and the road types of the edges, shared by all
+        // derived controllers
+        let coordinates: HashMap<NodeId, (f64, f64)> = graph
+            .input_vertices()
+            .filter(|(_, v)| v.has_coordinates())
+            .map(|(node, v)| (node, (v.x, v.y)))
+            .collect();
+        let mut road_ranks: HashMap<(NodeId, NodeId), usize> = HashMap::new();
+        for (from, to, edge) in graph.input_edges() {
+            let rank = edge
+                .highway
+                .as_deref()
+                .and_then(|h| MOTOR_ROADS.iter().position(|r| *r == h))
+                .unwrap_or(MOTOR_ROADS.len());
+            let best = road_ranks.entry((from, to)).or_insert(rank);
+            *best = rank.min(*best);
+        }
+
+        for (node, vertex) in graph.input_vertices() {
+            let controller = match vertex.highway.as_deref() {
+                Some("traffic_signals") => default_signal(graph, &coordinates, node),
+                Some("stop") => Controller::AllWayStop,
+                Some("give_way") => default_yield(graph, &road_ranks, node),
+                _ => continue,
+            };
+            intersections.insert(graph, node, controller);
+        }
+
+        if let Some(path) = file {
+            let parsed: IntersectionFile = read_structured(path)?;
+            for Intersection { node, controller } in parsed.intersection {
+                controller.check(node)?;
+                let controller = controller
+                    .indexed(graph)
+                    .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))?;
+                match graph.node(node).filter(|n| graph.contains_node(*n)) {
+                    Some(node) => intersections.insert(graph, node, controller),
+                    None => {
+                        return Err(Error::Config(format!(
+                            "{}: node {} is not part of the graph",
+                            path.display(),
+                            node
+                        )))
+                    }
+                }
+            }
+        }
+
+        Ok(intersections)
+    }
+
+    /// Places a controller on a node, replacing its previous controller.
+    pub fn insert(&mut self, graph: &OSMGraph, node: NodeId, controller: Controller) {
+        let approaches = graph.predecessors(node).collect();
+        self.approaches.insert(node, approaches);
+        self.controllers.insert(node, controller);
+    }
+
+    /// Returns the controller of a node.
+    pub fn get(&self, node: NodeId) -> Option<&Controller> {
+        self.controllers.get(&node)
+    }
+
+    /// Returns the nodes the edges into a controlled node come from.
+    pub fn approaches(&self, node: NodeId) -> &[NodeId] {
+        self.approaches.get(&node).map_or(&[], |a| a.as_slice())
+    }
+
+    /// Returns the nodes controlled by an all-way stop.
+    pub fn stops(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.controllers
+            .iter()
+            .filter(|(_, c)| **c == Controller::AllWayStop)
+            .map(|(node, _)| *node)
+    }
+
+    /// Returns the number of controlled nodes.
+    pub fn len(&self) -> usize {
+        self.controllers.len()
+    }
+
+    /// Returns whether no node is controlled.
+    pub fn is_empty(&self) -> bool {
+        self.controllers.is_empty()
+    }
+}
+
+/// Vehicles waiting at an all-way stop.
+#[derive(Debug, Clone, Default)]
+pub struct StopState {
+    /// Vehicles that stopped at the stop line, in the order they arrived.
+    pub waiting: VecDeque<u64>,
+    /// Vehicles allowed to enter the intersection.
+    pub released: HashSet<u64>,
+    /// Time the last vehicle was released in seconds.
+    pub last_release: Option<f64>,
+}
+
+impl StopState {
+    /// Releases the vehicle that waited longest, once the intersection is clear.
+    pub fn release(&mut self, time: f64) {
+        let clear = self.released.is_empty()
+            && self
+                .last_release
+                .is_none_or(|last| time - last >= STOP_HEADWAY);
+        if clear {
+            if let Some(id) = self.waiting.pop_front() {
+                self.released.insert(id);
+                self.last_release = Some(time);
+            }
+        }
+    }
+}
+
+// Two-phase signal plan: approaches along the x-axis and along the y-axis get green in turn.
+// Signals with approaches along one axis only, like pedestrian crossings, give the second
+// phase to the crossing pedestrians.
+fn default_signal(
+    graph: &OSMGraph,
+    coordinates: &HashMap<NodeId, (f64, f64)>,
+    node: NodeId,
+) -> Controller {
+    let mut along_x = Vec::new();
+    let mut along_y = Vec::new();
+    for from in graph.predecessors(node) {
+        match (coordinates.get(&from), coordinates.get(&node)) {
+            (Some(a), Some(b)) if (b.0 - a.0).abs() < (b.1 - a.1).abs() => along_y.push(from),
+            _ => along_x.push(from),
+        }
+    }
+
+    Controller::Signal {
+        phases: vec![
+            Phase {
+                approaches: along_x,
+                green: DEFAULT_GREEN,
+            },
+            Phase {
+                approaches: along_y,
+                green: DEFAULT_GREEN,
+            },
+        ],
+        clearance: default_clearance(),
+        offset: 0.0,
+    }
+}
+
+// Gives the right of way to the approaches on the most important road type,
+// given the rank of the road type of every edge in `MOTOR_ROADS`
+fn default_yield(
+    graph: &OSMGraph,
+    road_ranks: &HashMap<(NodeId, NodeId), usize>,
+    node: NodeId,
+) -> Controller {
+    let rank = |from: NodeId| {
+        road_ranks
+            .get(&(from, node))
+            .copied()
+            .unwrap_or(MOTOR_ROADS.len())
+    };
+    let approaches: Vec<NodeId> = graph.predecessors(node).collect();
+    let best = approaches.iter().map(|a| rank(*a)).min();
+    Controller::Yield {
+        priority: approaches
+            .into_iter()
+            .filter(|a| Some(rank(*a)) == best)
+            .collect(),
+    }
+}
+
+// Unit tests for intersection control
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal() -> Controller {
+        Controller::Signal {
+            phases: vec![
+                Phase {
+                    approaches: vec![NodeId(1)],
+                    green: 20.0,
+                },
+                Phase {
+                    approaches: vec![NodeId(2), NodeId(3)],
+                    green: 10.0,
+                },
+            ],
+            clearance: 5.0,
+            offset: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_signal_phases() {
+        let signal = signal();
+        // cycle: 20s green for 1, 5s all-red, 10s green for 2 and 3, 5s all-red
+        assert!(signal.is_green(NodeId(1), 0.0) && signal.is_green(NodeId(1), 19.0));
+        assert!(!signal.is_green(NodeId(1), 22.0) && !signal.is_green(NodeId(2), 22.0));
+        assert!(signal.is_green(NodeId(2), 25.0) && signal.is_green(NodeId(3), 34.0));
+        assert!(!signal.is_green(NodeId(1), 30.0) && !signal.is_green(NodeId(3), 36.0));
+        assert!(signal.is_green(NodeId(1), 40.0));
+        assert!(Controller::AllWayStop.is_green(NodeId(7), 3.0));
+    }
+
+    #[test]
+    fn test_parse_intersection_file() {
+        let file: IntersectionFile = toml::from_str(
+            r#"
+            [[intersection]]
+            node = 5
+            kind = "signal"
+            clearance = 2.0
+            phases = [{ approaches = [4], green = 30.0 }, { approaches = [6], green = 20.0 }]
+
+            [[intersection]]
+            node = 6
+            kind = "all-way-stop"
+
+            [[intersection]]
+            node = 7
+            kind = "yield"
+            priority = [6]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(file.intersection.len(), 3);
+        assert!(matches!(
+            file.intersection[0].controller,
+            Controller::Signal { clearance, offset, .. } if clearance == 2.0 && offset == 0.0
+        ));
+        assert_eq!(file.intersection[1].controller, Controller::AllWayStop);
+        assert!(toml::from_str::<IntersectionFile>(
+            "[[intersection]]\nnode = 1\nkind = \"roundabout\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_release_order_at_stop() {
+        let mut state = StopState::default();
+        state.waiting.extend([1, 2]);
+
+        state.release(0.0);
+        assert!(state.released.contains(&1));
+        // the intersection is occupied
+        state.release(10.0);
+        assert_eq!(state.released.len(), 1);
+
+        state.released.clear();
+        state.release(1.0);
+        assert!(state.released.is_empty());
+        state.release(3.0);
+        assert!(state.released.contains(&2));
+    }
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    DEFAULT_KEEP_RIGHT_BIAS, DEFAULT_LANE_CHANGE_THRESHOLD, DEFAULT_POLITENESS,
+    DEFAULT_SAFE_DECELERATION,
+};
+
+/// Parameters of the MOBIL lane-change model ("Minimizing Overall Braking Induced by Lane changes").
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MobilParameters {
+    /// Weight of the advantage or disadvantage of the surrounding vehicles, from 0 (selfish) to 1.
+    pub politeness: f64,
+    /// Acceleration gain in m/s² a lane change has to bring.
+    pub threshold: f64,
+    /// Strongest deceleration in m/s² a lane change may impose on the new follower.
+    pub safe_deceleration: f64,
+    /// Acceleration gain in m/s² granted to changes to the right, keeping vehicles on the right lane.
+    pub keep_right_bias: f64,
+}
+
+impl Default for MobilParameters {
+    fn default() -> Self {
+        MobilParameters {
+            politeness: DEFAULT_POLITENESS,
+            threshold: DEFAULT_LANE_CHANGE_THRESHOLD,
+            safe_deceleration: DEFAULT_SAFE_DECELERATION,
+            keep_right_bias: DEFAULT_KEEP_RIGHT_BIAS,
+        }
+    }
+}
+
+/// The acceleration of a vehicle before and after a lane change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Accelerations {
+    /// Acceleration in m/s² if the lane change does not happen.
+    pub before: f64,
+    /// Acceleration in m/s² after the lane change.
+    pub after: f64,
+}
+
+impl Accelerations {
+    fn gain(&self) -> f64 {
+        self.after - self.before
+    }
+}
+
+// Returns the incentive of a lane change in m/s², or `None` if the change is unsafe for the
+// new follower or does not pay off. `bias` is added to the gain of the changing vehicle.
+pub fn mobil_incentive(
+    params: &MobilParameters,
+    own: Accelerations,
+    new_follower: Option<Accelerations>,
+    old_follower: Option<Accelerations>,
+    bias: f64,
+) -> Option<f64> {
+    if new_follower.is_some_and(|f| f.after < -params.safe_deceleration) {
+        return None;
+    }
+    let others = new_follower.map_or(0.0, |f| f.gain()) + old_follower.map_or(0.0, |f| f.gain());
+    let incentive = own.gain() + params.politeness * others + bias;
+    (incentive > params.threshold).then_some(incentive)
+}
+
+// Unit tests for the lane-change model
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acc(before: f64, after: f64) -> Accelerations {
+        Accelerations { before, after }
+    }
+
+    #[test]
+    fn test_change_pays_off() {
+        let params = MobilParameters::default();
+        // stuck behind a slow vehicle with a free lane next to it
+        assert!(mobil_incentive(&params, acc(-1.0, 1.0), None, Some(acc(0.0, 0.5)), 0.0).is_some());
+        // no gain, and the right lane is preferred
+        assert!(mobil_incentive(&params, acc(0.5, 0.5), None, None, 0.0).is_none());
+        assert!(mobil_incentive(&params, acc(0.5, 0.5), None, None, 0.2).is_some());
+        // the gain is eaten up by the disadvantage of the new follower
+        assert!(mobil_incentive(&params, acc(0.0, 0.5), Some(acc(1.0, -1.0)), None, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_unsafe_change_is_rejected() {
+        let params = MobilParameters::default();
+        let incentive = mobil_incentive(&params, acc(-3.0, 2.0), Some(acc(0.0, -5.0)), None, 0.0);
+        assert!(incentive.is_none());
+    }
+}
@@ -1,3 +1,18 @@
+pub mod activity;
+pub mod assignment;
+pub mod car_following;
 pub mod graph_input;
+pub mod handoff;
+pub mod incident;
+pub mod intersection;
+pub mod lane_change;
+pub mod observer;
+pub mod parking;
+pub mod rerouting;
+pub mod speed;
+pub mod traffic;
+pub mod transit;
 pub mod vehicle;
-mod vehicle_builder;
+pub mod vehicle_builder;
+pub mod vehicle_class;
+pub mod vehicle_generator;
@@ -0,0 +1,64 @@
+use crate::{graph::osm_graph::NodeId, models::vehicle::Vehicle};
+
+/// Receives the events of a simulation, e.g. to collect custom metrics or draw the vehicles.
+/// Every callback has an empty default, so observers only implement the events they need.
+/// The time is the step the event happens in. Nodes are given by their index,
+/// `OSMGraph::osm_id` returns their OSM ids.
+///
+/// Observers only run in a single-process [`Simulation`](crate::Simulation), where no vehicle
+/// ever leaves its partition; the MPI ranks of the `traffic-sim` binary do not call them.
+pub trait Observer {
+    /// A vehicle entered the road network, at the start of its route.
+    fn on_spawn(&mut self, _time: u64, _vehicle: &Vehicle) {}
+
+    /// A vehicle entered an edge.
+    fn on_edge_entry(&mut self, _time: u64, _vehicle: &Vehicle, _edge: (NodeId, NodeId)) {}
+
+    /// A vehicle left an edge.
+    fn on_edge_exit(&mut self, _time: u64, _vehicle: &Vehicle, _edge: (NodeId, NodeId)) {}
+
+    /// A vehicle reached the destination of its trip.
+    fn on_arrival(&mut self, _time: u64, _vehicle: &Vehicle) {}
+
+    /// All vehicles took the step.
+    fn on_step(&mut self, _time: u64) {}
+}
+
+/// Ignores every event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
+
+// Observers attached to a simulation all receive every event, in the order they were added
+impl<O: Observer + ?Sized> Observer for Vec<Box<O>> {
+    fn on_spawn(&mut self, time: u64, vehicle: &Vehicle) {
+        for observer in self.iter_mut() {
+            observer.on_spawn(time, vehicle);
+        }
+    }
+
+    fn on_edge_entry(&mut self, time: u64, vehicle: &Vehicle, edge: (NodeId, NodeId)) {
+        for observer in self.iter_mut() {
+            observer.on_edge_entry(time, vehicle, edge);
+        }
+    }
+
+    fn on_edge_exit(&mut self, time: u64, vehicle: &Vehicle, edge: (NodeId, NodeId)) {
+        for observer in self.iter_mut() {
+            observer.on_edge_exit(time, vehicle, edge);
+        }
+    }
+
+    fn on_arrival(&mut self, time: u64, vehicle: &Vehicle) {
+        for observer in self.iter_mut() {
+            observer.on_arrival(time, vehicle);
+        }
+    }
+
+    fn on_step(&mut self, time: u64) {
+        for observer in self.iter_mut() {
+            observer.on_step(time);
+        }
+    }
+}
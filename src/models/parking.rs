@@ -0,0 +1,243 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    config::read_structured,
+    graph::osm_graph::{NodeId, OSMGraph, Osmid},
+    prelude::*,
+};
+
+// Longest search for a parking space in seconds before a driver gives up
+const DEFAULT_MAX_SEARCH: f64 = 600.0;
+
+/// Parking spaces shared by the nodes of an area, as listed in a parking file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Zone {
+    /// The OSM ids of the nodes of the area.
+    pub nodes: Vec<Osmid>,
+    /// The number of parking spaces of the area.
+    pub capacity: usize,
+}
+
+// Contents of a parking file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParkingFile {
+    #[serde(default)]
+    default_capacity: Option<usize>,
+    #[serde(default)]
+    max_search: Option<f64>,
+    #[serde(default)]
+    zone: Vec<Zone>,
+}
+
+/// Parking spaces at the destinations of the vehicles.
+/// Without a parking file, every node has unlimited spaces.
+#[derive(Debug, Clone)]
+pub struct Parking {
+    /// The number of spaces and of taken spaces per zone.
+    zones: Vec<(usize, usize)>,
+    /// The zone of every node listed in a zone.
+    node_zones: HashMap<NodeId, usize>,
+    /// The number of spaces of nodes outside of the zones, unlimited if missing.
+    default_capacity: Option<usize>,
+    /// The number of taken spaces of nodes outside of the zones.
+    occupied: HashMap<NodeId, usize>,
+    /// Longest search for a parking space in seconds.
+    pub max_search: f64,
+}
+
+impl Default for Parking {
+    fn default() -> Self {
+        Parking {
+            zones: Vec::new(),
+            node_zones: HashMap::new(),
+            default_capacity: None,
+            occupied: HashMap::new(),
+            max_search: DEFAULT_MAX_SEARCH,
+        }
+    }
+}
+
+impl Parking {
+    /// Reads the parking zones from a TOML, YAML or JSON file.
+    pub fn load(graph: &OSMGraph, file: Option<&Path>) -> Result<Parking> {
+        let mut parking = Parking::default();
+        let path = match file {
+            Some(path) => path,
+            None => return Ok(parking),
+        };
+
+        let parsed: ParkingFile = read_structured(path)?;
+
+        if let Some(max_search) = parsed.max_search {
+            if max_search.is_nan() || max_search < 0.0 {
+                return Err(Error::Config(format!(
+                    "{}: longest parking search must not be negative, but is {}",
+                    path.display(),
+                    max_search
+                )));
+            }
+            parking.max_search = max_search;
+        }
+        parking.default_capacity = parsed.default_capacity;
+        for zone in parsed.zone {
+            for osm_id in &zone.nodes {
+                let node = match graph.node(*osm_id).filter(|n| graph.contains_node(*n)) {
+                    Some(node) => node,
+                    None => {
+                        return Err(Error::Config(format!(
+                            "{}: node {} is not part of the graph",
+                            path.display(),
+                            osm_id
+                        )))
+                    }
+                };
+                if parking
+                    .node_zones
+                    .insert(node, parking.zones.len())
+                    .is_some()
+                {
+                    return Err(Error::Config(format!(
+                        "{}: node {} is part of more than one zone",
+                        path.display(),
+                        osm_id
+                    )));
+                }
+            }
+            parking.zones.push((zone.capacity, 0));
+        }
+        Ok(parking)
+    }
+
+    /// Checks that no zone has nodes both inside and outside of the given partition.
+    /// Every rank loads all zones but only parks vehicles at the nodes of its partition,
+    /// so the spaces of a zone spanning several partitions would be counted once per rank.
+    pub fn check_partition(&self, partition: &OSMGraph) -> Result<()> {
+        let mut inside: HashMap<usize, (NodeId, bool)> = HashMap::new();
+        for (node, zone) in &self.node_zones {
+            let contained = partition.contains_node(*node);
+            match inside.insert(*zone, (*node, contained)) {
+                Some((other, was_contained)) if was_contained != contained => {
+                    return Err(Error::Config(format!(
+                        "Parking zone with nodes {} and {} spans several partitions, \
+                         which MPI runs do not support",
+                        partition.osm_id(other),
+                        partition.osm_id(*node)
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether a space is free at the given node.
+    pub fn has_space(&self, node: NodeId) -> bool {
+        match self.node_zones.get(&node) {
+            Some(zone) => self.zones[*zone].1 < self.zones[*zone].0,
+            None => self
+                .default_capacity
+                .is_none_or(|capacity| self.occupied.get(&node).copied().unwrap_or(0) < capacity),
+        }
+    }
+
+    /// Frees a space at the given node.
+    pub fn leave(&mut self, node: NodeId) {
+        match self.node_zones.get(&node) {
+            Some(zone) => self.zones[*zone].1 = self.zones[*zone].1.saturating_sub(1),
+            None => {
+                if let Some(occupied) = self.occupied.get_mut(&node) {
+                    *occupied = occupied.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Takes a space at the given node, if one is free.
+    pub fn park(&mut self, node: NodeId) -> bool {
+        if !self.has_space(node) {
+            return false;
+        }
+        match self.node_zones.get(&node) {
+            Some(zone) => self.zones[*zone].1 += 1,
+            None => *self.occupied.entry(node).or_default() += 1,
+        }
+        true
+    }
+}
+
+// Unit tests for parking
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::Partitioner,
+        fixtures::{edge, load_parking},
+        graph::osm_graph::GPartition,
+        models::graph_input::{Graph, Vertex},
+    };
+
+    #[test]
+    fn test_zones_fill_up() {
+        let mut parking = Parking {
+            zones: vec![(2, 0)],
+            node_zones: HashMap::from([(NodeId(1), 0), (NodeId(2), 0)]),
+            default_capacity: Some(1),
+            ..Parking::default()
+        };
+
+        assert!(parking.park(NodeId(1)));
+        assert!(parking.park(NodeId(2)));
+        assert!(!parking.has_space(NodeId(1)) && !parking.park(NodeId(2)));
+        // nodes outside of the zones have their own spaces
+        assert!(parking.park(NodeId(3)));
+        assert!(parking.park(NodeId(4)));
+        assert!(!parking.park(NodeId(3)));
+
+        let mut unlimited = Parking::default();
+        assert!((0..100).all(|_| unlimited.park(NodeId(1))));
+    }
+
+    #[test]
+    fn test_zones_must_not_span_partitions() {
+        // a line 1 -> 2 -> 3 -> 4 along the x-axis
+        let vertices = (1..=4)
+            .map(|i| Vertex {
+                x: i as f64,
+                y: 0.0,
+                osm_id: i,
+                highway: None,
+            })
+            .collect();
+        let edges = (1..4).map(|i| edge(i, i + 1, 10.0)).collect();
+        let graph = OSMGraph::new(Graph { vertices, edges }).unwrap();
+        let node = |osm_id| graph.node(osm_id).unwrap();
+        let parts: Vec<OSMGraph> = (0..2)
+            .map(|i| graph.partition(Partitioner::VerticalStripes, 2, i).unwrap())
+            .collect();
+        let zone = |osm_ids: &[Osmid]| {
+            load_parking(
+                &graph,
+                &format!("[[zone]]\nnodes = {:?}\ncapacity = 1\n", osm_ids),
+            )
+        };
+        let (first, second) = if parts[0].contains_node(node(1)) {
+            (&parts[0], &parts[1])
+        } else {
+            (&parts[1], &parts[0])
+        };
+        assert!(first.contains_node(node(2)) && second.contains_node(node(3)));
+
+        for part in [first, second] {
+            assert!(zone(&[1, 2]).check_partition(part).is_ok());
+            assert!(zone(&[3, 4]).check_partition(part).is_ok());
+            assert!(matches!(
+                zone(&[2, 3]).check_partition(part),
+                Err(Error::Config(_))
+            ));
+        }
+    }
+}
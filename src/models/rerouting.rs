@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use petgraph::{algo::astar, prelude::GraphMap, Directed};
+
+use crate::{
+    graph::osm_graph::{NodeId, OSMGraph},
+    models::{incident::Conditions, vehicle_class::VehicleClass},
+};
+
+// Speed assumed on roads without a known speed limit in m/s
+const DEFAULT_SPEED_LIMIT: f64 = 50.0 / 3.6;
+
+// Lowest speed used for travel times in m/s, standing traffic is assumed to move eventually
+const MIN_TRAVEL_SPEED: f64 = 0.5;
+
+/// When informed drivers re-plan their route.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReroutingOptions {
+    /// Seconds between two periodic re-plans of all informed drivers.
+    pub interval: u64,
+    /// Ratio of the current to the free-flow travel time of the next edge of the route,
+    /// above which an informed driver entering an edge re-plans right away.
+    pub congestion_threshold: f64,
+}
+
+/// Plans routes by the current travel times of the edges, on the roads each vehicle class may use.
+#[derive(Debug)]
+pub struct Router<'a> {
+    /// The whole graph, routes may lead through other partitions.
+    graph: &'a OSMGraph,
+    /// The roads every class may drive on.
+    classes: HashMap<String, GraphMap<NodeId, f64, Directed>>,
+    /// The speed limit of every edge in m/s.
+    limits: HashMap<(NodeId, NodeId), f64>,
+    /// When drivers re-plan their route.
+    pub options: ReroutingOptions,
+}
+
+impl<'a> Router<'a> {
+    /// Creates a router on the given graph for the given vehicle classes.
+    pub fn new<'c>(
+        graph: &'a OSMGraph,
+        classes: impl IntoIterator<Item = &'c VehicleClass>,
+        options: ReroutingOptions,
+    ) -> Router<'a> {
+        let classes = classes
+            .into_iter()
+            .map(|class| (class.name.clone(), graph.class_graph(class)))
+            .collect();
+        let limits = graph
+            .input_edges()
+            .map(|(from, to, e)| {
+                let limit = e.max_speed_mps().unwrap_or(DEFAULT_SPEED_LIMIT);
+                ((from, to), limit)
+            })
+            .collect();
+        Router {
+            graph,
+            classes,
+            limits,
+            options,
+        }
+    }
+
+    /// Returns the graph routes are planned on.
+    pub fn graph(&self) -> &'a OSMGraph {
+        self.graph
+    }
+
+    /// Returns the travel time of an edge in seconds for a vehicle with the given desired speed,
+    /// driving at the observed speed of the edge if there is one. Closed edges take forever.
+    pub fn travel_time(
+        &self,
+        edge: (NodeId, NodeId),
+        desired_speed: f64,
+        conditions: &Conditions,
+        observed: &HashMap<(NodeId, NodeId), f64>,
+    ) -> f64 {
+        if conditions.is_closed(edge.0, edge.1) {
+            return f64::INFINITY;
+        }
+        let length = self.graph.edge_length(edge.0, edge.1).unwrap_or(0.0);
+        let mut speed = self
+            .limits
+            .get(&edge)
+            .copied()
+            .unwrap_or(DEFAULT_SPEED_LIMIT)
+            .min(desired_speed)
+            * conditions.limits(edge.0, edge.1).speed_factor;
+        if let Some(observed) = observed.get(&edge) {
+            speed = speed.min(*observed);
+        }
+        length / speed.max(MIN_TRAVEL_SPEED)
+    }
+
+    /// Returns whether an edge is congested or closed for a vehicle with the given desired speed.
+    pub fn is_congested(
+        &self,
+        edge: (NodeId, NodeId),
+        desired_speed: f64,
+        conditions: &Conditions,
+        observed: &HashMap<(NodeId, NodeId), f64>,
+    ) -> bool {
+        let free_flow =
+            self.travel_time(edge, desired_speed, &Conditions::default(), &HashMap::new());
+        self.travel_time(edge, desired_speed, conditions, observed)
+            > free_flow * self.options.congestion_threshold
+    }
+
+    /// Finds the fastest open route of a vehicle of the given class between two nodes.
+    /// This is a plain A* search without a lower bound: the landmarks of the vehicle
+    /// generation bound distances, not travel times under the current congestion.
+    pub fn route(
+        &self,
+        class: &str,
+        from: NodeId,
+        to: NodeId,
+        desired_speed: f64,
+        conditions: &Conditions,
+        observed: &HashMap<(NodeId, NodeId), f64>,
+    ) -> Option<Vec<NodeId>> {
+        let graph = self.classes.get(class)?;
+        astar(
+            graph,
+            from,
+            |finish| finish == to,
+            |e| self.travel_time((e.0, e.1), desired_speed, conditions, observed),
+            |_| 0.,
+        )
+        .filter(|p| p.0.is_finite())
+        .map(|p| p.1)
+    }
+}
+
+// Unit tests for rerouting
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::edge,
+        graph::osm_graph::Osmid,
+        models::{
+            graph_input::{Edge, Graph},
+            incident::{Effect, Event, Schedule},
+        },
+    };
+
+    // An edge with a speed limit of 36 km/h, driven at 10 m/s
+    fn slow(from: Osmid, to: Osmid, length: f64) -> Edge {
+        Edge {
+            max_speed: String::from("36"),
+            ..edge(from, to, length)
+        }
+    }
+
+    // Two routes from 1 to 4: a short one over 2 and a long one over 3
+    fn detour() -> OSMGraph {
+        OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges: vec![
+                slow(1, 2, 100.0),
+                slow(2, 4, 100.0),
+                slow(1, 3, 150.0),
+                slow(3, 4, 150.0),
+            ],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_route_avoids_congestion() {
+        let graph = detour();
+        let car = VehicleClass::car();
+        let options = ReroutingOptions {
+            interval: 60,
+            congestion_threshold: 2.0,
+        };
+        let router = Router::new(&graph, [&car], options);
+        let [n1, n2, n3, n4] = [1, 2, 3, 4].map(|osm_id| graph.node(osm_id).unwrap());
+
+        let open = Conditions::default();
+        let free = HashMap::new();
+        assert_eq!(
+            router.route("car", n1, n4, 13.0, &open, &free),
+            Some(vec![n1, n2, n4])
+        );
+        assert!((router.travel_time((n1, n2), 13.0, &open, &free) - 10.0).abs() < 1e-9);
+
+        let jammed = HashMap::from([((n2, n4), 1.0)]);
+        assert_eq!(
+            router.route("car", n1, n4, 13.0, &open, &jammed),
+            Some(vec![n1, n3, n4])
+        );
+        assert!(router.is_congested((n2, n4), 13.0, &open, &jammed));
+        assert!(!router.is_congested((n1, n2), 13.0, &open, &jammed));
+        assert_eq!(router.route("truck", n1, n4, 13.0, &open, &free), None);
+    }
+
+    #[test]
+    fn test_route_avoids_closed_edges() {
+        let graph = detour();
+        let car = VehicleClass::car();
+        let options = ReroutingOptions {
+            interval: 60,
+            congestion_threshold: 2.0,
+        };
+        let router = Router::new(&graph, [&car], options);
+        let [n1, n2, n3, n4] = [1, 2, 3, 4].map(|osm_id| graph.node(osm_id).unwrap());
+        let close = |from, to| Event {
+            start: 0.0,
+            end: None,
+            effect: Effect::CloseEdge { from, to },
+        };
+        let free = HashMap::new();
+
+        let schedule = Schedule::load(&graph, None, vec![close(2, 4)]).unwrap();
+        let closed = schedule.conditions(0.0);
+        assert_eq!(
+            router.route("car", n1, n4, 13.0, &closed, &free),
+            Some(vec![n1, n3, n4])
+        );
+        assert!(router.is_congested((n2, n4), 13.0, &closed, &free));
+
+        let schedule = Schedule::load(&graph, None, vec![close(2, 4), close(1, 3)]).unwrap();
+        let closed = schedule.conditions(0.0);
+        assert_eq!(router.route("car", n1, n4, 13.0, &closed, &free), None);
+    }
+}
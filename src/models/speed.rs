@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+
+use rand::{distributions::WeightedIndex, Rng};
+use rand_distr::{Distribution, LogNormal, Normal};
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::SpeedDistributionKind, prelude::*, utils::random_velocity};
+
+// Number of draws before a truncated distribution falls back to clamping
+const MAX_TRUNCATION_DRAWS: usize = 100;
+
+/// Distribution vehicle speeds in m/s are drawn from.
+/// Every distribution is truncated to the configured minimum and maximum speed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum SpeedDistribution {
+    /// Uniform between the minimum and maximum speed.
+    Uniform,
+    /// Normal distribution with the given mean and standard deviation.
+    Normal { mean: f64, std_dev: f64 },
+    /// Log-normal distribution with the given mean and standard deviation of the speed.
+    LogNormal { mean: f64, std_dev: f64 },
+    /// Histogram read from a CSV file with `lower,upper,weight` rows.
+    Empirical { histogram: PathBuf },
+}
+
+impl SpeedDistribution {
+    /// Assembles a distribution from its kind and the parameters given on the command line.
+    pub fn from_kind(
+        kind: SpeedDistributionKind,
+        mean: Option<f64>,
+        std_dev: Option<f64>,
+        histogram: Option<PathBuf>,
+    ) -> Result<SpeedDistribution> {
+        let missing = |name: &str| {
+            Error::Config(format!(
+                "The {:?} speed distribution requires --{}",
+                kind, name
+            ))
+        };
+        Ok(match kind {
+            SpeedDistributionKind::Uniform => SpeedDistribution::Uniform,
+            SpeedDistributionKind::Normal => SpeedDistribution::Normal {
+                mean: mean.ok_or_else(|| missing("speed-mean"))?,
+                std_dev: std_dev.ok_or_else(|| missing("speed-std-dev"))?,
+            },
+            SpeedDistributionKind::LogNormal => SpeedDistribution::LogNormal {
+                mean: mean.ok_or_else(|| missing("speed-mean"))?,
+                std_dev: std_dev.ok_or_else(|| missing("speed-std-dev"))?,
+            },
+            SpeedDistributionKind::Empirical => SpeedDistribution::Empirical {
+                histogram: histogram.ok_or_else(|| missing("speed-histogram"))?,
+            },
+        })
+    }
+}
+
+/// Draws vehicle speeds from a `SpeedDistribution`.
+#[derive(Debug, Clone)]
+pub struct SpeedSampler {
+    /// The prepared distribution.
+    sampler: Sampler,
+    /// The minimum speed in m/s.
+    min: f64,
+    /// The maximum speed in m/s.
+    max: f64,
+}
+
+#[derive(Debug, Clone)]
+enum Sampler {
+    Uniform,
+    Normal(Normal<f64>),
+    LogNormal(LogNormal<f64>),
+    Empirical {
+        bins: Vec<(f64, f64)>,
+        index: WeightedIndex<f64>,
+    },
+}
+
+impl SpeedSampler {
+    /// Prepares a distribution for sampling, truncated to `[min, max]`.
+    pub fn new(distribution: &SpeedDistribution, min: f64, max: f64) -> Result<SpeedSampler> {
+        if !(min > 0.0 && min <= max) {
+            return Err(Error::Config(format!(
+                "Speed bounds must satisfy 0 < min <= max, but are [{}, {}]",
+                min, max
+            )));
+        }
+
+        let sampler = match distribution {
+            SpeedDistribution::Uniform => Sampler::Uniform,
+            SpeedDistribution::Normal { mean, std_dev } => Sampler::Normal(
+                Normal::new(*mean, *std_dev).map_err(|err| Error::Config(err.to_string()))?,
+            ),
+            SpeedDistribution::LogNormal { mean, std_dev } => {
+                if *mean <= 0.0 {
+                    return Err(Error::Config(format!(
+                        "Log-normal mean must be positive, but is {}",
+                        mean
+                    )));
+                }
+                // parameters of the underlying normal distribution
+                let sigma_sq = (1.0 + (std_dev * std_dev) / (mean * mean)).ln();
+                let mu = mean.ln() - sigma_sq / 2.0;
+                Sampler::LogNormal(
+                    LogNormal::new(mu, sigma_sq.sqrt())
+                        .map_err(|err| Error::Config(err.to_string()))?,
+                )
+            }
+            SpeedDistribution::Empirical { histogram } => {
+                let rows = read_histogram(histogram)?;
+                let index = WeightedIndex::new(rows.iter().map(|r| r.2))
+                    .map_err(|err| Error::Config(format!("{}: {}", histogram.display(), err)))?;
+                Sampler::Empirical {
+                    bins: rows.iter().map(|r| (r.0, r.1)).collect(),
+                    index,
+                }
+            }
+        };
+
+        Ok(SpeedSampler { sampler, min, max })
+    }
+
+    /// Draws a speed in m/s.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        let mut speed = f64::NAN;
+        for _ in 0..MAX_TRUNCATION_DRAWS {
+            speed = match &self.sampler {
+                Sampler::Uniform => return random_velocity(rng, self.min, self.max),
+                Sampler::Normal(d) => d.sample(rng),
+                Sampler::LogNormal(d) => d.sample(rng),
+                Sampler::Empirical { bins, index } => {
+                    let (lower, upper) = bins[index.sample(rng)];
+                    if lower < upper {
+                        rng.gen_range(lower..upper)
+                    } else {
+                        lower
+                    }
+                }
+            };
+            if self.min <= speed && speed <= self.max {
+                return speed;
+            }
+        }
+        speed.clamp(self.min, self.max)
+    }
+}
+
+// Reads a histogram of `lower,upper,weight` rows. A header row is skipped.
+fn read_histogram(path: &Path) -> Result<Vec<(f64, f64, f64)>> {
+    let content = std::fs::read_to_string(path)?;
+    let mut rows = Vec::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let values: Vec<f64> = fields.iter().filter_map(|f| f.parse().ok()).collect();
+        if values.len() != 3 || fields.len() != 3 {
+            if number == 0 {
+                // header
+                continue;
+            }
+            return Err(Error::Config(format!(
+                "{}:{}: expected `lower,upper,weight`, found `{}`",
+                path.display(),
+                number + 1,
+                line
+            )));
+        }
+        if values[0] > values[1] {
+            return Err(Error::Config(format!(
+                "{}:{}: lower bound exceeds upper bound",
+                path.display(),
+                number + 1
+            )));
+        }
+        rows.push((values[0], values[1], values[2]));
+    }
+
+    if rows.is_empty() {
+        return Err(Error::Config(format!(
+            "{}: histogram has no bins",
+            path.display()
+        )));
+    }
+    Ok(rows)
+}
+
+// Unit tests for speed distributions
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::temp_path, utils::rng_from_seed};
+
+    #[test]
+    fn test_truncated_normal_stays_in_bounds() {
+        let sampler = SpeedSampler::new(
+            &SpeedDistribution::Normal {
+                mean: 11.0,
+                std_dev: 5.0,
+            },
+            8.5,
+            13.8,
+        )
+        .unwrap();
+        let mut rng = rng_from_seed(Some(1));
+
+        for _ in 0..1000 {
+            let speed = sampler.sample(&mut rng);
+            assert!((8.5..=13.8).contains(&speed));
+        }
+    }
+
+    #[test]
+    fn test_log_normal_matches_mean() {
+        let sampler = SpeedSampler::new(
+            &SpeedDistribution::LogNormal {
+                mean: 10.0,
+                std_dev: 1.0,
+            },
+            0.1,
+            100.0,
+        )
+        .unwrap();
+        let mut rng = rng_from_seed(Some(1));
+
+        let mean = (0..10_000).map(|_| sampler.sample(&mut rng)).sum::<f64>() / 10_000.0;
+        assert!((mean - 10.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_empirical_histogram() {
+        let path = temp_path("histogram.csv");
+        std::fs::write(&path, "lower,upper,weight\n5,6,0\n10,11,3\n").unwrap();
+
+        let sampler = SpeedSampler::new(
+            &SpeedDistribution::Empirical {
+                histogram: path.clone(),
+            },
+            1.0,
+            20.0,
+        )
+        .unwrap();
+        let mut rng = rng_from_seed(Some(1));
+
+        for _ in 0..100 {
+            let speed = sampler.sample(&mut rng);
+            assert!((10.0..11.0).contains(&speed));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_bounds_are_rejected() {
+        assert!(SpeedSampler::new(&SpeedDistribution::Uniform, 10.0, 5.0).is_err());
+        assert!(SpeedSampler::new(&SpeedDistribution::Uniform, 0.0, 5.0).is_err());
+    }
+}
@@ -0,0 +1,1775 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeInclusive,
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::osm_graph::{NodeId, OSMGraph};
+use crate::models::{
+    assignment::LinkTimes,
+    car_following::{IdmParameters, Leader, RoadLimits, TIME_STEP},
+    incident::{Conditions, Schedule},
+    intersection::{Controller, Intersections, StopState, CRITICAL_GAP},
+    lane_change::{mobil_incentive, Accelerations, MobilParameters},
+    observer::{NoObserver, Observer},
+    parking::Parking,
+    rerouting::Router,
+    vehicle::{Moveable, Progress, Vehicle},
+};
+use crate::prelude::*;
+
+type EdgeKey = (NodeId, NodeId);
+type LaneKey = (NodeId, NodeId, usize);
+
+// Distance to the end of an edge in meters from which vehicles choose the lanes of their next turn
+const LANE_CHOICE_DISTANCE: f64 = 150.0;
+
+// Acceleration gain in m/s² granted to lane changes towards the lanes of the next turn
+const TURN_BIAS: f64 = 2.0;
+
+// Smallest change of direction in degrees counted as a turn
+const TURN_ANGLE: f64 = 30.0;
+
+/// The rear of the last vehicle on an edge, shared with the ranks whose vehicles
+/// drive towards that edge.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EdgeTail {
+    /// Start node of the edge.
+    pub from: NodeId,
+    /// End node of the edge.
+    pub to: NodeId,
+    /// Lane of the vehicle.
+    pub lane: usize,
+    /// Position of the vehicle's rear on the edge in meters.
+    pub rear: f64,
+    /// Speed of the vehicle in m/s.
+    pub velocity: f64,
+}
+
+/// Vehicles that stopped driving on the graph during a step.
+#[derive(Debug, Default)]
+pub struct StepOutcome {
+    /// Vehicles that reached their destination or used up their steps.
+    pub finished: Vec<Vehicle>,
+    /// Vehicles whose route continues outside of the graph.
+    pub left: Vec<Vehicle>,
+}
+
+/// Steps all vehicles on a graph together, keeping an ordered queue of vehicles per edge.
+/// Every vehicle follows the vehicle ahead of it on its lane, or the last vehicle on the
+/// next edge of its route, which may be simulated by another rank. On multi-lane edges,
+/// vehicles change lanes to overtake and to line up for their next turn.
+#[derive(Debug)]
+pub struct Traffic<'a> {
+    /// The graph the vehicles drive on.
+    graph: &'a OSMGraph,
+    /// The vehicles on the road.
+    vehicles: Vec<Vehicle>,
+    /// Indices into `vehicles` per edge, from the front to the back of the queue.
+    queues: BTreeMap<EdgeKey, Vec<usize>>,
+    /// The last vehicles per lane on edges simulated by other ranks.
+    remote_tails: HashMap<LaneKey, EdgeTail>,
+    /// The coordinates of the nodes, telling the direction of turns.
+    coordinates: HashMap<NodeId, (f64, f64)>,
+    /// The controllers of the intersections.
+    intersections: Intersections,
+    /// The vehicles waiting at all-way stops.
+    stops: HashMap<NodeId, StopState>,
+    /// The number of steps taken.
+    time: u64,
+    /// The events changing the road network.
+    schedule: Schedule,
+    /// The state of the road network at the current time.
+    conditions: Conditions,
+    /// Plans new routes for informed drivers and detours around closures.
+    router: Option<Router<'a>>,
+    /// The mean speed of the vehicles per edge in m/s.
+    edge_speeds: HashMap<EdgeKey, f64>,
+    /// The parking spaces at the destinations.
+    parking: Parking,
+    /// Vehicles parked at an activity, with the step at which they leave.
+    dwelling: Vec<(u64, Vehicle)>,
+    /// The time spent on every edge, if it is recorded.
+    link_times: Option<LinkTimes>,
+    /// Whether vehicles are stepped on multiple threads.
+    parallel: bool,
+    /// The parameters of the lane-change model.
+    mobil: MobilParameters,
+}
+
+impl<'a> Traffic<'a> {
+    /// Creates an empty road network on the given graph.
+    pub fn new(graph: &'a OSMGraph) -> Traffic<'a> {
+        Traffic {
+            graph,
+            vehicles: Vec::new(),
+            queues: BTreeMap::new(),
+            remote_tails: HashMap::new(),
+            coordinates: graph
+                .input_vertices()
+                .filter(|(_, v)| v.has_coordinates())
+                .map(|(node, v)| (node, (v.x, v.y)))
+                .collect(),
+            intersections: Intersections::default(),
+            stops: HashMap::new(),
+            time: 0,
+            schedule: Schedule::default(),
+            conditions: Conditions::default(),
+            router: None,
+            edge_speeds: HashMap::new(),
+            parking: Parking::default(),
+            dwelling: Vec::new(),
+            link_times: None,
+            parallel: false,
+            mobil: MobilParameters::default(),
+        }
+    }
+
+    /// Applies the events of the given schedule to the road network.
+    pub fn with_schedule(mut self, schedule: Schedule) -> Traffic<'a> {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Lets informed drivers re-plan their routes, and all drivers detour around closures,
+    /// with the given router.
+    pub fn with_router(mut self, router: Router<'a>) -> Traffic<'a> {
+        self.router = Some(router);
+        self
+    }
+
+    /// Controls the given intersections.
+    pub fn with_intersections(mut self, intersections: Intersections) -> Traffic<'a> {
+        self.stops = intersections
+            .stops()
+            .map(|n| (n, StopState::default()))
+            .collect();
+        self.intersections = intersections;
+        self
+    }
+
+    /// Lets arriving vehicles park in the given parking spaces.
+    pub fn with_parking(mut self, parking: Parking) -> Traffic<'a> {
+        self.parking = parking;
+        self
+    }
+
+    /// Lets vehicles change lanes following the given parameters of the lane-change model.
+    pub fn with_lane_change(mut self, params: MobilParameters) -> Traffic<'a> {
+        self.mobil = params;
+        self
+    }
+
+    /// Records the time the vehicles spend on every edge.
+    pub fn with_link_times(mut self) -> Traffic<'a> {
+        self.link_times = Some(LinkTimes::default());
+        self
+    }
+
+    /// Returns the time the vehicles spent on every edge, if it is recorded.
+    pub fn link_times(&self) -> Option<&LinkTimes> {
+        self.link_times.as_ref()
+    }
+
+    /// Steps the vehicles on the rayon thread pool.
+    pub fn with_parallel(mut self, parallel: bool) -> Traffic<'a> {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Sets the number of steps taken, letting the root rank keep the clock of all ranks.
+    pub fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+
+    /// Returns the number of steps taken.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Returns the vehicles on the road.
+    pub fn vehicles(&self) -> &[Vehicle] {
+        &self.vehicles
+    }
+
+    /// Returns the number of vehicles on the road.
+    pub fn len(&self) -> usize {
+        self.vehicles.len() + self.dwelling.len()
+    }
+
+    /// Returns whether no vehicle is on the road or parked at an activity.
+    pub fn is_empty(&self) -> bool {
+        self.vehicles.is_empty() && self.dwelling.is_empty()
+    }
+
+    /// Puts a vehicle on its current edge, behind the last vehicle of its lane.
+    /// Without room, the vehicle waits in front of the edge.
+    pub fn insert(&mut self, mut v: Vehicle) {
+        v.marked_for_deletion = false;
+        v.lane = v.lane.min(self.graph.edge_lanes(v.prev_id, v.next_id) - 1);
+        let queue = self.queues.entry((v.prev_id, v.next_id)).or_default();
+        let tail = queue
+            .iter()
+            .rev()
+            .map(|i| &self.vehicles[*i])
+            .find(|t| t.lane == v.lane);
+        if let Some(tail) = tail {
+            if v.position > tail.rear() {
+                v.position = tail.rear();
+                v.velocity = v.velocity.min(tail.velocity);
+            }
+        }
+        // vehicles on other lanes may be further back
+        let k = queue.partition_point(|i| self.vehicles[*i].position >= v.position);
+        queue.insert(k, self.vehicles.len());
+        self.vehicles.push(v);
+    }
+
+    /// Replaces the last vehicles on the edges simulated by other ranks.
+    pub fn set_remote_tails(&mut self, tails: Vec<EdgeTail>) {
+        self.remote_tails = tails
+            .into_iter()
+            .map(|t| ((t.from, t.to, t.lane), t))
+            .collect();
+    }
+
+    /// Returns the last vehicles per lane on the edges leading into the graph from outside,
+    /// which vehicles of other ranks follow.
+    pub fn boundary_tails(&self) -> Vec<EdgeTail> {
+        self.queues
+            .iter()
+            .filter(|(key, _)| !self.graph.contains_node(key.0))
+            .flat_map(|(key, queue)| {
+                (0..self.graph.edge_lanes(key.0, key.1))
+                    .filter_map(move |lane| self.tail(*key, queue, lane))
+            })
+            .collect()
+    }
+
+    /// Advances all vehicles by one time step.
+    pub fn step(&mut self) -> Result<StepOutcome> {
+        self.step_observed(&mut NoObserver)
+    }
+
+    /// Advances all vehicles by one time step, reporting the vehicles entering and leaving
+    /// edges, arriving and leaving the graph to the observer.
+    /// Fails if the route of a vehicle does not lead on from its edge.
+    pub fn step_observed(&mut self, observer: &mut dyn Observer) -> Result<StepOutcome> {
+        let now = self.time as f64 * TIME_STEP;
+        self.apply_schedule(now);
+        for state in self.stops.values_mut() {
+            state.release(now);
+        }
+        let mut outcome = StepOutcome::default();
+        self.depart(&mut outcome, observer);
+        self.change_lanes();
+        if let Some(router) = &self.router {
+            if self.time > 0 && self.time.is_multiple_of(router.options.interval.max(1)) {
+                for i in 0..self.vehicles.len() {
+                    if self.vehicles[i].informed {
+                        self.reroute(i);
+                    }
+                }
+            }
+        }
+
+        // leaders are determined from the state before the step
+        let mut leaders = vec![None; self.vehicles.len()];
+        let assignments: Vec<(usize, Option<Leader>)> = if self.parallel {
+            self.queues
+                .par_iter()
+                .flat_map_iter(|(_, queue)| {
+                    (0..queue.len()).map(|k| (queue[k], self.leader(queue, k)))
+                })
+                .collect()
+        } else {
+            self.queues
+                .values()
+                .flat_map(|queue| (0..queue.len()).map(|k| (queue[k], self.leader(queue, k))))
+                .collect()
+        };
+        for (i, leader) in assignments {
+            leaders[i] = leader;
+        }
+
+        let limits: Vec<RoadLimits> = self
+            .vehicles
+            .iter()
+            .map(|v| self.conditions.limits(v.prev_id, v.next_id))
+            .collect();
+
+        let graph = self.graph;
+        let progress: Vec<Progress> = if self.parallel {
+            self.vehicles
+                .par_iter_mut()
+                .zip(leaders.par_iter())
+                .zip(limits.par_iter())
+                .map(|((v, leader), limits)| v.step(graph, *leader, *limits))
+                .collect::<Result<_>>()?
+        } else {
+            self.vehicles
+                .iter_mut()
+                .zip(leaders.iter())
+                .zip(limits.iter())
+                .map(|((v, leader), limits)| v.step(graph, *leader, *limits))
+                .collect::<Result<_>>()?
+        };
+
+        let stranded = self.handle_closures(&progress);
+
+        // rebuild the queues: vehicles staying on their edge keep their order,
+        // vehicles entering an edge line up behind them
+        let queues = std::mem::take(&mut self.queues);
+        let mut vehicles: Vec<Option<Vehicle>> = std::mem::take(&mut self.vehicles)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut entered = Vec::new();
+
+        for (key, queue) in &queues {
+            if let Some(times) = &mut self.link_times {
+                times.record_occupancy(*key, queue.len());
+            }
+            for i in queue {
+                if progress[*i] != Progress::Driving || stranded.contains(i) {
+                    continue;
+                }
+                let stays = vehicles[*i]
+                    .take_if(|v| (v.prev_id, v.next_id) == *key && !v.is_out_of_steps());
+                if let Some(v) = stays {
+                    self.insert(v);
+                }
+            }
+        }
+        for (key, queue) in &queues {
+            for i in queue {
+                let v = match vehicles[*i].take() {
+                    Some(v) => v,
+                    None => continue,
+                };
+                if let Some(times) = &mut self.link_times {
+                    times.record_exit(*key);
+                }
+                observer.on_edge_exit(self.time, &v, *key);
+                match progress[*i] {
+                    Progress::Arrived => {
+                        observer.on_arrival(self.time, &v);
+                        if let Some(v) = self.arrive(v, observer) {
+                            outcome.finished.push(v);
+                        }
+                    }
+                    Progress::Left => outcome.left.push(v),
+                    Progress::Driving if v.is_out_of_steps() || stranded.contains(i) => {
+                        outcome.finished.push(v)
+                    }
+                    Progress::Driving => {
+                        observer.on_edge_entry(self.time, &v, (v.prev_id, v.next_id));
+                        entered.push(self.vehicles.len());
+                        self.insert(v);
+                    }
+                }
+            }
+        }
+
+        self.update_stops();
+        self.serve_transit_stops();
+        if self.router.is_some() {
+            self.update_edge_speeds();
+        }
+        // informed drivers entering an edge avoid congestion ahead
+        if let Some(router) = &self.router {
+            let congested: Vec<usize> = entered
+                .into_iter()
+                .filter(|i| {
+                    let v = &self.vehicles[*i];
+                    v.informed
+                        && v.upcoming_node().is_some_and(|next| {
+                            router.is_congested(
+                                (v.next_id, next),
+                                v.speed,
+                                &self.conditions,
+                                &self.edge_speeds,
+                            )
+                        })
+                })
+                .collect();
+            for i in congested {
+                self.reroute(i);
+            }
+        }
+        observer.on_step(self.time);
+        self.time += 1;
+        Ok(outcome)
+    }
+
+    // Applies the events in effect, informed drivers re-plan routes leading over closed edges
+    fn apply_schedule(&mut self, now: f64) {
+        if self.schedule.is_empty() {
+            return;
+        }
+        let conditions = self.schedule.conditions(now);
+        if conditions == self.conditions {
+            return;
+        }
+        log::debug!("Road network changed after {}s", now);
+        self.conditions = conditions;
+        for i in 0..self.vehicles.len() {
+            let v = &self.vehicles[i];
+            if v.informed && self.conditions.blocks(v.remaining_route()) {
+                self.reroute(i);
+            }
+        }
+    }
+
+    // Parks a vehicle at the destination it arrived at, the end of the last edge of its route.
+    // Without a free space, the driver cruises to a neighbouring node and tries again,
+    // until the longest search is over. Returns the vehicle if it stops driving.
+    fn arrive(&mut self, mut v: Vehicle, observer: &mut dyn Observer) -> Option<Vehicle> {
+        let node = v.next_id;
+        if let Some(trip) = &mut v.transit {
+            let terminal = v.path_ids.last().copied().unwrap_or(node);
+            trip.finish(self.time, terminal);
+            return Some(v);
+        }
+        if self.parking.park(node) {
+            if let Some(start) = v.search_start.take() {
+                v.search_steps += v.steps - start;
+            }
+            v.trips += 1;
+            return self.dwell(v);
+        }
+        v.is_parked = false;
+        let start = *v.search_start.get_or_insert(v.steps);
+        if (v.steps - start) as f64 * TIME_STEP >= self.parking.max_search {
+            log::debug!("Vehicle {} found no parking space", v.id);
+            return Some(v);
+        }
+
+        // prefer neighbours with free spaces, and not turning back
+        let successors: Vec<NodeId> = self.graph.successors(node).collect();
+        let ahead: Vec<NodeId> = successors
+            .iter()
+            .copied()
+            .filter(|n| *n != v.prev_id)
+            .collect();
+        let candidates = if ahead.is_empty() {
+            &successors
+        } else {
+            &ahead
+        };
+        let next = match candidates.iter().find(|n| self.parking.has_space(**n)) {
+            Some(next) => *next,
+            None if candidates.is_empty() => return Some(v),
+            None => candidates[v.steps as usize % candidates.len()],
+        };
+
+        log::debug!("Vehicle {} is searching for parking towards {}", v.id, next);
+        v.start_route(vec![node, next]);
+        v.position = 0.0;
+        v.lane = v.lane.min(self.graph.edge_lanes(node, next) - 1);
+        observer.on_edge_entry(self.time, &v, (node, next));
+        self.insert(v);
+        None
+    }
+
+    // Keeps a parked vehicle at its activity for the dwell time, unless the trip chain is over
+    fn dwell(&mut self, mut v: Vehicle) -> Option<Vehicle> {
+        if v.activities.len() < 2 {
+            return Some(v);
+        }
+        let activity = v.activities.remove(0);
+        let departure = self.time + (activity.dwell / TIME_STEP).ceil() as u64;
+        log::debug!(
+            "Vehicle {} stays at {} until step {}",
+            v.id,
+            v.next_id,
+            departure
+        );
+        self.dwelling.push((departure, v));
+        None
+    }
+
+    // Sends the vehicles whose dwell time is over on the trip to their next activity.
+    // Vehicles without a route to it end their trip chain.
+    fn depart(&mut self, outcome: &mut StepOutcome, observer: &mut dyn Observer) {
+        if self.dwelling.is_empty() {
+            return;
+        }
+        let (leaving, staying) = std::mem::take(&mut self.dwelling)
+            .into_iter()
+            .partition(|(departure, _)| *departure <= self.time);
+        self.dwelling = staying;
+
+        for (_, mut v) in leaving {
+            let from = v.next_id;
+            let to = v.activities[0].node;
+            if from == to {
+                if let Some(v) = self.dwell(v) {
+                    outcome.finished.push(v);
+                }
+                continue;
+            }
+            self.parking.leave(from);
+            let unknown = HashMap::new();
+            let observed = if v.informed {
+                &self.edge_speeds
+            } else {
+                &unknown
+            };
+            let route = self.router.as_ref().and_then(|router| {
+                router.route(&v.class, from, to, v.speed, &self.conditions, observed)
+            });
+            match route {
+                Some(route) if route.len() > 1 => {
+                    log::debug!("Vehicle {} leaves {} for {}", v.id, from, to);
+                    v.start_route(route);
+                    v.lane = 0;
+                    v.position = 0.0;
+                    v.velocity = 0.0;
+                    v.is_parked = false;
+                    observer.on_edge_entry(self.time, &v, (v.prev_id, v.next_id));
+                    self.insert(v);
+                }
+                _ => {
+                    log::debug!("Vehicle {} found no route from {} to {}", v.id, from, to);
+                    v.activities.clear();
+                    outcome.finished.push(v);
+                }
+            }
+        }
+    }
+
+    // Drivers halted in front of a closed edge look for a detour, or wait until it reopens.
+    // Returns the vehicles that end their trip because the edge stays closed for good.
+    fn handle_closures(&mut self, progress: &[Progress]) -> HashSet<usize> {
+        let mut stranded = HashSet::new();
+        if self.schedule.is_empty() {
+            return stranded;
+        }
+        let now = self.time as f64 * TIME_STEP;
+        let halt_distance = IdmParameters::default().min_gap + 1.0;
+        let blocked: Vec<usize> = (0..self.vehicles.len())
+            .filter(|i| {
+                let v = &self.vehicles[*i];
+                progress[*i] == Progress::Driving
+                    && v.velocity < 0.1
+                    && v.upcoming_node()
+                        .is_some_and(|next| self.conditions.is_closed(v.next_id, next))
+                    && self
+                        .graph
+                        .edge_length(v.prev_id, v.next_id)
+                        .is_some_and(|length| length - v.position <= halt_distance)
+            })
+            .collect();
+        for i in blocked {
+            self.reroute(i);
+            let v = &self.vehicles[i];
+            if v.upcoming_node()
+                .is_some_and(|next| self.schedule.is_closed_for_good(v.next_id, next, now))
+            {
+                log::debug!("Vehicle {} cannot get past a closed edge", v.id);
+                stranded.insert(i);
+            }
+        }
+        stranded
+    }
+
+    // Re-plans the rest of a vehicle's route. Informed drivers know the current travel times,
+    // the others only the closures they run into.
+    fn reroute(&mut self, i: usize) {
+        let router = match &self.router {
+            Some(router) => router,
+            None => return,
+        };
+        let v = &self.vehicles[i];
+        let destination = match v.path_ids.last() {
+            Some(destination) => *destination,
+            None => return,
+        };
+        let unknown = HashMap::new();
+        let observed = if v.informed {
+            &self.edge_speeds
+        } else {
+            &unknown
+        };
+        if let Some(route) = router.route(
+            &v.class,
+            v.next_id,
+            destination,
+            v.speed,
+            &self.conditions,
+            observed,
+        ) {
+            if self.vehicles[i].replace_route(route) {
+                log::debug!("Vehicle {} re-planned its route", self.vehicles[i].id);
+            }
+        }
+    }
+
+    // Measures the mean speed of the vehicles on every edge. Only the edges of the graph are
+    // measured: with MPI, the router sees the edges of other partitions at their speed limits.
+    fn update_edge_speeds(&mut self) {
+        self.edge_speeds = self
+            .queues
+            .iter()
+            .map(|(key, queue)| {
+                let total: f64 = queue.iter().map(|i| self.vehicles[*i].velocity).sum();
+                (*key, total / queue.len() as f64)
+            })
+            .collect();
+    }
+
+    // Finds the vehicle ahead of the k-th vehicle of a queue
+    fn leader(&self, queue: &[usize], k: usize) -> Option<Leader> {
+        self.lane_leader(queue, k, self.vehicles[queue[k]].lane)
+    }
+
+    // Finds the vehicle ahead of the k-th vehicle of a queue if it drove on the given lane
+    fn lane_leader(&self, queue: &[usize], k: usize, lane: usize) -> Option<Leader> {
+        let v = &self.vehicles[queue[k]];
+        let ahead = queue[..k]
+            .iter()
+            .rev()
+            .map(|i| &self.vehicles[*i])
+            .find(|l| l.lane == lane);
+        if let Some(leader) = ahead {
+            return Some(Leader {
+                gap: leader.rear() - v.position,
+                speed: leader.velocity,
+            });
+        }
+
+        // the first vehicle of a lane stops at the stop line of a closed intersection,
+        // and follows the last vehicle on the lane it enters on the next edge of its route
+        let length = self.graph.edge_length(v.prev_id, v.next_id)?;
+        let stop_line = self.must_stop(v, length).then_some(Leader {
+            gap: length - v.position,
+            speed: 0.0,
+        });
+        let tail = v.upcoming_node().and_then(|next| {
+            let lane = lane.min(self.graph.edge_lanes(v.next_id, next) - 1);
+            let tail = match self.queues.get(&(v.next_id, next)) {
+                Some(queue) => self.tail((v.next_id, next), queue, lane),
+                None => self.remote_tails.get(&(v.next_id, next, lane)).copied(),
+            }?;
+            Some(Leader {
+                gap: length - v.position + tail.rear,
+                speed: tail.velocity,
+            })
+        });
+
+        match (stop_line, tail) {
+            (Some(a), Some(b)) => Some(if a.gap <= b.gap { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    // Finds the position in a queue of the vehicle behind the k-th vehicle on the given lane
+    fn lane_follower(&self, queue: &[usize], k: usize, lane: usize) -> Option<usize> {
+        (k + 1..queue.len()).find(|j| self.vehicles[queue[*j]].lane == lane)
+    }
+
+    // Lets the vehicles on multi-lane edges change to a neighbouring lane, one after the other
+    fn change_lanes(&mut self) {
+        let edges: Vec<(EdgeKey, usize)> = self
+            .queues
+            .keys()
+            .map(|key| (*key, self.graph.edge_lanes(key.0, key.1)))
+            .filter(|(_, lanes)| *lanes > 1)
+            .collect();
+        for (key, lanes) in edges {
+            let queue = self.queues[&key].clone();
+            for k in 0..queue.len() {
+                if let Some(lane) = self.lane_change(&queue, k, lanes) {
+                    self.vehicles[queue[k]].lane = lane;
+                }
+            }
+        }
+    }
+
+    // Picks the neighbouring lane the k-th vehicle of a queue changes to, following MOBIL
+    fn lane_change(&self, queue: &[usize], k: usize, lanes: usize) -> Option<usize> {
+        let params = self.mobil;
+        let v = &self.vehicles[queue[k]];
+        // vehicles waiting in front of the edge keep their lane
+        if v.position < 0.0 {
+            return None;
+        }
+        let length = self.graph.edge_length(v.prev_id, v.next_id)?;
+        let preferred = self.preferred_lanes(v, lanes);
+        let near_end = length - v.position < LANE_CHOICE_DISTANCE;
+        let off_turn = |lane: usize| {
+            (*preferred.start()).saturating_sub(lane) + lane.saturating_sub(*preferred.end())
+        };
+
+        let limits = self.conditions.limits(v.prev_id, v.next_id);
+        let leader = self.lane_leader(queue, k, v.lane);
+        let before = v.desired_acceleration(leader, limits);
+        let old_follower = self.lane_follower(queue, k, v.lane).map(|j| {
+            let f = &self.vehicles[queue[j]];
+            Accelerations {
+                before: f.desired_acceleration(
+                    Some(Leader {
+                        gap: v.rear() - f.position,
+                        speed: v.velocity,
+                    }),
+                    limits,
+                ),
+                after: f.desired_acceleration(
+                    leader.map(|l| Leader {
+                        gap: l.gap + v.position - f.position,
+                        speed: l.speed,
+                    }),
+                    limits,
+                ),
+            }
+        });
+
+        let mut best: Option<(usize, f64)> = None;
+        let targets = [v.lane.checked_sub(1), Some(v.lane + 1)];
+        for lane in targets.into_iter().flatten().filter(|l| *l < lanes) {
+            let mut bias = if lane < v.lane {
+                params.keep_right_bias
+            } else {
+                -params.keep_right_bias
+            };
+            if near_end {
+                match off_turn(lane).cmp(&off_turn(v.lane)) {
+                    std::cmp::Ordering::Less => bias += TURN_BIAS,
+                    std::cmp::Ordering::Greater => continue,
+                    std::cmp::Ordering::Equal => {}
+                }
+            }
+
+            // there has to be room on the target lane
+            let new_leader = self.lane_leader(queue, k, lane);
+            if new_leader.is_some_and(|l| l.gap <= 0.0) {
+                continue;
+            }
+            let new_follower = match self.lane_follower(queue, k, lane) {
+                Some(j) => {
+                    let f = &self.vehicles[queue[j]];
+                    let gap = v.rear() - f.position;
+                    if gap <= 0.0 {
+                        continue;
+                    }
+                    Some(Accelerations {
+                        before: f.desired_acceleration(self.lane_leader(queue, j, lane), limits),
+                        after: f.desired_acceleration(
+                            Some(Leader {
+                                gap,
+                                speed: v.velocity,
+                            }),
+                            limits,
+                        ),
+                    })
+                }
+                None => None,
+            };
+
+            let own = Accelerations {
+                before,
+                after: v.desired_acceleration(new_leader, limits),
+            };
+            if let Some(incentive) = mobil_incentive(&params, own, new_follower, old_follower, bias)
+            {
+                if best.is_none_or(|b| incentive > b.1) {
+                    best = Some((lane, incentive));
+                }
+            }
+        }
+        best.map(|b| b.0)
+    }
+
+    // Returns the lanes suited for the next turn of a vehicle: the rightmost lane for right
+    // turns and before parking, the leftmost lane for left turns, and the lanes continuing
+    // on the next edge otherwise
+    fn preferred_lanes(&self, v: &Vehicle, lanes: usize) -> RangeInclusive<usize> {
+        let next = match v.upcoming_node() {
+            Some(next) => next,
+            None => return 0..=0,
+        };
+        match self.turn_angle(v.prev_id, v.next_id, next) {
+            Some(angle) if angle > TURN_ANGLE => lanes - 1..=lanes - 1,
+            Some(angle) if angle < -TURN_ANGLE => 0..=0,
+            _ => 0..=lanes.min(self.graph.edge_lanes(v.next_id, next)) - 1,
+        }
+    }
+
+    // Returns the change of direction in degrees when driving from `from` over `via` to `to`,
+    // positive for left turns
+    fn turn_angle(&self, from: NodeId, via: NodeId, to: NodeId) -> Option<f64> {
+        let a = self.coordinates.get(&from)?;
+        let b = self.coordinates.get(&via)?;
+        let c = self.coordinates.get(&to)?;
+        let (ux, uy) = (b.0 - a.0, b.1 - a.1);
+        let (wx, wy) = (c.0 - b.0, c.1 - b.1);
+        Some((ux * wy - uy * wx).atan2(ux * wx + uy * wy).to_degrees())
+    }
+
+    // Decides whether the first vehicle of an edge has to stop before the intersection ahead,
+    // which it must not cross while its next edge is closed, or before the stop of its line
+    fn must_stop(&self, v: &Vehicle, length: f64) -> bool {
+        // buses halt at their next stop, but drive on to the terminal, where their trip ends
+        if v.upcoming_node().is_some()
+            && v.transit.as_ref().is_some_and(|t| t.stops_at(v.cursor + 1))
+        {
+            return true;
+        }
+        // vehicles ending their trip here do not enter the intersection
+        let next = match v.upcoming_node() {
+            Some(next) => next,
+            None => return false,
+        };
+        if self.conditions.is_closed(v.next_id, next) {
+            return true;
+        }
+        let controller = match self.intersections.get(v.next_id) {
+            Some(controller) => controller,
+            None => return false,
+        };
+
+        // vehicles too close to stop comfortably keep going
+        let params = IdmParameters::default();
+        let distance = length - v.position;
+        let can_stop = distance >= v.velocity.powi(2) / (2.0 * params.comfortable_deceleration);
+
+        match controller {
+            Controller::Signal { .. } => {
+                can_stop && !controller.is_green(v.prev_id, self.time as f64 * TIME_STEP)
+            }
+            Controller::Yield { priority } => {
+                can_stop
+                    && !priority.contains(&v.prev_id)
+                    && priority.iter().any(|from| {
+                        self.queues
+                            .get(&(*from, v.next_id))
+                            .and_then(|queue| queue.first())
+                            .map(|i| &self.vehicles[*i])
+                            .is_some_and(|other| {
+                                let length =
+                                    self.graph.edge_length(*from, v.next_id).unwrap_or(0.0);
+                                other.upcoming_node().is_some()
+                                    && (length - other.position) / other.velocity.max(0.1)
+                                        < CRITICAL_GAP
+                            })
+                    })
+            }
+            Controller::AllWayStop => !self
+                .stops
+                .get(&v.next_id)
+                .is_some_and(|state| state.released.contains(&v.id)),
+        }
+    }
+
+    // Lines up the vehicles that came to a halt at an all-way stop
+    fn update_stops(&mut self) {
+        let halt_distance = IdmParameters::default().min_gap + 1.0;
+        for (node, state) in self.stops.iter_mut() {
+            let mut approaching = HashSet::new();
+            for from in self.intersections.approaches(*node) {
+                let queue = match self.queues.get(&(*from, *node)) {
+                    Some(queue) => queue,
+                    None => continue,
+                };
+                approaching.extend(queue.iter().map(|i| self.vehicles[*i].id));
+
+                // the first vehicle of every lane
+                let length = self.graph.edge_length(*from, *node).unwrap_or(0.0);
+                for (k, i) in queue.iter().enumerate() {
+                    let front = &self.vehicles[*i];
+                    if queue[..k]
+                        .iter()
+                        .any(|j| self.vehicles[*j].lane == front.lane)
+                    {
+                        continue;
+                    }
+                    if front.velocity < 0.1
+                        && length - front.position <= halt_distance
+                        && !state.released.contains(&front.id)
+                        && !state.waiting.contains(&front.id)
+                    {
+                        state.waiting.push_back(front.id);
+                    }
+                }
+            }
+            state.released.retain(|id| approaching.contains(id));
+            state.waiting.retain(|id| approaching.contains(id));
+        }
+    }
+
+    // Lets the buses that came to a halt at their next stop dwell there, and sends them off
+    // once the dwell time is over
+    fn serve_transit_stops(&mut self) {
+        let halt_distance = IdmParameters::default().min_gap + 1.0;
+        let graph = self.graph;
+        let time = self.time;
+        for v in self.vehicles.iter_mut() {
+            // the stop ends the current edge, which follows the cursor on the route;
+            // the stop at the terminal is served when the bus arrives
+            let index = v.cursor + 1;
+            let terminal = v.upcoming_node().is_none();
+            let trip = match &mut v.transit {
+                Some(trip) if !terminal && trip.stops_at(index) => trip,
+                _ => continue,
+            };
+            let length = graph.edge_length(v.prev_id, v.next_id).unwrap_or(0.0);
+            if v.velocity >= 0.1 || length - v.position > halt_distance {
+                continue;
+            }
+            match trip.dwell_until {
+                None => trip.arrive(time),
+                Some(until) if until <= time => trip.leave(),
+                Some(_) => {}
+            }
+        }
+    }
+
+    // Describes the last vehicle of a queue on the given lane
+    fn tail(&self, key: EdgeKey, queue: &[usize], lane: usize) -> Option<EdgeTail> {
+        let v = queue
+            .iter()
+            .rev()
+            .map(|i| &self.vehicles[*i])
+            .find(|v| v.lane == lane)?;
+        Some(EdgeTail {
+            from: key.0,
+            to: key.1,
+            lane,
+            rear: v.rear(),
+            velocity: v.velocity,
+        })
+    }
+}
+
+// Unit tests for the traffic on a graph
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::Partitioner,
+        fixtures::{edge, load_parking},
+        graph::osm_graph::{GPartition, Osmid},
+        models::{
+            activity::Activity,
+            graph_input::{Edge, Graph, Vertex},
+            handoff::Handoff,
+            incident::{Effect, Event},
+            intersection::{Phase, STOP_HEADWAY},
+            rerouting::ReroutingOptions,
+            transit::{TransitTrip, TripStop},
+            vehicle_builder::VehicleBuilder,
+            vehicle_class::VehicleClass,
+        },
+    };
+
+    // A line 1 -> 2 -> ... -> 10 with 50m edges
+    fn line() -> OSMGraph {
+        let edges = (1..10).map(|i| edge(i, i + 1, 50.0)).collect();
+        let vertices = (1..=10)
+            .map(|i| Vertex {
+                x: i as f64,
+                y: 0.0,
+                osm_id: i,
+                highway: None,
+            })
+            .collect();
+        OSMGraph::new(Graph { vertices, edges }).unwrap()
+    }
+
+    // The line 1 <-> 2 <-> ... <-> 10 with edges in both directions
+    fn two_way_line() -> OSMGraph {
+        let edges = (1..10)
+            .flat_map(|i| [edge(i, i + 1, 50.0), edge(i + 1, i, 50.0)])
+            .collect();
+        let vertices = (1..=10)
+            .map(|i| Vertex {
+                x: i as f64,
+                y: 0.0,
+                osm_id: i,
+                highway: None,
+            })
+            .collect();
+        OSMGraph::new(Graph { vertices, edges }).unwrap()
+    }
+
+    // A vehicle driving the line from 1 to 10
+    fn vehicle(graph: &OSMGraph, speed: f64) -> Vehicle {
+        let route: Vec<NodeId> = (1..=10).map(|i| graph.node(i).unwrap()).collect();
+        VehicleBuilder::new()
+            .with_speed(speed)
+            .with_prev_id(route[0])
+            .with_next_id(route[1])
+            .with_path_ids(route)
+            .build()
+            .unwrap()
+    }
+
+    // Positions of all vehicles along the line, from the front to the back
+    fn positions(traffic: &Traffic) -> Vec<(f64, f64)> {
+        let mut positions: Vec<_> = traffic
+            .vehicles
+            .iter()
+            .map(|v| {
+                let front = (traffic.graph.osm_id(v.prev_id) - 1) as f64 * 50.0 + v.position;
+                (front, front - v.length)
+            })
+            .collect();
+        positions.sort_by(|a, b| b.0.total_cmp(&a.0));
+        positions
+    }
+
+    #[test]
+    fn test_vehicles_never_overlap() {
+        let graph = line();
+        let mut traffic = Traffic::new(&graph);
+        // a fast vehicle starting behind a slow one
+        traffic.insert(vehicle(&graph, 3.0));
+        traffic.insert(vehicle(&graph, 13.0));
+        traffic.insert(vehicle(&graph, 8.0));
+
+        let mut finished = 0;
+        while !traffic.is_empty() {
+            for pair in positions(&traffic).windows(2) {
+                assert!(pair[0].1 >= pair[1].0 - 1e-9, "{:?}", pair);
+            }
+            let outcome = traffic.step().unwrap();
+            assert!(outcome.left.is_empty());
+            finished += outcome.finished.len();
+        }
+        assert_eq!(finished, 3);
+    }
+
+    #[test]
+    fn test_broken_route_fails_the_step() {
+        let graph = line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let mut traffic = Traffic::new(&graph);
+        // the route does not contain the edge the vehicle drives on
+        let mut v = vehicle(&graph, 13.0);
+        v.path_ids = (5..=10).map(n).collect();
+        traffic.insert(v);
+
+        let mut result = Ok(StepOutcome::default());
+        while result.is_ok() && !traffic.is_empty() {
+            result = traffic.step();
+        }
+        assert!(matches!(result, Err(Error::Routing(_))));
+    }
+
+    #[test]
+    fn test_follower_is_slowed_by_leader() {
+        let graph = line();
+        let mut alone = Traffic::new(&graph);
+        alone.insert(vehicle(&graph, 13.0));
+        let mut queued = Traffic::new(&graph);
+        queued.insert(vehicle(&graph, 3.0));
+        queued.insert(vehicle(&graph, 13.0));
+
+        for _ in 0..20 {
+            alone.step().unwrap();
+            queued.step().unwrap();
+        }
+        let fast_alone = alone.vehicles[0].velocity;
+        let fast_queued = queued
+            .vehicles
+            .iter()
+            .map(|v| v.velocity)
+            .fold(0.0, f64::max);
+        assert!(fast_queued < 4.0 && fast_alone > 12.0);
+    }
+
+    #[test]
+    fn test_remote_tail_blocks_edge_exit() {
+        let graph = line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let mut traffic = Traffic::new(&graph);
+        traffic.insert(vehicle(&graph, 13.0));
+        // a standing vehicle at the very start of the next edge, simulated elsewhere
+        traffic.set_remote_tails(vec![EdgeTail {
+            from: n(2),
+            to: n(3),
+            lane: 0,
+            rear: 0.0,
+            velocity: 0.0,
+        }]);
+
+        for _ in 0..60 {
+            traffic.step().unwrap();
+        }
+        let v = &traffic.vehicles[0];
+        assert_eq!((v.prev_id, v.next_id), (n(1), n(2)));
+        assert!(v.position <= 50.0 && v.velocity < 0.1);
+    }
+
+    #[test]
+    fn test_vehicles_cross_partitions() {
+        let graph = line();
+        let parts: Vec<OSMGraph> = (0..2)
+            .map(|i| graph.partition(Partitioner::VerticalStripes, 2, i).unwrap())
+            .collect();
+        // the edge between the partitions is driven by the partition it leads into
+        let crossing = parts[1]
+            .boundary
+            .keys()
+            .copied()
+            .find(|e| parts[0].contains_node(e.0))
+            .unwrap();
+
+        let mut traffic: Vec<Traffic> = parts.iter().map(Traffic::new).collect();
+        traffic[0].insert(vehicle(&graph, 3.0));
+        traffic[0].insert(vehicle(&graph, 13.0));
+
+        // relay vehicles and tails between the partitions, like the root rank does
+        let mut finished = Vec::new();
+        let mut crossing_speeds = Vec::new();
+        for _ in 0..500 {
+            let tails = traffic[1].boundary_tails();
+            traffic[0].set_remote_tails(tails);
+            let first = traffic[0].step().unwrap();
+            let second = traffic[1].step().unwrap();
+            for v in first.left {
+                assert_eq!((v.prev_id, v.next_id), crossing);
+                crossing_speeds.push(v.velocity);
+                traffic[1].insert(v);
+            }
+            assert!(second.left.is_empty());
+            finished.extend(first.finished);
+            finished.extend(second.finished);
+            if traffic.iter().all(|t| t.is_empty()) {
+                break;
+            }
+        }
+
+        assert_eq!(finished.len(), 2);
+        assert!(finished.iter().all(|v| v.is_parked));
+        // both vehicles keep their speed across the boundary, the fast one stuck behind the slow one
+        assert!(crossing_speeds.iter().all(|s| *s > 0.0 && *s < 4.0));
+    }
+
+    #[test]
+    fn test_route_revisiting_nodes_is_driven_to_the_end() {
+        let graph = two_way_line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let mut traffic = Traffic::new(&graph);
+        // out to node 6 and back, passing nodes 2 to 5 twice
+        let route = [2, 3, 4, 5, 6, 5, 4, 3, 2, 1].map(n).to_vec();
+        let v = VehicleBuilder::new()
+            .with_speed(13.0)
+            .with_prev_id(n(2))
+            .with_next_id(n(3))
+            .with_path_ids(route.clone())
+            .build()
+            .unwrap();
+        traffic.insert(v);
+
+        let mut finished = Vec::new();
+        for _ in 0..200 {
+            finished.extend(traffic.step().unwrap().finished);
+            if traffic.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(finished.len(), 1);
+        let v = &finished[0];
+        assert!(v.is_parked);
+        // the vehicle ends at the last node of its route, not at an earlier visit of a node
+        assert_eq!(v.next_id, *route.last().unwrap());
+        assert_eq!(v.prev_id, route[route.len() - 2]);
+        assert_eq!(v.cursor, route.len() - 2);
+        // nine edges of 50m, the vehicle stops at the end of the last one
+        assert!((v.distance - 450.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_route_crosses_partitions_several_times() {
+        let graph = two_way_line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let parts: Vec<OSMGraph> = (0..2)
+            .map(|i| graph.partition(Partitioner::VerticalStripes, 2, i).unwrap())
+            .collect();
+        let mut traffic: Vec<Traffic> = parts.iter().map(Traffic::new).collect();
+        // over into the second partition, back, and over again
+        let v = VehicleBuilder::new()
+            .with_speed(13.0)
+            .with_prev_id(n(3))
+            .with_next_id(n(4))
+            .with_path_ids([3, 4, 5, 6, 7, 6, 5, 4, 5, 6, 7, 8].map(n).to_vec())
+            .build()
+            .unwrap();
+        traffic[0].insert(v);
+
+        // hand the vehicle over between the partitions, like the root rank does
+        let mut crossings = Vec::new();
+        let mut finished = Vec::new();
+        for _ in 0..500 {
+            for i in 0..2 {
+                let outcome = traffic[i].step().unwrap();
+                let handoff: Handoff = outcome.left.into_iter().collect();
+                for v in handoff.into_vehicles().unwrap() {
+                    crossings.push((v.prev_id, v.next_id));
+                    traffic[1 - i].insert(v);
+                }
+                finished.extend(outcome.finished);
+            }
+            if traffic.iter().all(|t| t.is_empty()) {
+                break;
+            }
+        }
+        assert_eq!(
+            crossings,
+            [(5, 6), (6, 5), (5, 6)].map(|(a, b)| (n(a), n(b)))
+        );
+        assert_eq!(finished.len(), 1);
+        assert_eq!((finished[0].prev_id, finished[0].next_id), (n(7), n(8)));
+    }
+
+    #[test]
+    fn test_vehicle_waits_at_red_signal() {
+        let graph = line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        // node 3 is red for vehicles from 2 during the first 40 seconds
+        let mut intersections = Intersections::default();
+        intersections.insert(
+            &graph,
+            n(3),
+            Controller::Signal {
+                phases: vec![
+                    Phase {
+                        approaches: vec![n(2)],
+                        green: 20.0,
+                    },
+                    Phase {
+                        approaches: Vec::new(),
+                        green: 40.0,
+                    },
+                ],
+                clearance: 0.0,
+                offset: 20.0,
+            },
+        );
+        let mut traffic = Traffic::new(&graph).with_intersections(intersections);
+        traffic.insert(vehicle(&graph, 13.0));
+
+        for _ in 0..40 {
+            traffic.step().unwrap();
+        }
+        let v = &traffic.vehicles[0];
+        assert_eq!((v.prev_id, v.next_id), (n(2), n(3)));
+        assert!(v.velocity < 0.1 && v.position > 40.0);
+
+        for _ in 0..20 {
+            traffic.step().unwrap();
+        }
+        assert!(traffic.vehicles[0].prev_id >= n(3));
+    }
+
+    #[test]
+    fn test_all_way_stop_releases_one_vehicle_at_a_time() {
+        // two approaches 1 -> 3 and 2 -> 3 meeting at an all-way stop
+        let mut edges = vec![edge(1, 3, 50.0), edge(2, 3, 50.0)];
+        edges.extend((3..6).map(|i| edge(i, i + 1, 50.0)));
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges,
+        })
+        .unwrap();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let mut intersections = Intersections::default();
+        intersections.insert(&graph, n(3), Controller::AllWayStop);
+
+        let mut traffic = Traffic::new(&graph).with_intersections(intersections);
+        for start in [1, 2] {
+            traffic.insert(
+                VehicleBuilder::new()
+                    .with_speed(13.0)
+                    .with_prev_id(n(start))
+                    .with_next_id(n(3))
+                    .with_path_ids([start, 3, 4, 5, 6].map(n).to_vec())
+                    .build()
+                    .unwrap(),
+            );
+        }
+
+        // the time each vehicle passed the stop and its lowest speed before
+        let mut passed = HashMap::new();
+        let mut slowest = HashMap::new();
+        for time in 0..120 {
+            for v in &traffic.vehicles {
+                if v.next_id == n(3) {
+                    let speed = slowest.entry(v.id).or_insert(f64::MAX);
+                    *speed = v.velocity.min(*speed);
+                } else {
+                    passed.entry(v.id).or_insert(time);
+                }
+            }
+            traffic.step().unwrap();
+        }
+
+        assert_eq!(passed.len(), 2);
+        assert!(slowest.values().all(|s| *s < 0.1));
+        let times: Vec<f64> = passed.values().map(|t| *t as f64).collect();
+        assert!((times[0] - times[1]).abs() >= STOP_HEADWAY);
+    }
+
+    // A graph with edges of the given number of lanes and vertices at the given coordinates
+    fn lanes(edges: &[(Osmid, Osmid)], lanes: &str, vertices: &[(Osmid, f64, f64)]) -> OSMGraph {
+        let edges = edges
+            .iter()
+            .map(|(from, to)| Edge {
+                lanes: Some(String::from(lanes)),
+                ..edge(*from, *to, 50.0)
+            })
+            .collect();
+        let vertices = vertices
+            .iter()
+            .map(|(osm_id, x, y)| Vertex {
+                x: *x,
+                y: *y,
+                osm_id: *osm_id,
+                highway: None,
+            })
+            .collect();
+        OSMGraph::new(Graph { vertices, edges }).unwrap()
+    }
+
+    #[test]
+    fn test_fast_vehicle_overtakes_on_two_lanes() {
+        let edges: Vec<_> = (1..10).map(|i| (i, i + 1)).collect();
+        let vertices: Vec<_> = (1..=10).map(|i| (i, i as f64, 0.0)).collect();
+        let graph = lanes(&edges, "2", &vertices);
+        let mut traffic = Traffic::new(&graph);
+        traffic.insert(vehicle(&graph, 3.0));
+        traffic.insert(vehicle(&graph, 13.0));
+
+        let mut finished = Vec::new();
+        let mut overtaking = false;
+        while !traffic.is_empty() {
+            overtaking |= traffic.vehicles.iter().any(|v| v.lane == 1);
+            finished.extend(traffic.step().unwrap().finished);
+        }
+        assert!(overtaking);
+        let speeds: Vec<f64> = finished.iter().map(|v| v.speed).collect();
+        assert_eq!(speeds, vec![13.0, 3.0]);
+        // back on the right lane before parking
+        assert!(finished.iter().all(|v| v.lane == 0));
+    }
+
+    #[test]
+    fn test_lane_change_threshold_keeps_vehicles_on_their_lane() {
+        let edges: Vec<_> = (1..10).map(|i| (i, i + 1)).collect();
+        let vertices: Vec<_> = (1..=10).map(|i| (i, i as f64, 0.0)).collect();
+        let graph = lanes(&edges, "2", &vertices);
+        let reluctant = MobilParameters {
+            threshold: f64::INFINITY,
+            ..MobilParameters::default()
+        };
+        let mut traffic = Traffic::new(&graph).with_lane_change(reluctant);
+        traffic.insert(vehicle(&graph, 3.0));
+        traffic.insert(vehicle(&graph, 13.0));
+
+        let mut finished = Vec::new();
+        while !traffic.is_empty() {
+            assert!(traffic.vehicles.iter().all(|v| v.lane == 0));
+            finished.extend(traffic.step().unwrap().finished);
+        }
+        // the fast vehicle stays behind the slow one
+        let speeds: Vec<f64> = finished.iter().map(|v| v.speed).collect();
+        assert_eq!(speeds, vec![3.0, 13.0]);
+    }
+
+    #[test]
+    fn test_vehicles_line_up_for_left_turn() {
+        // two-lane road along the x-axis, turning left at node 3
+        let graph = lanes(
+            &[(1, 2), (2, 3), (3, 4), (4, 5), (5, 6)],
+            "2",
+            &[
+                (1, 0.0, 0.0),
+                (2, 1.0, 0.0),
+                (3, 2.0, 0.0),
+                (4, 2.0, 1.0),
+                (5, 2.0, 2.0),
+                (6, 2.0, 3.0),
+            ],
+        );
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let mut traffic = Traffic::new(&graph);
+        traffic.insert(
+            VehicleBuilder::new()
+                .with_speed(13.0)
+                .with_prev_id(n(1))
+                .with_next_id(n(2))
+                .with_path_ids((1..=6).map(n).collect())
+                .build()
+                .unwrap(),
+        );
+
+        let mut lane_before_turn = None;
+        while !traffic.is_empty() {
+            for v in &traffic.vehicles {
+                if v.next_id == n(3) {
+                    lane_before_turn = Some(v.lane);
+                }
+            }
+            traffic.step().unwrap();
+        }
+        assert_eq!(lane_before_turn, Some(1));
+    }
+
+    #[test]
+    fn test_informed_driver_avoids_congestion() {
+        // two equally long routes from 1 to 4, over 2 and over 3
+        let edges = [
+            (8, 9),
+            (9, 1),
+            (1, 2),
+            (2, 4),
+            (1, 3),
+            (3, 4),
+            (4, 5),
+            (5, 6),
+        ];
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges: edges
+                .iter()
+                .map(|(from, to)| edge(*from, *to, 50.0))
+                .collect(),
+        })
+        .unwrap();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let car = VehicleClass::car();
+
+        let route = |informed: bool| {
+            let options = ReroutingOptions {
+                interval: 1000,
+                congestion_threshold: 2.0,
+            };
+            let mut traffic =
+                Traffic::new(&graph).with_router(Router::new(&graph, [&car], options));
+            // a crawling vehicle on the edge 1 -> 2
+            traffic.insert(
+                VehicleBuilder::new()
+                    .with_speed(0.5)
+                    .with_prev_id(n(1))
+                    .with_next_id(n(2))
+                    .with_position(10.0)
+                    .with_path_ids([1, 2, 4, 5, 6].map(n).to_vec())
+                    .build()
+                    .unwrap(),
+            );
+            traffic.insert(
+                VehicleBuilder::new()
+                    .with_speed(13.0)
+                    .with_informed(informed)
+                    .with_prev_id(n(8))
+                    .with_next_id(n(9))
+                    .with_path_ids([8, 9, 1, 2, 4, 5, 6].map(n).to_vec())
+                    .build()
+                    .unwrap(),
+            );
+            for _ in 0..15 {
+                traffic.step().unwrap();
+            }
+            let v = traffic.vehicles.iter().find(|v| v.speed == 13.0).unwrap();
+            (v.path_ids.clone(), v.reroutes)
+        };
+
+        assert_eq!(route(true), ([9, 1, 3, 4, 5, 6].map(n).to_vec(), 1));
+        assert_eq!(route(false), ([8, 9, 1, 2, 4, 5, 6].map(n).to_vec(), 0));
+    }
+
+    #[test]
+    fn test_vehicle_cruises_to_free_parking() {
+        let graph = two_way_line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let drive = |max_search: f64| {
+            // no spaces at the destination 10, unlimited spaces elsewhere
+            let parking = load_parking(
+                &graph,
+                &format!(
+                    "max_search = {:.1}\n[[zone]]\nnodes = [10]\ncapacity = 0\n",
+                    max_search
+                ),
+            );
+
+            let mut traffic = Traffic::new(&graph).with_parking(parking);
+            traffic.insert(vehicle(&graph, 13.0));
+            let mut finished = Vec::new();
+            while !traffic.is_empty() {
+                finished.extend(traffic.step().unwrap().finished);
+            }
+            finished.pop().unwrap()
+        };
+
+        let v = drive(600.0);
+        assert!(v.is_parked);
+        // the driver turns back from the dead end to the free spaces at 9
+        assert_eq!((v.prev_id, v.next_id), (n(10), n(9)));
+        assert!(v.parking_search() > 0);
+
+        let v = drive(0.0);
+        assert!(!v.is_parked);
+        assert_eq!(v.next_id, n(10));
+        assert_eq!(v.parking_search(), 0);
+        assert!(v.search_start.is_some());
+    }
+
+    #[test]
+    fn test_vehicle_drives_round_trip() {
+        // a ring 1 -> 2 -> ... -> 6 -> 1
+        let mut edges: Vec<Edge> = (1..6).map(|i| edge(i, i + 1, 50.0)).collect();
+        edges.push(edge(6, 1, 50.0));
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges,
+        })
+        .unwrap();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let car = VehicleClass::car();
+        let options = ReroutingOptions {
+            interval: 1000,
+            congestion_threshold: 2.0,
+        };
+        let mut traffic = Traffic::new(&graph).with_router(Router::new(&graph, [&car], options));
+        traffic.insert(
+            VehicleBuilder::new()
+                .with_speed(13.0)
+                .with_prev_id(n(1))
+                .with_next_id(n(2))
+                .with_path_ids([1, 2, 3, 4, 5].map(n).to_vec())
+                .with_activities(vec![
+                    Activity {
+                        node: n(5),
+                        dwell: 30.0,
+                    },
+                    Activity {
+                        node: n(1),
+                        dwell: 0.0,
+                    },
+                ])
+                .build()
+                .unwrap(),
+        );
+
+        let mut finished = Vec::new();
+        let mut dwelling = 0;
+        let mut dwell_nodes = HashSet::new();
+        while !traffic.is_empty() {
+            finished.extend(traffic.step().unwrap().finished);
+            if traffic.vehicles.is_empty() && !traffic.is_empty() {
+                dwelling += 1;
+            }
+            dwell_nodes.extend(traffic.dwelling.iter().map(|(_, v)| v.next_id));
+        }
+        // the vehicle stays at its first activity, then drives home
+        assert!((29..=31).contains(&dwelling));
+        assert_eq!(dwell_nodes, HashSet::from([n(5)]));
+        assert_eq!(finished.len(), 1);
+        let v = &finished[0];
+        assert_eq!(v.trips, 2);
+        assert_eq!(v.path_ids, [5, 6, 1].map(n));
+        assert!(v.is_parked);
+        assert_eq!(v.next_id, n(1));
+    }
+
+    #[test]
+    fn test_bus_dwells_at_stops() {
+        let graph = line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        // the route starts at node 1, so node i is at position i - 1
+        let stop = |osm_id: Osmid, time| TripStop {
+            node: n(osm_id),
+            time,
+            index: osm_id - 1,
+        };
+        let bus = VehicleClass::bus();
+        let mut traffic = Traffic::new(&graph);
+        traffic.insert(
+            VehicleBuilder::new()
+                .with_class(&bus)
+                .with_speed(13.0)
+                .with_prev_id(n(1))
+                .with_next_id(n(2))
+                .with_path_ids((1..=10).map(n).collect())
+                .with_transit(TransitTrip {
+                    line: String::from("1"),
+                    departure: 0.0,
+                    stops: vec![stop(5, 20.0), stop(10, 60.0)],
+                    dwell: 20.0,
+                    dwell_until: None,
+                    arrivals: Vec::new(),
+                })
+                .build()
+                .unwrap(),
+        );
+
+        let mut finished = Vec::new();
+        let mut halted = 0;
+        while !traffic.is_empty() {
+            finished.extend(traffic.step().unwrap().finished);
+            if traffic
+                .vehicles
+                .first()
+                .is_some_and(|v| v.next_id == n(5) && v.velocity < 0.1)
+            {
+                halted += 1;
+            }
+        }
+        assert!(halted >= 20);
+        let trip = finished[0].transit.as_ref().unwrap();
+        assert!(trip.stops.is_empty());
+        let nodes: Vec<_> = trip.arrivals.iter().map(|a| a.node).collect();
+        assert_eq!(nodes, [5, 10].map(n));
+        assert!(trip.arrivals[0].delay() > 0.0 && trip.arrivals[0].delay() < 30.0);
+        assert!(trip.arrivals[1].actual >= trip.arrivals[0].actual + 20.0);
+        // the terminal is reached at the end of the route, in the last step of the bus
+        let bus = &finished[0];
+        assert_eq!(bus.next_id, n(10));
+        assert!((bus.distance - 450.0).abs() < 1e-9);
+        assert_eq!(trip.arrivals[1].actual, (bus.steps - 1) as f64 * TIME_STEP);
+    }
+
+    #[test]
+    fn test_bus_serves_stop_on_the_right_pass() {
+        let graph = two_way_line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let bus = VehicleClass::bus();
+        let mut traffic = Traffic::new(&graph);
+        // out to node 5 and back, stopping at node 3 on the way back only
+        traffic.insert(
+            VehicleBuilder::new()
+                .with_class(&bus)
+                .with_speed(13.0)
+                .with_prev_id(n(1))
+                .with_next_id(n(2))
+                .with_path_ids([1, 2, 3, 4, 5, 4, 3, 2, 1].map(n).to_vec())
+                .with_transit(TransitTrip {
+                    line: String::from("1"),
+                    departure: 0.0,
+                    stops: vec![TripStop {
+                        node: n(3),
+                        time: 60.0,
+                        index: 6,
+                    }],
+                    dwell: 20.0,
+                    dwell_until: None,
+                    arrivals: Vec::new(),
+                })
+                .build()
+                .unwrap(),
+        );
+
+        let mut finished = Vec::new();
+        let mut halts = HashSet::new();
+        while !traffic.is_empty() {
+            finished.extend(traffic.step().unwrap().finished);
+            for v in &traffic.vehicles {
+                if v.next_id == n(3) && v.velocity < 0.1 {
+                    halts.insert(v.cursor);
+                }
+            }
+        }
+        assert_eq!(halts, HashSet::from([5]));
+        let trip = finished[0].transit.as_ref().unwrap();
+        assert!(trip.stops.is_empty());
+        assert_eq!(trip.arrivals.len(), 1);
+    }
+
+    fn closure(from: Osmid, to: Osmid, start: f64, end: Option<f64>) -> Event<Osmid> {
+        Event {
+            start,
+            end,
+            effect: Effect::CloseEdge { from, to },
+        }
+    }
+
+    #[test]
+    fn test_vehicle_waits_at_closed_edge() {
+        let graph = line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let schedule = Schedule::load(&graph, None, vec![closure(3, 4, 0.0, Some(40.0))]).unwrap();
+        let mut traffic = Traffic::new(&graph).with_schedule(schedule);
+        traffic.insert(vehicle(&graph, 13.0));
+
+        for _ in 0..30 {
+            traffic.step().unwrap();
+        }
+        let v = &traffic.vehicles[0];
+        assert_eq!((v.prev_id, v.next_id), (n(2), n(3)));
+        assert!(v.velocity < 0.1 && v.position < 50.0);
+
+        for _ in 30..60 {
+            traffic.step().unwrap();
+        }
+        assert!(traffic.vehicles[0].prev_id >= n(3));
+    }
+
+    #[test]
+    fn test_vehicle_gives_up_at_permanent_closure() {
+        let graph = line();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let schedule = Schedule::load(&graph, None, vec![closure(3, 4, 0.0, None)]).unwrap();
+        let mut traffic = Traffic::new(&graph).with_schedule(schedule);
+        traffic.insert(vehicle(&graph, 13.0));
+
+        let mut finished = Vec::new();
+        for _ in 0..60 {
+            finished.extend(traffic.step().unwrap().finished);
+        }
+        assert!(traffic.is_empty());
+        assert_eq!(finished.len(), 1);
+        assert!(!finished[0].is_parked);
+        assert_eq!(finished[0].next_id, n(3));
+    }
+
+    #[test]
+    fn test_vehicle_detours_around_closed_edge() {
+        // a short route from 1 to 4 over 2 and a long one over 3
+        let mut edges: Vec<Edge> = [(8, 9), (9, 1), (1, 2), (2, 4), (4, 5), (5, 6)]
+            .iter()
+            .map(|(from, to)| edge(*from, *to, 50.0))
+            .collect();
+        edges.push(edge(1, 3, 80.0));
+        edges.push(edge(3, 4, 80.0));
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges,
+        })
+        .unwrap();
+        let n = |osm_id| graph.node(osm_id).unwrap();
+        let car = VehicleClass::car();
+        let options = ReroutingOptions {
+            interval: 1000,
+            congestion_threshold: 2.0,
+        };
+        let schedule = Schedule::load(&graph, None, vec![closure(1, 2, 0.0, None)]).unwrap();
+        let mut traffic = Traffic::new(&graph)
+            .with_schedule(schedule)
+            .with_router(Router::new(&graph, [&car], options));
+        traffic.insert(
+            VehicleBuilder::new()
+                .with_speed(13.0)
+                .with_prev_id(n(8))
+                .with_next_id(n(9))
+                .with_path_ids([8, 9, 1, 2, 4, 5, 6].map(n).to_vec())
+                .build()
+                .unwrap(),
+        );
+
+        // the vehicle halts before the closed edge, then turns onto the detour
+        for _ in 0..40 {
+            traffic.step().unwrap();
+            if traffic.vehicles[0].next_id == n(3) {
+                break;
+            }
+        }
+        let v = &traffic.vehicles[0];
+        assert_eq!((v.prev_id, v.next_id), (n(1), n(3)));
+        assert_eq!(v.path_ids, [9, 1, 3, 4, 5, 6].map(n));
+        assert_eq!(v.reroutes, 1);
+    }
+}
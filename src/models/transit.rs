@@ -0,0 +1,594 @@
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::read_structured,
+    graph::osm_graph::{NodeId, OSMGraph, Osmid},
+    models::{
+        car_following::TIME_STEP, vehicle::Vehicle, vehicle_builder::VehicleBuilder,
+        vehicle_class::VehicleClass,
+    },
+    prelude::*,
+};
+
+// Seconds a bus may arrive before its scheduled time and still count as on time
+const EARLY_TOLERANCE: f64 = 60.0;
+
+// Seconds a bus may arrive after its scheduled time and still count as on time
+const LATE_TOLERANCE: f64 = 300.0;
+
+// Seconds a bus stays at a stop, unless the feed or the line says otherwise
+const DEFAULT_DWELL: f64 = 20.0;
+
+/// A stop of a transit line. Transit feeds give its node by the OSM id, `ScheduledStop<Osmid>`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledStop<N = NodeId> {
+    /// The node of the stop.
+    pub node: N,
+    /// Scheduled arrival in seconds after the departure of the trip.
+    pub time: f64,
+}
+
+/// A bus line with a fixed route.
+/// Transit feeds give the nodes of the route and the stops by their OSM ids, `Line<Osmid>`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Line<N = NodeId> {
+    /// The name of the line.
+    pub name: String,
+    /// The vehicle class serving the line, a bus by default.
+    #[serde(default)]
+    pub class: Option<String>,
+    /// The nodes the buses drive along.
+    pub route: Vec<N>,
+    /// The stops along the route, in the order they are served.
+    #[serde(default)]
+    pub stop: Vec<ScheduledStop<N>>,
+    /// Seconds a bus stays at every stop.
+    #[serde(default)]
+    pub dwell: Option<f64>,
+    /// Scheduled departures in seconds, as a timetable.
+    #[serde(default)]
+    pub departures: Vec<f64>,
+    /// Seconds between two departures, as an alternative to the timetable.
+    #[serde(default)]
+    pub headway: Option<f64>,
+    /// First departure in seconds when running at a headway.
+    #[serde(default)]
+    pub first_departure: f64,
+    /// Last departure in seconds when running at a headway.
+    #[serde(default)]
+    pub last_departure: Option<f64>,
+}
+
+impl Line<Osmid> {
+    // Replaces the OSM ids of the route and the stops by the indices of their nodes
+    fn indexed(self, graph: &OSMGraph) -> Result<Line> {
+        let index = |osm_id: Osmid| {
+            graph.node(osm_id).ok_or_else(|| {
+                Error::Config(format!(
+                    "Line {}: node {} is not part of the graph",
+                    self.name, osm_id
+                ))
+            })
+        };
+        let route = self
+            .route
+            .iter()
+            .map(|n| index(*n))
+            .collect::<Result<_>>()?;
+        let stop = self
+            .stop
+            .iter()
+            .map(|s| {
+                Ok(ScheduledStop {
+                    node: index(s.node)?,
+                    time: s.time,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Line {
+            name: self.name,
+            class: self.class,
+            route,
+            stop,
+            dwell: self.dwell,
+            departures: self.departures,
+            headway: self.headway,
+            first_departure: self.first_departure,
+            last_departure: self.last_departure,
+        })
+    }
+}
+
+// Contents of a transit feed
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransitFile {
+    #[serde(default)]
+    dwell: Option<f64>,
+    #[serde(default)]
+    line: Vec<Line<Osmid>>,
+}
+
+/// A stop a bus has yet to serve on its trip.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TripStop {
+    /// The node of the stop.
+    pub node: NodeId,
+    /// Scheduled arrival in seconds.
+    pub time: f64,
+    /// The position of the node in the route of the bus, so that routes passing the node
+    /// more than once stop on the right pass.
+    pub index: usize,
+}
+
+/// The arrival of a bus at a stop.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StopArrival {
+    /// The node of the stop.
+    pub node: NodeId,
+    /// Scheduled arrival in seconds.
+    pub scheduled: f64,
+    /// Actual arrival in seconds.
+    pub actual: f64,
+}
+
+impl StopArrival {
+    /// Returns the delay in seconds, negative if the bus was early.
+    pub fn delay(&self) -> f64 {
+        self.actual - self.scheduled
+    }
+}
+
+/// A trip of a transit line, carried by the bus serving it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransitTrip {
+    /// The name of the line.
+    pub line: String,
+    /// Scheduled departure in seconds.
+    pub departure: f64,
+    /// The stops not served yet.
+    pub stops: Vec<TripStop>,
+    /// Seconds the bus stays at every stop.
+    pub dwell: f64,
+    /// The step at which the bus leaves the stop it is halted at.
+    pub dwell_until: Option<u64>,
+    /// The arrivals at the stops served so far.
+    pub arrivals: Vec<StopArrival>,
+}
+
+impl TransitTrip {
+    /// Returns the node of the next stop to serve.
+    pub fn next_stop(&self) -> Option<NodeId> {
+        self.stops.first().map(|s| s.node)
+    }
+
+    /// Returns whether the next stop to serve is the node at the given position of the route.
+    pub fn stops_at(&self, index: usize) -> bool {
+        self.stops.first().is_some_and(|s| s.index == index)
+    }
+
+    /// Shifts the positions of the stops left to a route whose first `start` nodes were cut off.
+    pub fn cut_route(&mut self, start: usize) {
+        for stop in &mut self.stops {
+            stop.index = stop.index.saturating_sub(start);
+        }
+    }
+
+    /// Finds the stops left on a new route of the bus, which starts at the node the bus
+    /// just passed. Stops missing from the new route can no longer be served.
+    pub fn follow_route(&mut self, route: &[NodeId]) {
+        let mut index = 1;
+        for stop in &mut self.stops {
+            match route
+                .get(index..)
+                .and_then(|r| r.iter().position(|n| *n == stop.node))
+            {
+                Some(k) => index += k,
+                None => index = route.len(),
+            }
+            stop.index = index;
+        }
+    }
+
+    /// Records the arrival at the next stop and starts the dwell time.
+    pub fn arrive(&mut self, time: u64) {
+        let stop = match self.stops.first() {
+            Some(stop) => *stop,
+            None => return,
+        };
+        self.arrivals.push(StopArrival {
+            node: stop.node,
+            scheduled: stop.time,
+            actual: time as f64 * TIME_STEP,
+        });
+        self.dwell_until = Some(time + (self.dwell / TIME_STEP).ceil() as u64);
+    }
+
+    /// Leaves the stop the bus is halted at.
+    pub fn leave(&mut self) {
+        if !self.stops.is_empty() {
+            self.stops.remove(0);
+        }
+        self.dwell_until = None;
+    }
+
+    /// Ends the trip at the given terminal, serving the stops left there.
+    /// All other stops left were missed.
+    pub fn finish(&mut self, time: u64, terminal: NodeId) {
+        let (served, missed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.stops)
+            .into_iter()
+            .partition(|s| s.node == terminal);
+        self.stops = missed;
+        self.arrivals
+            .extend(served.into_iter().map(|s| StopArrival {
+                node: s.node,
+                scheduled: s.time,
+                actual: time as f64 * TIME_STEP,
+            }));
+        self.dwell_until = None;
+    }
+}
+
+/// The transit lines and their timetables, spawning buses at their scheduled departures.
+#[derive(Debug, Clone, Default)]
+pub struct TransitFeed {
+    /// The lines with the class serving them, the dwell time at their stops
+    /// and the positions of their stops in the route.
+    lines: Vec<(Line, VehicleClass, f64, Vec<usize>)>,
+    /// The step of every departure and the line departing, sorted by step.
+    departures: Vec<(u64, usize)>,
+}
+
+impl TransitFeed {
+    /// Reads the transit lines from a TOML, YAML or JSON file.
+    /// Classes serving a line are looked up among `classes`, then among the built-in ones.
+    pub fn load(
+        graph: &OSMGraph,
+        file: Option<&Path>,
+        classes: &[VehicleClass],
+    ) -> Result<TransitFeed> {
+        let path = match file {
+            Some(path) => path,
+            None => return Ok(TransitFeed::default()),
+        };
+        let parsed: TransitFile = read_structured(path)?;
+        let lines = parsed
+            .line
+            .into_iter()
+            .map(|line| line.indexed(graph))
+            .collect::<Result<Vec<Line>>>()
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))?;
+        TransitFeed::new(graph, lines, parsed.dwell.unwrap_or(DEFAULT_DWELL), classes)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Creates a feed from the given lines, checking their routes, stops and timetables.
+    pub fn new(
+        graph: &OSMGraph,
+        lines: Vec<Line>,
+        dwell: f64,
+        classes: &[VehicleClass],
+    ) -> Result<TransitFeed> {
+        let mut feed = TransitFeed::default();
+        for line in lines {
+            let class_name = line.class.as_deref().unwrap_or("bus");
+            let class = match classes.iter().find(|c| c.name == class_name) {
+                Some(class) => class.clone(),
+                None => VehicleClass::builtin(class_name).ok_or_else(|| {
+                    Error::Config(format!(
+                        "Line {}: unknown vehicle class {}",
+                        line.name, class_name
+                    ))
+                })?,
+            };
+            if line.route.len() < 2 {
+                return Err(Error::Config(format!(
+                    "Line {}: routes need at least two nodes",
+                    line.name
+                )));
+            }
+            if let Some(w) = line
+                .route
+                .windows(2)
+                .find(|w| graph.edge_length(w[0], w[1]).is_none())
+            {
+                return Err(Error::Config(format!(
+                    "Line {}: edge {} -> {} is not part of the graph",
+                    line.name,
+                    graph.osm_id(w[0]),
+                    graph.osm_id(w[1])
+                )));
+            }
+            // the stops have to follow the route, in time
+            let mut index = 0;
+            let mut time = 0.0;
+            let mut positions = Vec::with_capacity(line.stop.len());
+            for stop in &line.stop {
+                match line.route[index..].iter().position(|n| *n == stop.node) {
+                    Some(k) if stop.time >= time => {
+                        index += k;
+                        time = stop.time;
+                        positions.push(index);
+                    }
+                    _ => {
+                        return Err(Error::Config(format!(
+                            "Line {}: stop {} at {}s does not follow the route",
+                            line.name,
+                            graph.osm_id(stop.node),
+                            stop.time
+                        )))
+                    }
+                }
+            }
+
+            let times = match line.headway {
+                Some(headway) => {
+                    let last = line.last_departure.ok_or_else(|| {
+                        Error::Config(format!(
+                            "Line {}: lines running at a headway need a last departure",
+                            line.name
+                        ))
+                    })?;
+                    if headway.is_nan() || headway <= 0.0 {
+                        return Err(Error::Config(format!(
+                            "Line {}: headway must be positive, but is {}",
+                            line.name, headway
+                        )));
+                    }
+                    let mut times = line.departures.clone();
+                    let mut departure = line.first_departure;
+                    while departure <= last {
+                        times.push(departure);
+                        departure += headway;
+                    }
+                    times
+                }
+                None => line.departures.clone(),
+            };
+            if times.is_empty() {
+                return Err(Error::Config(format!(
+                    "Line {}: lines need departures or a headway",
+                    line.name
+                )));
+            }
+            if times.iter().any(|t| t.is_nan() || *t < 0.0) {
+                return Err(Error::Config(format!(
+                    "Line {}: departures must not be negative",
+                    line.name
+                )));
+            }
+
+            let index = feed.lines.len();
+            feed.departures
+                .extend(times.iter().map(|t| ((t / TIME_STEP).ceil() as u64, index)));
+            let dwell = line.dwell.unwrap_or(dwell);
+            feed.lines.push((line, class, dwell, positions));
+        }
+        feed.departures.sort();
+        Ok(feed)
+    }
+
+    /// Returns the number of lines.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns whether the feed has no lines.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Returns the number of trips of all lines.
+    pub fn trips(&self) -> usize {
+        self.departures.len()
+    }
+
+    /// Returns whether no bus departs at or after the given step.
+    pub fn is_done(&self, time: u64) -> bool {
+        self.departures.last().is_none_or(|(step, _)| *step < time)
+    }
+
+    /// Creates the buses departing at the given step.
+    pub fn depart(&self, time: u64) -> Result<Vec<Vehicle>> {
+        let first = self.departures.partition_point(|(step, _)| *step < time);
+        self.departures[first..]
+            .iter()
+            .take_while(|(step, _)| *step == time)
+            .map(|(step, index)| {
+                let (line, class, dwell, positions) = &self.lines[*index];
+                let departure = *step as f64 * TIME_STEP;
+                let mut trip = TransitTrip {
+                    line: line.name.clone(),
+                    departure,
+                    stops: line
+                        .stop
+                        .iter()
+                        .zip(positions)
+                        .map(|(s, index)| TripStop {
+                            node: s.node,
+                            time: departure + s.time,
+                            index: *index,
+                        })
+                        .collect(),
+                    dwell: *dwell,
+                    dwell_until: None,
+                    arrivals: Vec::new(),
+                };
+                // buses depart from the stop at the start of the route on time
+                if trip.stops_at(0) {
+                    trip.arrive(time);
+                    trip.leave();
+                }
+                VehicleBuilder::new()
+                    .with_class(class)
+                    .with_speed(class.max_speed)
+                    .with_prev_id(line.route[0])
+                    .with_next_id(line.route[1])
+                    .with_path_ids(line.route.clone())
+                    .with_transit(trip)
+                    .build()
+            })
+            .collect()
+    }
+}
+
+/// Schedule adherence of the buses of one line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineStatistics {
+    /// Number of finished trips.
+    pub trips: usize,
+    /// Number of arrivals at stops.
+    pub arrivals: usize,
+    /// Sum of the delays at the stops in seconds.
+    pub delay: f64,
+    /// Largest delay at a stop in seconds.
+    pub max_delay: f64,
+    /// Number of arrivals within the tolerance around the scheduled time.
+    pub on_time: usize,
+    /// Number of stops that were not served.
+    pub missed: usize,
+}
+
+/// Schedule adherence of all lines, with every stop arrival.
+#[derive(Debug, Clone, Default)]
+pub struct TransitStatistics {
+    /// The statistics of every line by name.
+    pub lines: BTreeMap<String, LineStatistics>,
+    /// Every arrival with the line and the scheduled departure of its trip.
+    pub arrivals: Vec<(String, f64, StopArrival)>,
+}
+
+impl TransitStatistics {
+    /// Records a bus that finished its trip.
+    pub fn record(&mut self, v: &Vehicle) {
+        let trip = match &v.transit {
+            Some(trip) => trip,
+            None => return,
+        };
+        let entry = self.lines.entry(trip.line.clone()).or_default();
+        entry.trips += 1;
+        entry.missed += trip.stops.len();
+        for arrival in &trip.arrivals {
+            let delay = arrival.delay();
+            entry.arrivals += 1;
+            entry.delay += delay;
+            entry.max_delay = entry.max_delay.max(delay);
+            if (-EARLY_TOLERANCE..=LATE_TOLERANCE).contains(&delay) {
+                entry.on_time += 1;
+            }
+            self.arrivals
+                .push((trip.line.clone(), trip.departure, *arrival));
+        }
+    }
+
+    /// Returns whether no bus finished.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Logs the schedule adherence of every line.
+    pub fn log(&self, rank: i32) {
+        for (name, s) in &self.lines {
+            log::info!(
+                "[{}] Line {}: {} trip(s), {} stop arrival(s) with {:.1}s mean and {:.1}s largest delay, {:.1}% on time, {} missed stop(s)",
+                rank,
+                name,
+                s.trips,
+                s.arrivals,
+                s.delay / s.arrivals.max(1) as f64,
+                s.max_delay,
+                100.0 * s.on_time as f64 / s.arrivals.max(1) as f64,
+                s.missed
+            );
+        }
+    }
+
+    /// Returns every stop arrival as CSV, with the stops given by their OSM ids.
+    pub fn to_csv(&self, graph: &OSMGraph) -> String {
+        let mut csv = String::from("line,departure,stop,scheduled,actual,delay\n");
+        for (line, departure, arrival) in &self.arrivals {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                line,
+                departure,
+                graph.osm_id(arrival.node),
+                arrival.scheduled,
+                arrival.actual,
+                arrival.delay()
+            );
+        }
+        csv
+    }
+}
+
+// Unit tests for transit lines
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::edge, models::graph_input::Graph};
+
+    #[test]
+    fn test_feed_spawns_buses_on_schedule() {
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges: (1..6).map(|i| edge(i, i + 1, 100.0)).collect(),
+        })
+        .unwrap();
+        let file: TransitFile = toml::from_str(
+            r#"
+            [[line]]
+            name = "42"
+            route = [1, 2, 3, 4, 5, 6]
+            headway = 300.0
+            last_departure = 600.0
+            stop = [{ node = 1, time = 0.0 }, { node = 4, time = 60.0 }]
+            "#,
+        )
+        .unwrap();
+        let lines: Vec<Line> = file
+            .line
+            .into_iter()
+            .map(|line| line.indexed(&graph))
+            .collect::<Result<_>>()
+            .unwrap();
+        let feed = TransitFeed::new(&graph, lines.clone(), DEFAULT_DWELL, &[]).unwrap();
+
+        assert_eq!((feed.len(), feed.trips()), (1, 3));
+        assert!(feed.depart(1).unwrap().is_empty());
+        let buses = feed.depart(300).unwrap();
+        assert_eq!(buses.len(), 1);
+        let trip = buses[0].transit.as_ref().unwrap();
+        assert_eq!(buses[0].class, "bus");
+        assert_eq!(trip.next_stop(), graph.node(4));
+        assert!(trip.stops_at(3));
+        assert_eq!(trip.stops[0].time, 360.0);
+        assert_eq!(trip.arrivals.len(), 1);
+        assert!(!feed.is_done(600) && feed.is_done(601));
+
+        let mut statistics = TransitStatistics::default();
+        let mut bus = buses[0].clone();
+        let trip = bus.transit.as_mut().unwrap();
+        trip.arrive(400);
+        trip.leave();
+        statistics.record(&bus);
+        assert_eq!(statistics.lines["42"].arrivals, 2);
+        assert_eq!(statistics.lines["42"].max_delay, 40.0);
+        assert_eq!(statistics.lines["42"].on_time, 2);
+
+        // stops have to lie on the route
+        let mut line = lines[0].clone();
+        line.stop.push(ScheduledStop {
+            node: graph.node(2).unwrap(),
+            time: 90.0,
+        });
+        assert!(TransitFeed::new(&graph, vec![line], DEFAULT_DWELL, &[]).is_err());
+        // the positions of the stops are derived from the route, not read from the feed
+        let positioned = "[[line]]\nname = \"1\"\nroute = [1, 2]\nstop = [{ node = 1, time = 0.0, index = 0 }]\n";
+        assert!(toml::from_str::<TransitFile>(positioned).is_err());
+    }
+}
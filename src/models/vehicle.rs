@@ -1,208 +1,169 @@
-use crate::graph::osm_graph::Osmid;
-use crate::utils::{get_random_vector_element, random_velocity, MpiMessageContent};
+use crate::graph::osm_graph::NodeId;
+use crate::models::{
+    activity::Activity,
+    car_following::{idm_acceleration, integrate, IdmParameters, Leader, RoadLimits, TIME_STEP},
+    transit::TransitTrip,
+};
 use crate::{graph::osm_graph::OSMGraph, prelude::*};
-use bincode::{deserialize, serialize};
-use petgraph::algo::astar;
-use petgraph::prelude::GraphMap;
-use petgraph::Directed;
-use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use super::vehicle_builder::VehicleBuilder;
-
 /// Represents a vehicle that can move within a graph.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vehicle {
     /// The unique identifier of the vehicle.
-    pub id: String,
+    pub id: u64,
+    /// The name of the vehicle class.
+    pub class: String,
+    /// The length of the vehicle in meters.
+    pub length: f64,
+    /// The maximum acceleration of the vehicle in m/s².
+    pub acceleration: f64,
     /// The path IDs representing the route the vehicle follows.
-    pub path_ids: Vec<Osmid>,
-    /// The speed of the vehicle.
+    pub path_ids: Vec<NodeId>,
+    /// The index of the start of the current edge in `path_ids`, advanced on every edge
+    /// the vehicle enters.
+    pub cursor: usize,
+    /// The desired speed of the vehicle in m/s.
     pub speed: f64,
-    /// The delta value for the vehicle.
-    pub delta: f64,
+    /// The current speed of the vehicle in m/s.
+    pub velocity: f64,
+    /// The distance of the vehicle's front from the start of its current edge in meters.
+    pub position: f64,
+    /// The lane of the vehicle on its current edge, counted from the rightmost lane `0`.
+    pub lane: usize,
     /// The next path ID the vehicle is moving toward.
-    pub next_id: Osmid,
+    pub next_id: NodeId,
     /// The previous path ID the vehicle was at.
-    pub prev_id: Osmid,
+    pub prev_id: NodeId,
     /// Indicates whether the vehicle is parked.
     pub is_parked: bool,
-    /// The remaining distance the vehicle has to travel.
-    pub distance_remaining: f64,
     /// Indicates whether the vehicle is marked for deletion.
     pub marked_for_deletion: bool,
     /// The number of steps the vehicle has taken.
     pub steps: u64,
+    /// The distance the vehicle has driven in meters.
+    pub distance: f64,
+    /// The maximum number of steps the vehicle may take.
+    pub max_steps: Option<u64>,
+    /// Whether the driver re-plans the route by the current travel times.
+    pub informed: bool,
+    /// The number of times the route was re-planned.
+    pub reroutes: u32,
+    /// The number of steps taken when the driver started the current search for parking.
+    pub search_start: Option<u64>,
+    /// The number of steps spent on earlier searches for parking.
+    pub search_steps: u64,
+    /// The activities of the trip chain not done yet, starting with the destination
+    /// of the current trip. Empty for vehicles making a single trip.
+    pub activities: Vec<Activity>,
+    /// The number of trips that ended at a parking space.
+    pub trips: u32,
+    /// The trip of the transit line the vehicle serves, if it is a bus of one.
+    pub transit: Option<TransitTrip>,
+}
+
+/// Where a vehicle is after moving along its route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// The vehicle is on an edge of the current graph.
+    Driving,
+    /// The vehicle reached its destination.
+    Arrived,
+    /// The route of the vehicle continues outside of the current graph.
+    Left,
 }
 
 /// A trait for moveable objects.
 pub trait Moveable {
-    /// Drives the moveable object within a graph.
-    fn drive(&mut self, graph: &OSMGraph);
-    /// Takes a step for the moveable object.
-    fn step(&mut self, graph: &OSMGraph);
+    /// Takes a time step for the moveable object, following the given leader
+    /// within the limits of its edge. Fails if its route does not lead on from its edge.
+    fn step(
+        &mut self,
+        graph: &OSMGraph,
+        leader: Option<Leader>,
+        limits: RoadLimits,
+    ) -> Result<Progress>;
     /// Gets the next node for the moveable object.
-    fn get_next_node(&mut self, prev_id: Osmid, current_graph: &OSMGraph) -> Option<Osmid>;
-    /// Calculates the step for the moveable object.
-    fn calculate_step(&mut self);
+    fn get_next_node(&mut self, prev_id: NodeId, current_graph: &OSMGraph) -> Option<NodeId>;
+    /// Calculates the step for the moveable object with the given acceleration
+    /// and returns the travelled distance.
+    fn calculate_step(&mut self, acceleration: f64) -> f64;
 }
 
 impl Moveable for Vehicle {
-    fn drive(&mut self, osm_graph: &OSMGraph) {
-        while !self.is_parked {
-            self.step(osm_graph);
-        }
-    }
-
-    fn calculate_step(&mut self) {
+    fn calculate_step(&mut self, acceleration: f64) -> f64 {
         // NOTE: adding CPU-intensive placeholder function simulating a complex calculation by
         // generating a random prime number
         #[cfg(feature = "complex-calculation")]
         {
             let mut rng = rand::thread_rng();
             let number = rand::Rng::gen_range(&mut rng, 1_000_000..=3_000_000);
-            let _some_unused_prime = primal::Primes::all().nth(number).unwrap();
+            let _some_unused_prime = primal::Primes::all().nth(number);
         }
-        // WARN: Actually crucial code. Do not remove.
-        self.distance_remaining -= self.speed;
+        let (velocity, distance) = integrate(self.velocity, acceleration, TIME_STEP);
+        self.velocity = velocity;
+        distance
     }
 
-    fn step(&mut self, osm_graph: &OSMGraph) {
+    fn step(
+        &mut self,
+        osm_graph: &OSMGraph,
+        leader: Option<Leader>,
+        limits: RoadLimits,
+    ) -> Result<Progress> {
         log::debug!("Vehicle {} is stepping", self.id);
         self.steps += 1;
 
-        let gg = &osm_graph.graph;
-        if !gg.contains_edge(self.prev_id, self.next_id) {
-            let old_prev = self.prev_id;
-            self.prev_id = self.next_id;
-            self.next_id = match self.get_next_node(self.next_id, osm_graph) {
-                Some(id) => id,
-                None => {
-                    self.prev_id = old_prev;
-                    if self.marked_for_deletion || self.is_parked {
-                        return;
-                    }
-                    log::error!(
-                        "No next node found for prev_id={}, V={:?}",
-                        self.prev_id,
-                        self
-                    );
-                    self.next_id
-                }
-            }
-        }
+        let acceleration = self.desired_acceleration(leader, limits);
+        let mut distance = self.calculate_step(acceleration);
 
-        let edge = match gg
-            .edges_directed(self.prev_id, petgraph::Direction::Outgoing)
-            .find(|e| e.1 == self.next_id)
-        {
-            Some(e) => e,
-            None => {
-                self.marked_for_deletion = true;
-                log::debug!(
-                    "No edge found {}->{}  @ {:?} while stepping. Marking for deletion",
-                    self.prev_id,
-                    self.next_id,
-                    self.id,
-                );
-                return;
+        // never drive into the leader
+        if let Some(leader) = leader {
+            let room = leader.gap.max(0.0);
+            if distance > room {
+                distance = room;
+                self.velocity = self.velocity.min(leader.speed);
             }
-        };
-        let length = *edge.2;
-
-        self.distance_remaining = length;
-        self.distance_remaining += self.delta;
-
-        // * NOTE: Vehicle travels the entire distance in one step
-        // *        This is not correct, as the vehicle should only travel
-        // *        the distance of its speed in one step.
-        // *        Maybe change to "if"
-        while (self.distance_remaining >= self.speed) && (self.distance_remaining - self.speed > 0.)
-        {
-            self.calculate_step();
         }
-        self.delta = self.distance_remaining;
-        self.distance_remaining = 0_f64;
 
-        let tmp = match self.get_next_node(self.next_id, osm_graph) {
-            Some(id) => id,
-            None => {
-                if self.marked_for_deletion {
-                    log::debug!("Vehicle {} is marked for deletion", self.id);
-                    return;
-                } else if self.is_parked
-                    || self.path_ids.last().is_some()
-                        && self.path_ids.last().unwrap() == &self.prev_id
-                {
-                    self.is_parked = true;
-                    log::debug!("Vehicle - 3 {} is done driving", self.id);
-                    return;
-                } else {
-                    panic!(
-                        "No next node found for prev_id={}, V={:?}",
-                        self.prev_id, self
-                    );
-                }
-            }
-        };
-        log::debug!("Prev={} Tmp={} Next={}", self.prev_id, tmp, self.next_id);
-        self.prev_id = self.next_id;
-        self.next_id = tmp;
-        log::debug!(
-            "Vehicle {} is stepping to {}->{}",
-            self.id,
-            self.prev_id,
-            self.next_id
-        );
-
-        if self.next_id == 0 {
-            self.is_parked = true;
-        }
+        self.advance(distance, osm_graph)
     }
 
-    fn get_next_node(&mut self, prev_id: Osmid, current_graph: &OSMGraph) -> Option<Osmid> {
-        // if prev_id is last element in path_ids, return None
-        if self.path_ids.last().unwrap() == &prev_id
-            || self.path_ids.get(self.path_ids.len() - 2).unwrap() == &prev_id
-        {
-            self.is_parked = true;
+    fn get_next_node(&mut self, prev_id: NodeId, current_graph: &OSMGraph) -> Option<NodeId> {
+        // prev_id ends the current edge, so it follows the cursor on the route
+        let index = self.cursor + 1;
+        if self.path_ids.get(index) != Some(&prev_id) {
+            log::error!(
+                "prev_id={} is not at index {} of the route of vehicle {}",
+                prev_id,
+                index,
+                self.id
+            );
             return None;
         }
 
-        // get the index of the next id, which is the index of prev_id + 1
-        let next_id_index = match self.path_ids.par_iter().position_any(|&x| x == prev_id) {
-            Some(i) => i + 1,
-            None => {
-                log::error!("No next id found for prev_id={}", prev_id);
-                return None;
-            }
-        };
-
-        // check if next_id_index is out of bounds
-        if next_id_index >= self.path_ids.len() {
-            log::error!(
-                "next_id_index={} is out of bounds for path_ids.len()={}",
-                next_id_index,
-                self.path_ids.len()
-            );
+        // the vehicle arrived once prev_id is the last node of its route
+        if index + 1 >= self.path_ids.len() {
+            self.is_parked = true;
             return None;
         }
 
         // get the next id
-        let next_id = self.path_ids[next_id_index];
+        let next_id = self.path_ids[index + 1];
         log::debug!("prev_id={} next_id={}", self.prev_id, next_id);
 
-        // check if next_id is in the current graph, if not return None and mark for deletion
-        let cg = current_graph.graph.clone();
-        if !cg.nodes().any(|x| x == next_id) {
+        // check if next_id is in the current graph, if not return None and mark for deletion.
+        // The vehicle keeps the edge leading out of the graph, which the next rank drives.
+        if !current_graph.contains_node(next_id) {
             log::debug!(
                 "next_id={} is not in current graph. Marking VID={} for deletion",
                 next_id,
                 self.id
             );
             self.marked_for_deletion = true;
-            self.prev_id = next_id;
-            self.next_id = self.path_ids[next_id_index + 1];
+            self.prev_id = prev_id;
+            self.next_id = next_id;
+            self.cursor = index;
             return None;
         }
 
@@ -210,67 +171,133 @@ impl Moveable for Vehicle {
     }
 }
 
-impl MpiMessageContent<Vehicle> for Vehicle {
-    fn from_bytes(data: Vec<u8>) -> Result<Vehicle> {
-        match deserialize::<Vehicle>(&data) {
-            Ok(v) => Ok(v),
-            Err(err) => Err(Error::Bincode(err)),
-        }
-    }
-
-    fn to_bytes(data: Vehicle) -> Result<Vec<u8>> {
-        match serialize(&data) {
-            Ok(arr) => Ok(arr),
-            Err(err) => Err(Error::Bincode(err)),
-        }
-    }
-}
-
 impl Vehicle {
-    /// Generates a default vehicle with a random route and speed within a given graph.
-    pub fn generate_default(
-        graph: &GraphMap<usize, f64, Directed>,
-        min_speed: f64,
-        max_speed: f64,
-    ) -> Result<Vehicle> {
-        let vtx: Vec<_> = graph.nodes().collect();
-        let mut path = None;
-        let mut path_length = 0;
+    /// Moves the vehicle along its route, entering the following edges as needed.
+    /// Fails if the route does not lead on from an edge the vehicle reaches the end of.
+    pub fn advance(&mut self, distance: f64, osm_graph: &OSMGraph) -> Result<Progress> {
+        self.position += distance;
+        self.distance += distance;
 
-        while path.is_none() || path_length < 5 {
-            let start = match get_random_vector_element(&vtx) {
-                Some(v) => *v,
-                None => Err(Error::Generic(String::from("No random vertex found")))?,
+        loop {
+            let length = match osm_graph.edge_length(self.prev_id, self.next_id) {
+                Some(length) => length,
+                None => {
+                    self.marked_for_deletion = true;
+                    log::debug!(
+                        "No edge found {}->{}  @ {:?} while stepping. Marking for deletion",
+                        self.prev_id,
+                        self.next_id,
+                        self.id,
+                    );
+                    return Ok(Progress::Left);
+                }
             };
+            if self.position < length {
+                return Ok(Progress::Driving);
+            }
 
-            let end = match get_random_vector_element(&vtx) {
-                Some(v) => *v,
-                None => Err(Error::Generic(String::from("No random vertex found")))?,
+            let next = match self.get_next_node(self.next_id, osm_graph) {
+                Some(id) => id,
+                None => {
+                    if self.marked_for_deletion {
+                        log::debug!("Vehicle {} is marked for deletion", self.id);
+                        self.position -= length;
+                        return Ok(Progress::Left);
+                    } else if self.is_parked {
+                        log::debug!("Vehicle - 3 {} is done driving", self.id);
+                        // the vehicle stops at the end of the edge
+                        self.distance -= self.position - length;
+                        self.position = length;
+                        self.velocity = 0.0;
+                        return Ok(Progress::Arrived);
+                    } else {
+                        return Err(Error::Routing(format!(
+                            "Route of vehicle {} does not lead on from {}->{}",
+                            self.id, self.prev_id, self.next_id
+                        )));
+                    }
+                }
             };
+            self.position -= length;
+            self.prev_id = self.next_id;
+            self.next_id = next;
+            self.cursor += 1;
+            // vehicles keep their lane unless the new edge has fewer lanes
+            self.lane = self
+                .lane
+                .min(osm_graph.edge_lanes(self.prev_id, self.next_id) - 1);
+            log::debug!(
+                "Vehicle {} is stepping to {}->{}",
+                self.id,
+                self.prev_id,
+                self.next_id
+            );
+        }
+    }
 
-            path = astar(&graph, start, |finish| finish == end, |e| *e.2, |_| 0.);
+    /// Returns the node following the current edge, if the route continues after it.
+    pub fn upcoming_node(&self) -> Option<NodeId> {
+        self.path_ids.get(self.cursor + 2).copied()
+    }
 
-            path_length = match path.as_ref() {
-                Some(p) => p.1.len(),
-                None => 0,
-            };
+    /// Replaces the rest of the route, starting at the end of the current edge.
+    /// Returns whether the route changed.
+    pub fn replace_route(&mut self, route: Vec<NodeId>) -> bool {
+        if route.first() != Some(&self.next_id) || self.remaining_route() == route {
+            return false;
         }
+        self.path_ids = std::iter::once(self.prev_id).chain(route).collect();
+        self.cursor = 0;
+        self.reroutes += 1;
+        if let Some(trip) = &mut self.transit {
+            trip.follow_route(&self.path_ids);
+        }
+        true
+    }
 
-        let path = match path {
-            Some(p) => p.1,
-            None => Err(Error::Generic(String::from("No path found")))?,
+    /// Puts the vehicle at the start of a new route, which needs at least two nodes.
+    pub fn start_route(&mut self, route: Vec<NodeId>) {
+        self.prev_id = route[0];
+        self.next_id = route[1];
+        self.path_ids = route;
+        self.cursor = 0;
+    }
+
+    /// Returns the acceleration in m/s² the car-following model chooses behind the given leader,
+    /// within the limits of the edge.
+    pub fn desired_acceleration(&self, leader: Option<Leader>, limits: RoadLimits) -> f64 {
+        let defaults = IdmParameters::default();
+        let params = IdmParameters {
+            time_headway: defaults.time_headway / limits.capacity_factor,
+            ..defaults
         };
+        idm_acceleration(
+            &params,
+            self.velocity,
+            self.speed * limits.speed_factor,
+            self.acceleration,
+            leader,
+        )
+    }
+
+    /// Returns the rest of the route, starting at the end of the current edge.
+    pub fn remaining_route(&self) -> &[NodeId] {
+        self.path_ids.get(self.cursor + 1..).unwrap_or(&[])
+    }
+
+    /// Returns the position of the vehicle's rear on its current edge in meters.
+    pub fn rear(&self) -> f64 {
+        self.position - self.length
+    }
 
-        let velocity = random_velocity(min_speed, max_speed);
+    /// Returns the number of steps spent searching for parking.
+    pub fn parking_search(&self) -> u64 {
+        self.search_steps + self.search_start.map_or(0, |start| self.steps - start)
+    }
 
-        let veh = VehicleBuilder::new()
-            .with_delta(0.0)
-            .with_is_parked(false)
-            .with_speed(velocity)
-            .with_path_ids(path.clone())
-            .with_prev_id(path[0])
-            .with_next_id(path[1])
-            .build()?;
-        Ok(veh)
+    /// Returns whether the vehicle used up its step budget.
+    pub fn is_out_of_steps(&self) -> bool {
+        self.max_steps
+            .is_some_and(|max_steps| self.steps >= max_steps)
     }
 }
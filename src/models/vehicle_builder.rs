@@ -1,67 +1,116 @@
-use crate::models::vehicle::Vehicle;
+use crate::graph::osm_graph::NodeId;
+use crate::models::{
+    activity::Activity, transit::TransitTrip, vehicle::Vehicle, vehicle_class::VehicleClass,
+};
 use crate::prelude::*;
-use nanoid::nanoid;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::vec::Vec;
 
+// Identifier of the next built vehicle. Only the root hands out identifiers under MPI,
+// so identifiers are unique within a run.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Reserves `count` consecutive vehicle identifiers and returns the first of them.
+/// Vehicles built with these identifiers do not collide with any other vehicle.
+pub fn reserve_ids(count: usize) -> u64 {
+    NEXT_ID.fetch_add(count as u64, Ordering::Relaxed)
+}
+
 /// Builder for creating instances of the `Vehicle` struct.
 #[derive(Debug)]
 pub struct VehicleBuilder {
-    /// The speed of the vehicle.
+    /// The identifier of the vehicle, the next free one if unset.
+    pub id: Option<u64>,
+    /// The class of the vehicle.
+    pub class: VehicleClass,
+    /// The desired speed of the vehicle.
     pub speed: f64,
     /// The path IDs associated with the vehicle's route.
-    pub path_ids: Vec<usize>,
+    pub path_ids: Vec<NodeId>,
 
-    /// The delta value for the vehicle.
-    pub delta: f64,
+    /// The position of the vehicle on its first edge.
+    pub position: f64,
     /// Indicates whether the vehicle is parked.
     pub is_parked: bool,
 
     /// The previous ID of the vehicle.
-    pub prev_id: usize,
+    pub prev_id: NodeId,
     /// The next ID of the vehicle.
-    pub next_id: usize,
+    pub next_id: NodeId,
+    /// The maximum number of steps the vehicle may take.
+    pub max_steps: Option<u64>,
+    /// Whether the driver re-plans the route by the current travel times.
+    pub informed: bool,
+    /// The activities of the vehicle's trip chain.
+    pub activities: Vec<Activity>,
+    /// The trip of the transit line the vehicle serves.
+    pub transit: Option<TransitTrip>,
+}
+
+impl Default for VehicleBuilder {
+    fn default() -> Self {
+        VehicleBuilder::new()
+    }
 }
 
 impl VehicleBuilder {
     /// Creates a new `VehicleBuilder` instance with default values.
     pub fn new() -> VehicleBuilder {
         VehicleBuilder {
+            id: None,
+            class: VehicleClass::car(),
             speed: 0.0,
             path_ids: Vec::new(),
-            delta: 0.0,
+            position: 0.0,
             is_parked: false,
-            prev_id: 0,
-            next_id: 0,
+            prev_id: NodeId::default(),
+            next_id: NodeId::default(),
+            max_steps: None,
+            informed: false,
+            activities: Vec::new(),
+            transit: None,
         }
     }
 
-    /// Sets the speed of the vehicle.
+    /// Sets the identifier of the vehicle, which must be reserved with `reserve_ids`.
+    pub fn with_id(mut self, id: Option<u64>) -> VehicleBuilder {
+        self.id = id;
+        self
+    }
+
+    /// Sets the class of the vehicle.
+    pub fn with_class(mut self, class: &VehicleClass) -> VehicleBuilder {
+        self.class = class.clone();
+        self
+    }
+
+    /// Sets the desired speed of the vehicle.
     pub fn with_speed(mut self, speed: f64) -> VehicleBuilder {
         self.speed = speed;
         self
     }
 
     /// Sets the path IDs for the vehicle's route.
-    pub fn with_path_ids(mut self, path_ids: Vec<usize>) -> VehicleBuilder {
+    pub fn with_path_ids(mut self, path_ids: Vec<NodeId>) -> VehicleBuilder {
         self.path_ids = path_ids;
         self
     }
 
     /// Sets the previous ID of the vehicle.
-    pub fn with_prev_id(mut self, prev_id: usize) -> VehicleBuilder {
+    pub fn with_prev_id(mut self, prev_id: NodeId) -> VehicleBuilder {
         self.prev_id = prev_id;
         self
     }
 
     /// Sets the next ID of the vehicle.
-    pub fn with_next_id(mut self, next_id: usize) -> VehicleBuilder {
+    pub fn with_next_id(mut self, next_id: NodeId) -> VehicleBuilder {
         self.next_id = next_id;
         self
     }
 
-    /// Sets the delta value for the vehicle.
-    pub fn with_delta(mut self, delta: f64) -> VehicleBuilder {
-        self.delta = delta;
+    /// Sets the position of the vehicle on its first edge.
+    pub fn with_position(mut self, position: f64) -> VehicleBuilder {
+        self.position = position;
         self
     }
 
@@ -71,42 +120,92 @@ impl VehicleBuilder {
         self
     }
 
+    /// Sets the maximum number of steps the vehicle may take.
+    pub fn with_max_steps(mut self, max_steps: Option<u64>) -> VehicleBuilder {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets whether the driver re-plans the route by the current travel times.
+    pub fn with_informed(mut self, informed: bool) -> VehicleBuilder {
+        self.informed = informed;
+        self
+    }
+
     /// Performs validation checks on the builder's data.
     fn check(&mut self) -> crate::prelude::Result<()> {
         if self.speed == 0.0 {
-            return Err(Error::Generic(String::from("Speed is 0.0")));
+            return Err(Error::Config(String::from("Speed is 0.0")));
         }
-        if self.path_ids.len() < 2 {
-            return Err(Error::Generic(String::from("PathIDs length < 2")));
+        if self.speed > self.class.max_speed {
+            return Err(Error::Config(format!(
+                "Speed exceeds the maximum speed of class {}",
+                self.class.name
+            )));
         }
-        if self.prev_id < 1 {
-            return Err(Error::Generic(String::from("PrevID < 1")));
+        if self.path_ids.len() < 2 {
+            return Err(Error::Routing(String::from("PathIDs length < 2")));
         }
 
         Ok(())
     }
 
-    /// Builds a `Vehicle` instance from the builder's data.
-    pub fn build(&mut self) -> crate::prelude::Result<Vehicle> {
-        let alphabet: [char; 16] = [
-            '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'a', 'b', 'c', 'd', 'e', 'f',
-        ];
+    /// Sets the activities of the vehicle's trip chain, starting with its first destination.
+    pub fn with_activities(mut self, activities: Vec<Activity>) -> VehicleBuilder {
+        self.activities = activities;
+        self
+    }
 
-        let id = nanoid!(10, &alphabet);
+    /// Lets the vehicle serve a trip of a transit line.
+    pub fn with_transit(mut self, trip: TransitTrip) -> VehicleBuilder {
+        self.transit = Some(trip);
+        self
+    }
 
+    /// Builds a `Vehicle` instance from the builder's data.
+    /// Fails unless the edge from the previous to the next ID is part of the route.
+    pub fn build(&mut self) -> crate::prelude::Result<Vehicle> {
         self.check()?;
+        // the vehicle starts on the first occurrence of its edge
+        let cursor = self
+            .path_ids
+            .windows(2)
+            .position(|w| w[0] == self.prev_id && w[1] == self.next_id)
+            .ok_or_else(|| {
+                Error::Routing(format!(
+                    "Edge {}->{} is not part of the route",
+                    self.prev_id, self.next_id
+                ))
+            })?;
+        let id = self
+            .id
+            .unwrap_or_else(|| NEXT_ID.fetch_add(1, Ordering::Relaxed));
 
         Ok(Vehicle {
             id,
+            class: self.class.name.clone(),
+            length: self.class.length,
+            acceleration: self.class.acceleration,
             path_ids: self.path_ids.clone(),
+            cursor,
             speed: self.speed,
-            delta: self.delta,
+            velocity: 0.0,
+            position: self.position,
+            lane: 0,
             next_id: self.next_id,
             prev_id: self.prev_id,
             is_parked: self.is_parked,
-            distance_remaining: 0.0,
             marked_for_deletion: false,
             steps: 0,
+            distance: 0.0,
+            max_steps: self.max_steps,
+            informed: self.informed,
+            reroutes: 0,
+            search_start: None,
+            search_steps: 0,
+            activities: self.activities.clone(),
+            trips: 0,
+            transit: self.transit.take(),
         })
     }
 }
@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{graph_input::Edge, speed::SpeedDistribution, speed::SpeedSampler, vehicle::Vehicle},
+    prelude::*,
+};
+
+// Name of the class used when no class mix is configured
+pub const DEFAULT_CLASS: &str = "car";
+
+// Road types open to motorised traffic, from the most to the least important
+pub const MOTOR_ROADS: [&str; 14] = [
+    "motorway",
+    "motorway_link",
+    "trunk",
+    "trunk_link",
+    "primary",
+    "primary_link",
+    "secondary",
+    "secondary_link",
+    "tertiary",
+    "tertiary_link",
+    "unclassified",
+    "residential",
+    "living_street",
+    "service",
+];
+
+/// Preference used when searching the route of a vehicle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoutingPreference {
+    /// Minimise the driven distance.
+    #[default]
+    Shortest,
+    /// Minimise the travel time at the speed limit of each road.
+    Fastest,
+}
+
+/// Describes a kind of vehicle, such as cars, trucks, buses or bicycles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VehicleClass {
+    /// The name of the class.
+    pub name: String,
+    /// The length of the vehicle in meters.
+    pub length: f64,
+    /// The maximum speed of the vehicle in m/s.
+    pub max_speed: f64,
+    /// The maximum acceleration of the vehicle in m/s².
+    pub acceleration: f64,
+    /// The OSM `highway` types the class may drive on. Empty allows all roads.
+    /// Roads without a known type are always allowed.
+    #[serde(default)]
+    pub road_types: Vec<String>,
+    /// How the routes of the class are chosen.
+    #[serde(default)]
+    pub routing: RoutingPreference,
+    /// The speed distribution of the class. Defaults to the scenario's distribution.
+    #[serde(default)]
+    pub speed: Option<SpeedDistribution>,
+    /// The minimum speed of the class in m/s. Defaults to the scenario's minimum speed.
+    #[serde(default)]
+    pub min_speed: Option<f64>,
+}
+
+impl VehicleClass {
+    /// A passenger car.
+    pub fn car() -> VehicleClass {
+        VehicleClass {
+            name: String::from("car"),
+            length: 4.5,
+            max_speed: 50.0,
+            acceleration: 2.6,
+            road_types: Vec::new(),
+            routing: RoutingPreference::Shortest,
+            speed: None,
+            min_speed: None,
+        }
+    }
+
+    /// A heavy goods vehicle, kept off residential streets.
+    pub fn truck() -> VehicleClass {
+        VehicleClass {
+            name: String::from("truck"),
+            length: 12.0,
+            max_speed: 25.0,
+            acceleration: 1.0,
+            road_types: MOTOR_ROADS[..11].iter().map(|r| r.to_string()).collect(),
+            routing: RoutingPreference::Fastest,
+            speed: None,
+            min_speed: None,
+        }
+    }
+
+    /// A city bus.
+    pub fn bus() -> VehicleClass {
+        VehicleClass {
+            name: String::from("bus"),
+            length: 12.0,
+            max_speed: 22.2,
+            acceleration: 1.2,
+            road_types: MOTOR_ROADS
+                .iter()
+                .chain(["busway"].iter())
+                .map(|r| r.to_string())
+                .collect(),
+            routing: RoutingPreference::Fastest,
+            speed: None,
+            min_speed: None,
+        }
+    }
+
+    /// A bicycle, kept off motorways and trunk roads.
+    pub fn bicycle() -> VehicleClass {
+        VehicleClass {
+            name: String::from("bicycle"),
+            length: 1.8,
+            max_speed: 8.0,
+            acceleration: 1.0,
+            road_types: MOTOR_ROADS[4..]
+                .iter()
+                .chain(["cycleway", "track", "path"].iter())
+                .map(|r| r.to_string())
+                .collect(),
+            routing: RoutingPreference::Shortest,
+            speed: Some(SpeedDistribution::Normal {
+                mean: 4.5,
+                std_dev: 1.0,
+            }),
+            min_speed: Some(2.0),
+        }
+    }
+
+    /// Looks up a built-in class by its name.
+    pub fn builtin(name: &str) -> Option<VehicleClass> {
+        match name {
+            "car" => Some(VehicleClass::car()),
+            "truck" => Some(VehicleClass::truck()),
+            "bus" => Some(VehicleClass::bus()),
+            "bicycle" => Some(VehicleClass::bicycle()),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the class may drive on the given edge.
+    pub fn allows(&self, edge: &Edge) -> bool {
+        match &edge.highway {
+            Some(highway) => self.road_types.is_empty() || self.road_types.contains(highway),
+            None => true,
+        }
+    }
+
+    /// Returns the routing cost of the given edge for this class.
+    pub fn edge_cost(&self, edge: &Edge) -> f64 {
+        match self.routing {
+            RoutingPreference::Shortest => edge.length,
+            RoutingPreference::Fastest => {
+                let speed = edge
+                    .max_speed_mps()
+                    .map_or(self.max_speed, |limit| limit.min(self.max_speed));
+                edge.length / speed
+            }
+        }
+    }
+
+    /// Prepares the speed distribution of the class, falling back to the scenario's
+    /// distribution and speed bounds.
+    pub fn speed_sampler(
+        &self,
+        distribution: &SpeedDistribution,
+        min_speed: f64,
+        max_speed: f64,
+    ) -> Result<SpeedSampler> {
+        match &self.speed {
+            Some(own) => {
+                SpeedSampler::new(own, self.min_speed.unwrap_or(min_speed), self.max_speed)
+            }
+            None => SpeedSampler::new(
+                distribution,
+                self.min_speed.unwrap_or(min_speed),
+                max_speed.min(self.max_speed),
+            ),
+        }
+    }
+}
+
+/// A vehicle class together with its share of the generated vehicles.
+#[derive(Debug, Clone)]
+pub struct ClassShare {
+    /// The vehicle class.
+    pub class: VehicleClass,
+    /// The relative share of vehicles of this class.
+    pub share: f64,
+    /// The distribution speeds of this class are drawn from.
+    pub speeds: SpeedSampler,
+}
+
+// Parses a class mix like `car=0.8,truck=0.2`. Classes are looked up in `custom` first,
+// then among the built-in classes. Without a mix, all vehicles are cars.
+pub fn parse_class_mix(
+    mix: Option<&str>,
+    custom: &[VehicleClass],
+) -> Result<Vec<(VehicleClass, f64)>> {
+    let mix = match mix {
+        Some(mix) => mix,
+        None => {
+            let class = custom
+                .iter()
+                .find(|c| c.name == DEFAULT_CLASS)
+                .cloned()
+                .unwrap_or_else(VehicleClass::car);
+            return Ok(vec![(class, 1.0)]);
+        }
+    };
+
+    let mut classes = Vec::new();
+    for entry in mix.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        let (name, share) = match entry.split_once('=') {
+            Some((name, share)) => {
+                let share = share.trim().parse::<f64>().map_err(|_| {
+                    Error::Config(format!("Invalid share `{}` for class {}", share, name))
+                })?;
+                (name.trim(), share)
+            }
+            None => (entry, 1.0),
+        };
+        if !(share >= 0.0 && share.is_finite()) {
+            return Err(Error::Config(format!(
+                "Share of class {} must be a non-negative number",
+                name
+            )));
+        }
+
+        let class = match custom.iter().find(|c| c.name == name) {
+            Some(class) => class.clone(),
+            None => VehicleClass::builtin(name)
+                .ok_or_else(|| Error::Config(format!("Unknown vehicle class {}", name)))?,
+        };
+        classes.push((class, share));
+    }
+
+    if classes.iter().all(|c| c.1 == 0.0) {
+        return Err(Error::Config(String::from(
+            "Class mix needs at least one class with a positive share",
+        )));
+    }
+    Ok(classes)
+}
+
+/// Parses the class mix and creates the speed sampler of every class in it.
+pub fn class_shares(
+    mix: Option<&str>,
+    custom: &[VehicleClass],
+    distribution: &SpeedDistribution,
+    min_speed: f64,
+    max_speed: f64,
+) -> Result<Vec<ClassShare>> {
+    parse_class_mix(mix, custom)?
+        .into_iter()
+        .map(|(class, share)| {
+            let speeds = class.speed_sampler(distribution, min_speed, max_speed)?;
+            Ok(ClassShare {
+                class,
+                share,
+                speeds,
+            })
+        })
+        .collect()
+}
+
+/// Results of the vehicles of one class.
+#[derive(Debug, Clone, Default)]
+pub struct ClassStatistics {
+    /// Number of vehicles.
+    pub vehicles: usize,
+    /// Number of vehicles that reached their destination.
+    pub finished: usize,
+    /// Number of steps taken by all vehicles.
+    pub steps: u64,
+    /// Distance driven by all vehicles in meters.
+    pub distance: f64,
+    /// Number of informed drivers.
+    pub informed: usize,
+    /// Number of steps taken by the informed drivers.
+    pub informed_steps: u64,
+    /// Number of re-planned routes.
+    pub reroutes: u64,
+    /// Number of vehicles that had to search for parking.
+    pub searching: usize,
+    /// Number of steps spent searching for parking.
+    pub search_steps: u64,
+    /// Number of vehicles that found no parking space.
+    pub unparked: usize,
+    /// Number of trips that ended at a parking space.
+    pub trips: u64,
+}
+
+// Records a vehicle in the statistics of its class
+pub fn record_vehicle(statistics: &mut HashMap<String, ClassStatistics>, v: &Vehicle) {
+    let entry = statistics.entry(v.class.clone()).or_default();
+    entry.vehicles += 1;
+    entry.steps += v.steps;
+    entry.distance += v.distance;
+    entry.reroutes += v.reroutes as u64;
+    if v.informed {
+        entry.informed += 1;
+        entry.informed_steps += v.steps;
+    }
+    if v.is_parked {
+        entry.finished += 1;
+    }
+    entry.trips += v.trips as u64;
+    if v.search_start.is_some() || v.search_steps > 0 {
+        entry.searching += 1;
+        entry.search_steps += v.parking_search();
+        if !v.is_parked {
+            entry.unparked += 1;
+        }
+    }
+}
+
+// Logs the statistics of every class, sorted by class name
+pub fn log_class_statistics(rank: i32, statistics: &HashMap<String, ClassStatistics>) {
+    let mut classes: Vec<_> = statistics.iter().collect();
+    classes.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, s) in classes {
+        log::info!(
+            "[{}] Class {}: {} vehicles ({} finished) in {} steps, {:.1} steps per vehicle",
+            rank,
+            name,
+            s.vehicles,
+            s.finished,
+            s.steps,
+            s.steps as f64 / s.vehicles.max(1) as f64
+        );
+        if s.informed > 0 {
+            let uninformed = s.vehicles - s.informed;
+            log::info!(
+                "[{}] Class {}: {} informed driver(s) with {:.1} steps per vehicle and {} reroute(s), {:.1} steps per uninformed vehicle",
+                rank,
+                name,
+                s.informed,
+                s.informed_steps as f64 / s.informed as f64,
+                s.reroutes,
+                (s.steps - s.informed_steps) as f64 / uninformed.max(1) as f64
+            );
+        }
+        if s.trips > s.vehicles as u64 {
+            log::info!(
+                "[{}] Class {}: {} trips in activity chains, {:.1} per vehicle",
+                rank,
+                name,
+                s.trips,
+                s.trips as f64 / s.vehicles as f64
+            );
+        }
+        if s.searching > 0 {
+            log::info!(
+                "[{}] Class {}: {} vehicle(s) searched for parking for {:.1} steps on average, {} found no space",
+                rank,
+                name,
+                s.searching,
+                s.search_steps as f64 / s.searching as f64,
+                s.unparked
+            );
+        }
+    }
+}
+
+// Unit tests for vehicle classes
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::edge;
+
+    // An edge of 100m on a road of the given type and speed limit
+    fn road(highway: Option<&str>, max_speed: &str) -> Edge {
+        Edge {
+            max_speed: String::from(max_speed),
+            highway: highway.map(String::from),
+            ..edge(1, 2, 100.0)
+        }
+    }
+
+    #[test]
+    fn test_road_type_restrictions() {
+        let truck = VehicleClass::truck();
+        let bicycle = VehicleClass::bicycle();
+
+        assert!(!truck.allows(&road(Some("residential"), "30")));
+        assert!(truck.allows(&road(Some("primary"), "50")));
+        assert!(truck.allows(&road(None, "50")));
+        assert!(!bicycle.allows(&road(Some("motorway"), "120")));
+        assert!(bicycle.allows(&road(Some("cycleway"), "")));
+    }
+
+    #[test]
+    fn test_fastest_routing_uses_speed_limit() {
+        let truck = VehicleClass::truck();
+
+        assert!((truck.edge_cost(&road(None, "36")) - 10.0).abs() < 1e-9);
+        // capped by the maximum speed of the class
+        assert!((truck.edge_cost(&road(None, "180")) - 4.0).abs() < 1e-9);
+        assert_eq!(VehicleClass::car().edge_cost(&road(None, "36")), 100.0);
+    }
+
+    #[test]
+    fn test_parse_class_mix() {
+        let mut van = VehicleClass::car();
+        van.name = String::from("van");
+
+        let mix = parse_class_mix(Some("car=0.7, van=0.2,bicycle"), &[van]).unwrap();
+        let names: Vec<_> = mix.iter().map(|c| (c.0.name.as_str(), c.1)).collect();
+        assert_eq!(names, vec![("car", 0.7), ("van", 0.2), ("bicycle", 1.0)]);
+
+        assert!(parse_class_mix(Some("tram=1"), &[]).is_err());
+        assert!(parse_class_mix(Some("car=0"), &[]).is_err());
+        assert_eq!(parse_class_mix(None, &[]).unwrap()[0].0.name, "car");
+    }
+}
@@ -0,0 +1,539 @@
+use std::path::Path;
+
+use petgraph::{algo::astar, prelude::GraphMap, Directed};
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::config::{DEFAULT_MAX_ATTEMPTS, DEFAULT_MAX_TRIP_LENGTH};
+use crate::graph::{
+    landmarks::Landmarks,
+    osm_graph::{NodeId, OSMGraph},
+    strongly_connected_components,
+};
+use crate::models::{
+    activity::TripChains,
+    speed::SpeedSampler,
+    vehicle::Vehicle,
+    vehicle_builder::VehicleBuilder,
+    vehicle_class::{ClassShare, VehicleClass},
+};
+use crate::prelude::*;
+use crate::utils::{get_random_vector_element, rng_from_seed};
+
+// Minimum number of nodes a generated route has to contain
+pub const MIN_ROUTE_NODES: usize = 5;
+
+/// Constraints for the trips of generated vehicles.
+#[derive(Debug, Clone, Copy)]
+pub struct TripConstraints {
+    /// Minimum length of a trip in meters.
+    pub min_length: f64,
+    /// Maximum length of a trip in meters.
+    pub max_length: f64,
+    /// Number of origin/destination pairs tried before giving up.
+    pub max_attempts: usize,
+}
+
+impl Default for TripConstraints {
+    fn default() -> Self {
+        TripConstraints {
+            min_length: 0.0,
+            max_length: DEFAULT_MAX_TRIP_LENGTH,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+}
+
+// Routing data of one vehicle class
+#[derive(Debug)]
+struct ClassRouting {
+    /// The vehicle class.
+    class: VehicleClass,
+    /// The roads the class may use, weighted by its routing preference.
+    graph: GraphMap<NodeId, f64, Directed>,
+    /// The nodes of the chosen component, origins and destinations are sampled from these.
+    nodes: Vec<NodeId>,
+    /// The landmarks guiding the route searches, plain A* searches are used without.
+    landmarks: Option<Landmarks>,
+    /// The distribution vehicle speeds are drawn from.
+    speeds: SpeedSampler,
+}
+
+/// Generates vehicles whose routes lie within one strongly connected component,
+/// so that every sampled origin can reach every sampled destination.
+/// The class of every vehicle is drawn from the configured class mix.
+#[derive(Debug)]
+pub struct VehicleGenerator<'a> {
+    /// The graph holding the edge lengths in meters.
+    graph: &'a OSMGraph,
+    /// The routing data of every class in the mix.
+    classes: Vec<ClassRouting>,
+    /// The distribution the class of a vehicle is drawn from.
+    mix: WeightedIndex<f64>,
+    /// The constraints every generated trip has to satisfy.
+    constraints: TripConstraints,
+    /// The maximum number of steps of every generated vehicle.
+    max_steps: Option<u64>,
+    /// The share of informed drivers, who re-plan their routes.
+    informed_share: f64,
+    /// The activity chains vehicles follow, if they make more than one trip.
+    chains: Option<TripChains>,
+    /// The random number generator classes, origins, destinations and speeds are drawn from.
+    rng: StdRng,
+}
+
+impl<'a> VehicleGenerator<'a> {
+    /// Creates a generator sampling from the `component`-th largest strongly connected
+    /// component of the roads of each class, where `0` is the largest one.
+    pub fn new(
+        osm_graph: &'a OSMGraph,
+        component: usize,
+        constraints: TripConstraints,
+        classes: Vec<ClassShare>,
+    ) -> Result<VehicleGenerator<'a>> {
+        if constraints.min_length > constraints.max_length {
+            return Err(Error::Config(format!(
+                "Minimum trip length {} exceeds maximum trip length {}",
+                constraints.min_length, constraints.max_length
+            )));
+        }
+
+        let mix = WeightedIndex::new(classes.iter().map(|c| c.share))
+            .map_err(|err| Error::Config(format!("Invalid class mix: {}", err)))?;
+
+        let mut routings = Vec::with_capacity(classes.len());
+        for ClassShare { class, speeds, .. } in classes {
+            let graph = osm_graph.class_graph(&class);
+            let components = strongly_connected_components(&graph);
+            let nodes = match components.into_iter().nth(component) {
+                Some(nodes) => nodes,
+                None => {
+                    return Err(Error::Graph(format!(
+                        "Roads of class {} have no strongly connected component with index {}",
+                        class.name, component
+                    )))
+                }
+            };
+            if nodes.len() < MIN_ROUTE_NODES {
+                return Err(Error::Graph(format!(
+                    "Component {} of class {} has {} node(s), but routes need at least {}",
+                    component,
+                    class.name,
+                    nodes.len(),
+                    MIN_ROUTE_NODES
+                )));
+            }
+
+            log::debug!(
+                "Sampling routes of class {} from component {} with {} of {} nodes",
+                class.name,
+                component,
+                nodes.len(),
+                osm_graph.node_count()
+            );
+            routings.push(ClassRouting {
+                class,
+                graph,
+                nodes,
+                landmarks: None,
+                speeds,
+            });
+        }
+
+        Ok(VehicleGenerator {
+            graph: osm_graph,
+            classes: routings,
+            mix,
+            constraints,
+            max_steps: None,
+            informed_share: 0.0,
+            chains: None,
+            rng: rng_from_seed(None),
+        })
+    }
+
+    /// Seeds the random number generator, making the generated vehicles reproducible.
+    pub fn with_seed(mut self, seed: Option<u64>) -> VehicleGenerator<'a> {
+        self.rng = rng_from_seed(seed);
+        self
+    }
+
+    /// Sets the maximum number of steps of every generated vehicle.
+    pub fn with_max_steps(mut self, max_steps: Option<u64>) -> VehicleGenerator<'a> {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets the share of informed drivers among the generated vehicles, between 0 and 1.
+    pub fn with_informed_share(mut self, share: f64) -> Result<VehicleGenerator<'a>> {
+        if !(0.0..=1.0).contains(&share) {
+            return Err(Error::Config(format!(
+                "Share of informed drivers must be between 0 and 1, but is {}",
+                share
+            )));
+        }
+        self.informed_share = share;
+        Ok(self)
+    }
+
+    /// Guides the route searches by `count` landmarks per class, or by none if `count` is 0.
+    /// The landmarks are read from and written to the cache directory, if there is one.
+    pub fn with_landmarks(
+        mut self,
+        count: usize,
+        cache: Option<&Path>,
+    ) -> Result<VehicleGenerator<'a>> {
+        for routing in &mut self.classes {
+            routing.landmarks = match (count, cache) {
+                (0, _) => None,
+                (count, Some(dir)) => Some(Landmarks::cached(
+                    dir,
+                    &routing.graph,
+                    &routing.nodes,
+                    count,
+                )?),
+                (count, None) => Some(Landmarks::new(&routing.graph, &routing.nodes, count)),
+            };
+        }
+        Ok(self)
+    }
+
+    /// Lets the generated vehicles follow activity chains, starting at the origin of their first trip.
+    pub fn with_trip_chains(mut self, chains: Option<TripChains>) -> VehicleGenerator<'a> {
+        self.chains = chains;
+        self
+    }
+
+    /// Generates a vehicle with a random class, route and speed.
+    /// Fails once `max_attempts` origin/destination pairs did not yield a valid trip.
+    pub fn generate(&mut self) -> Result<Vehicle> {
+        let mut rng = self.rng.clone();
+        let vehicle = self.sample(&mut rng, None);
+        self.rng = rng;
+        vehicle
+    }
+
+    /// Generates `count` vehicles with the identifiers from `first_id` on, in parallel.
+    /// Every vehicle draws from a random number generator of its own, seeded by this
+    /// generator and the vehicle's place in the batch, so the vehicles do not depend on
+    /// the number of threads. The identifiers must be reserved with `reserve_ids`.
+    pub fn generate_all(&mut self, first_id: u64, count: usize) -> Result<Vec<Vehicle>> {
+        self.generate_where(first_id, count, |_| true)
+    }
+
+    /// Generates the vehicles `generate_all` would, but only those whose first sampled
+    /// origin satisfies `keep`. Generators seeded alike thus split the vehicles between
+    /// them by origin, each one generating its share only.
+    pub fn generate_where(
+        &mut self,
+        first_id: u64,
+        count: usize,
+        keep: impl Fn(NodeId) -> bool + Sync,
+    ) -> Result<Vec<Vehicle>> {
+        let seed: u64 = self.rng.gen();
+        let generator = &*self;
+        (0..count)
+            .into_par_iter()
+            .filter_map(|i| {
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(i as u64));
+                if !keep(generator.first_origin(rng.clone())) {
+                    return None;
+                }
+                Some(generator.sample(&mut rng, Some(first_id + i as u64)))
+            })
+            .collect()
+    }
+
+    // Returns the origin the first attempt of `sample` draws with the given random number generator
+    fn first_origin(&self, mut rng: StdRng) -> NodeId {
+        let routing = &self.classes[self.mix.sample(&mut rng)];
+        routing.nodes[rng.gen_range(0..routing.nodes.len())]
+    }
+
+    // Draws a vehicle with a random class, route and speed and builds it with the given identifier
+    fn sample(&self, rng: &mut StdRng, id: Option<u64>) -> Result<Vehicle> {
+        let routing = &self.classes[self.mix.sample(rng)];
+
+        for _ in 0..self.constraints.max_attempts {
+            let start = match get_random_vector_element(rng, &routing.nodes) {
+                Some(v) => *v,
+                None => Err(Error::Graph(String::from("No random vertex found")))?,
+            };
+
+            let end = match get_random_vector_element(rng, &routing.nodes) {
+                Some(v) => *v,
+                None => Err(Error::Graph(String::from("No random vertex found")))?,
+            };
+
+            let path = match &routing.landmarks {
+                Some(landmarks) => landmarks.route(&routing.graph, start, end),
+                None => astar(
+                    &routing.graph,
+                    start,
+                    |finish| finish == end,
+                    |e| *e.2,
+                    |_| 0.,
+                ),
+            };
+            let path = match path {
+                Some(p) => p.1,
+                None => continue,
+            };
+
+            // trip length in meters, independent of the routing preference
+            let length: f64 = path
+                .windows(2)
+                .filter_map(|w| self.graph.edge_length(w[0], w[1]))
+                .sum();
+
+            if path.len() < MIN_ROUTE_NODES
+                || length < self.constraints.min_length
+                || length > self.constraints.max_length
+            {
+                continue;
+            }
+
+            let velocity = routing.speeds.sample(rng);
+            let informed = self.informed_share > 0.0 && rng.gen_bool(self.informed_share);
+            let activities = match &self.chains {
+                Some(chains) => chains.sample(rng, path[0], path[path.len() - 1], |rng| {
+                    routing.nodes[rng.gen_range(0..routing.nodes.len())]
+                }),
+                None => Vec::new(),
+            };
+
+            return VehicleBuilder::new()
+                .with_id(id)
+                .with_class(&routing.class)
+                .with_position(0.0)
+                .with_is_parked(false)
+                .with_speed(velocity)
+                .with_max_steps(self.max_steps)
+                .with_informed(informed)
+                .with_activities(activities)
+                .with_prev_id(path[0])
+                .with_next_id(path[1])
+                .with_path_ids(path)
+                .build();
+        }
+
+        Err(Error::Routing(format!(
+            "No {} trip between {} and {} meters found after {} attempts",
+            routing.class.name,
+            self.constraints.min_length,
+            self.constraints.max_length,
+            self.constraints.max_attempts
+        )))
+    }
+}
+
+// Unit tests for the vehicle generator
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::edge,
+        graph::osm_graph::Osmid,
+        models::{
+            graph_input::{Edge, Graph},
+            speed::SpeedDistribution,
+        },
+    };
+
+    fn cars() -> Vec<ClassShare> {
+        vec![share(VehicleClass::car(), 1.0)]
+    }
+
+    fn share(class: VehicleClass, share: f64) -> ClassShare {
+        let speeds = class
+            .speed_sampler(&SpeedDistribution::Uniform, 2.0, 13.8)
+            .unwrap();
+        ClassShare {
+            class,
+            share,
+            speeds,
+        }
+    }
+
+    // An edge of 100m on a road of the given type
+    fn road(from: Osmid, to: Osmid, highway: &str) -> Edge {
+        Edge {
+            highway: Some(String::from(highway)),
+            ..edge(from, to, 100.0)
+        }
+    }
+
+    // A ring 1 -> 2 -> ... -> 6 -> 1 with 100m edges and a dead end 6 -> 7
+    fn ring() -> OSMGraph {
+        let mut edges: Vec<Edge> = (1..6).map(|i| road(i, i + 1, "primary")).collect();
+        edges.push(road(6, 1, "primary"));
+        edges.push(road(6, 7, "primary"));
+        OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_routes_stay_in_component() {
+        let graph = ring();
+        let mut generator =
+            VehicleGenerator::new(&graph, 0, TripConstraints::default(), cars()).unwrap();
+        let dead_end = graph.node(7).unwrap();
+
+        for _ in 0..20 {
+            let v = generator.generate().unwrap();
+            assert!(v.path_ids.len() >= MIN_ROUTE_NODES);
+            assert!(!v.path_ids.contains(&dead_end));
+        }
+    }
+
+    #[test]
+    fn test_trip_length_bounds() {
+        let graph = ring();
+        let constraints = TripConstraints {
+            min_length: 450.0,
+            max_length: 550.0,
+            max_attempts: 10_000,
+        };
+        let mut generator = VehicleGenerator::new(&graph, 0, constraints, cars()).unwrap();
+
+        let v = generator.generate().unwrap();
+        assert_eq!(v.path_ids.len(), 6);
+    }
+
+    #[test]
+    fn test_unsatisfiable_constraints_fail() {
+        let graph = ring();
+        let constraints = TripConstraints {
+            min_length: 10_000.0,
+            max_length: f64::INFINITY,
+            max_attempts: 50,
+        };
+        let mut generator = VehicleGenerator::new(&graph, 0, constraints, cars()).unwrap();
+
+        assert!(matches!(generator.generate(), Err(Error::Routing(_))));
+        assert!(VehicleGenerator::new(&graph, 5, TripConstraints::default(), cars()).is_err());
+    }
+
+    #[test]
+    fn test_seeded_generation_is_reproducible() {
+        let graph = ring();
+        let routes = |seed| {
+            let mut generator =
+                VehicleGenerator::new(&graph, 0, TripConstraints::default(), cars())
+                    .unwrap()
+                    .with_seed(Some(seed));
+            (0..10)
+                .map(|_| {
+                    let v = generator.generate().unwrap();
+                    (v.path_ids, v.speed)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(routes(42), routes(42));
+        assert_ne!(routes(42), routes(43));
+    }
+
+    #[test]
+    fn test_parallel_generation_ignores_the_thread_count() {
+        let graph = ring();
+        let routes = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let mut generator =
+                VehicleGenerator::new(&graph, 0, TripConstraints::default(), cars())
+                    .unwrap()
+                    .with_seed(Some(42));
+            pool.install(|| generator.generate_all(100, 50))
+                .unwrap()
+                .into_iter()
+                .map(|v| (v.id, v.path_ids, v.speed))
+                .collect::<Vec<_>>()
+        };
+
+        let single = routes(1);
+        assert_eq!(single, routes(4));
+        assert!(single.iter().map(|r| r.0).eq(100..150));
+    }
+
+    #[test]
+    fn test_vehicles_are_split_by_origin() {
+        let graph = ring();
+        let split_at = graph.node(3).unwrap();
+        let generate = |keep: &(dyn Fn(NodeId) -> bool + Sync)| {
+            VehicleGenerator::new(&graph, 0, TripConstraints::default(), cars())
+                .unwrap()
+                .with_seed(Some(5))
+                .generate_where(0, 40, keep)
+                .unwrap()
+        };
+
+        let all = generate(&|_| true);
+        let mut split = generate(&|n| n <= split_at);
+        split.extend(generate(&|n| n > split_at));
+        split.sort_by_key(|v| v.id);
+        assert_eq!(split.len(), all.len());
+        for (a, b) in all.iter().zip(&split) {
+            assert_eq!((a.id, &a.path_ids, a.speed), (b.id, &b.path_ids, b.speed));
+        }
+    }
+
+    #[test]
+    fn test_landmarks_find_the_same_routes() {
+        let graph = ring();
+        let routes = |landmarks| {
+            let mut generator =
+                VehicleGenerator::new(&graph, 0, TripConstraints::default(), cars())
+                    .unwrap()
+                    .with_seed(Some(7))
+                    .with_landmarks(landmarks, None)
+                    .unwrap();
+            (0..20)
+                .map(|_| generator.generate().unwrap().path_ids)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(routes(0), routes(3));
+    }
+
+    #[test]
+    fn test_classes_follow_their_roads() {
+        // two rings sharing node 1: a primary road ring and a residential ring
+        let mut edges: Vec<Edge> = (1..6).map(|i| road(i, i + 1, "primary")).collect();
+        edges.push(road(6, 1, "primary"));
+        edges.push(road(1, 11, "residential"));
+        edges.extend((11..16).map(|i| road(i, i + 1, "residential")));
+        edges.push(road(16, 1, "residential"));
+        let graph = OSMGraph::new(Graph {
+            vertices: Vec::new(),
+            edges,
+        })
+        .unwrap();
+
+        let classes = vec![
+            share(VehicleClass::truck(), 1.0),
+            share(VehicleClass::bicycle(), 1.0),
+        ];
+        let mut generator = VehicleGenerator::new(&graph, 0, TripConstraints::default(), classes)
+            .unwrap()
+            .with_seed(Some(1));
+
+        let mut seen = Vec::new();
+        for _ in 0..50 {
+            let v = generator.generate().unwrap();
+            if v.class == "truck" {
+                assert!(v.path_ids.iter().all(|id| graph.osm_id(*id) < 10));
+                assert_eq!(v.length, 12.0);
+            } else {
+                assert!(v.speed <= VehicleClass::bicycle().max_speed);
+            }
+            seen.push(v.class);
+        }
+        assert!(seen.iter().any(|c| c == "truck") && seen.iter().any(|c| c == "bicycle"));
+    }
+}
@@ -0,0 +1,711 @@
+//! Runs the simulation on a single process, without MPI.
+//! A `Simulation` either runs to the end at once or is advanced step by step,
+//! with observers receiving the events of every vehicle.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::Parallelism,
+    config::{
+        Scenario, DEFAULT_CONGESTION_THRESHOLD, DEFAULT_CONVERGENCE_GAP, DEFAULT_KEEP_RIGHT_BIAS,
+        DEFAULT_LANE_CHANGE_THRESHOLD, DEFAULT_LOGIT_SCALE, DEFAULT_MAX_ITERATIONS,
+        DEFAULT_MAX_SPEED, DEFAULT_MIN_SPEED, DEFAULT_NUM_VEHICLES, DEFAULT_POLITENESS,
+        DEFAULT_REROUTE_INTERVAL, DEFAULT_SAFE_DECELERATION,
+    },
+    graph::osm_graph::{OSMGraph, Osmid},
+    models::{
+        activity::TripChains,
+        assignment::{Assignment, AssignmentOptions},
+        incident::{Event, Schedule},
+        intersection::Intersections,
+        lane_change::MobilParameters,
+        observer::Observer,
+        parking::Parking,
+        rerouting::{ReroutingOptions, Router},
+        speed::SpeedDistribution,
+        traffic::Traffic,
+        transit::{TransitFeed, TransitStatistics},
+        vehicle::Vehicle,
+        vehicle_builder::reserve_ids,
+        vehicle_class::{class_shares, record_vehicle, ClassShare, ClassStatistics, VehicleClass},
+        vehicle_generator::{TripConstraints, VehicleGenerator},
+    },
+    prelude::*,
+};
+
+/// Steps in which no vehicle on the road moves, after which a run is given up as gridlocked.
+pub const GRIDLOCK_STEPS: u64 = 3600;
+
+// Speed in m/s below which a vehicle counts as standing
+const STANDSTILL_SPEED: f64 = 0.1;
+
+/// Results of a run.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Generated vehicles that finished, at their destination or out of steps.
+    pub finished: usize,
+    /// Generated vehicles that never finished, because they left the graph
+    /// or were still on the road when the run was given up.
+    pub lost: usize,
+    /// Steps taken by all generated vehicles.
+    pub steps: u64,
+    /// Steps the simulation took.
+    pub time: u64,
+    /// Results of the generated vehicles per class.
+    pub classes: HashMap<String, ClassStatistics>,
+    /// Schedule adherence of the transit lines.
+    pub transit: TransitStatistics,
+    /// Every run of the traffic assignment, empty without one.
+    pub assignment: Vec<Iteration>,
+    /// Whether the run was given up because the vehicles on the road stopped moving.
+    pub gridlocked: bool,
+}
+
+/// A run of the traffic assignment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Iteration {
+    /// The number of the run, starting at 1.
+    pub iteration: usize,
+    /// Steps taken by all generated vehicles.
+    pub steps: u64,
+    /// Relative gap of the run.
+    pub gap: f64,
+}
+
+/// Configures a `Simulation` on a graph.
+/// Without further settings, it drives one car at 8.5 to 13.8 m/s on the largest component,
+/// with intersections controlled as tagged in the graph.
+#[derive(Debug)]
+pub struct SimulationBuilder<'a> {
+    /// The graph the vehicles drive on.
+    graph: &'a OSMGraph,
+    /// The number of generated vehicles.
+    num_vehicles: usize,
+    /// The strongly connected component trips are sampled from.
+    component: usize,
+    /// The constraints every generated trip has to satisfy.
+    constraints: TripConstraints,
+    /// The number of landmarks guiding the route searches of the vehicle generation.
+    landmarks: usize,
+    /// The directory the landmarks are cached in.
+    landmark_cache: Option<PathBuf>,
+    /// The vehicle classes and their shares, a car only if empty.
+    classes: Vec<ClassShare>,
+    /// The seed of the generated vehicles and route choices.
+    seed: Option<u64>,
+    /// The maximum number of steps of every generated vehicle.
+    max_steps: Option<u64>,
+    /// The share of informed drivers.
+    informed_share: f64,
+    /// When informed drivers re-plan their routes.
+    rerouting: ReroutingOptions,
+    /// The parameters of the lane-change model.
+    lane_change: MobilParameters,
+    /// Whether the vehicles are advanced on multiple threads.
+    parallel: bool,
+    /// The controllers of the intersections, derived from the graph if missing.
+    intersections: Option<Intersections>,
+    /// The events changing the road network.
+    schedule: Schedule,
+    /// The parking spaces at the destinations.
+    parking: Parking,
+    /// The activity chains the generated vehicles follow.
+    trip_chains: Option<TripChains>,
+    /// The transit lines.
+    transit: TransitFeed,
+    /// The settings of the traffic assignment, if there is one.
+    assignment: Option<AssignmentOptions>,
+}
+
+impl<'a> SimulationBuilder<'a> {
+    /// Creates a builder for a simulation of a scenario on the given graph.
+    /// The input file of the scenario is ignored, and its other files are loaded.
+    pub fn from_scenario(
+        graph: &'a OSMGraph,
+        scenario: &Scenario,
+    ) -> Result<SimulationBuilder<'a>> {
+        let vehicle_classes = scenario.vehicle_classes.clone().unwrap_or_default();
+        let speed_distribution = SpeedDistribution::from_kind(
+            scenario.speed_distribution.unwrap_or_default(),
+            scenario.speed_mean,
+            scenario.speed_std_dev,
+            scenario.speed_histogram.clone(),
+        )?;
+        let classes = class_shares(
+            scenario.class_mix.as_deref(),
+            &vehicle_classes,
+            &speed_distribution,
+            scenario.min_speed.unwrap_or(DEFAULT_MIN_SPEED),
+            scenario.max_speed.unwrap_or(DEFAULT_MAX_SPEED),
+        )?;
+        let defaults = TripConstraints::default();
+        let events = scenario.events.clone().unwrap_or_default();
+
+        Ok(SimulationBuilder::new(graph)
+            .with_vehicles(scenario.num_vehicles.unwrap_or(DEFAULT_NUM_VEHICLES))
+            .with_component(scenario.component.unwrap_or_default())
+            .with_trip_constraints(TripConstraints {
+                min_length: scenario.min_trip_length.unwrap_or(defaults.min_length),
+                max_length: scenario.max_trip_length.unwrap_or(defaults.max_length),
+                max_attempts: scenario.max_attempts.unwrap_or(defaults.max_attempts),
+            })
+            .with_landmarks(
+                scenario.landmarks.unwrap_or_default(),
+                scenario.landmark_cache.clone(),
+            )
+            .with_classes(classes)
+            .with_seed(scenario.seed)
+            .with_max_steps(scenario.duration)
+            .with_informed_share(scenario.informed_share.unwrap_or_default())
+            .with_rerouting(ReroutingOptions {
+                interval: scenario
+                    .reroute_interval
+                    .unwrap_or(DEFAULT_REROUTE_INTERVAL),
+                congestion_threshold: scenario
+                    .congestion_threshold
+                    .unwrap_or(DEFAULT_CONGESTION_THRESHOLD),
+            })
+            .with_lane_change(MobilParameters {
+                politeness: scenario.politeness.unwrap_or(DEFAULT_POLITENESS),
+                threshold: scenario
+                    .lane_change_threshold
+                    .unwrap_or(DEFAULT_LANE_CHANGE_THRESHOLD),
+                safe_deceleration: scenario
+                    .safe_deceleration
+                    .unwrap_or(DEFAULT_SAFE_DECELERATION),
+                keep_right_bias: scenario.keep_right_bias.unwrap_or(DEFAULT_KEEP_RIGHT_BIAS),
+            })
+            .with_parallel(scenario.parallelism == Some(Parallelism::MultiThreaded))
+            .with_intersections(load_intersections(
+                graph,
+                scenario.intersections.as_deref(),
+            )?)
+            .with_schedule(load_schedule(
+                graph,
+                scenario.event_file.as_deref(),
+                events,
+            )?)
+            .with_parking(load_parking(graph, scenario.parking.as_deref())?)
+            .with_trip_chains(load_trip_chains(scenario.trip_chains.as_deref())?)
+            .with_transit(load_transit(
+                graph,
+                scenario.transit.as_deref(),
+                &vehicle_classes,
+            )?)
+            .with_assignment(scenario.assign.map(|method| AssignmentOptions {
+                method,
+                max_iterations: scenario.max_iterations.unwrap_or(DEFAULT_MAX_ITERATIONS),
+                convergence_gap: scenario.convergence_gap.unwrap_or(DEFAULT_CONVERGENCE_GAP),
+                logit_scale: scenario.logit_scale.unwrap_or(DEFAULT_LOGIT_SCALE),
+            })))
+    }
+
+    /// Creates a builder for a simulation on the given graph.
+    pub fn new(graph: &'a OSMGraph) -> SimulationBuilder<'a> {
+        SimulationBuilder {
+            graph,
+            num_vehicles: DEFAULT_NUM_VEHICLES,
+            component: 0,
+            constraints: TripConstraints::default(),
+            landmarks: 0,
+            landmark_cache: None,
+            classes: Vec::new(),
+            seed: None,
+            max_steps: None,
+            informed_share: 0.0,
+            rerouting: ReroutingOptions {
+                interval: DEFAULT_REROUTE_INTERVAL,
+                congestion_threshold: DEFAULT_CONGESTION_THRESHOLD,
+            },
+            lane_change: MobilParameters::default(),
+            parallel: false,
+            intersections: None,
+            schedule: Schedule::default(),
+            parking: Parking::default(),
+            trip_chains: None,
+            transit: TransitFeed::default(),
+            assignment: None,
+        }
+    }
+
+    /// Sets the number of generated vehicles.
+    pub fn with_vehicles(mut self, num_vehicles: usize) -> SimulationBuilder<'a> {
+        self.num_vehicles = num_vehicles;
+        self
+    }
+
+    /// Sets the strongly connected component trips are sampled from, `0` being the largest.
+    pub fn with_component(mut self, component: usize) -> SimulationBuilder<'a> {
+        self.component = component;
+        self
+    }
+
+    /// Sets the constraints every generated trip has to satisfy.
+    pub fn with_trip_constraints(mut self, constraints: TripConstraints) -> SimulationBuilder<'a> {
+        self.constraints = constraints;
+        self
+    }
+
+    /// Guides the route searches of the vehicle generation by `count` landmarks per class,
+    /// cached in the given directory. Plain A* searches are used if `count` is 0.
+    pub fn with_landmarks(mut self, count: usize, cache: Option<PathBuf>) -> SimulationBuilder<'a> {
+        self.landmarks = count;
+        self.landmark_cache = cache;
+        self
+    }
+
+    /// Sets the vehicle classes and their shares of the generated vehicles.
+    pub fn with_classes(mut self, classes: Vec<ClassShare>) -> SimulationBuilder<'a> {
+        self.classes = classes;
+        self
+    }
+
+    /// Seeds the generated vehicles and route choices, making the run reproducible.
+    pub fn with_seed(mut self, seed: Option<u64>) -> SimulationBuilder<'a> {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the maximum number of steps of every generated vehicle.
+    pub fn with_max_steps(mut self, max_steps: Option<u64>) -> SimulationBuilder<'a> {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets the share of informed drivers, who re-plan their routes by the current travel times.
+    pub fn with_informed_share(mut self, share: f64) -> SimulationBuilder<'a> {
+        self.informed_share = share;
+        self
+    }
+
+    /// Sets when informed drivers re-plan their routes.
+    pub fn with_rerouting(mut self, options: ReroutingOptions) -> SimulationBuilder<'a> {
+        self.rerouting = options;
+        self
+    }
+
+    /// Sets the parameters of the lane-change model.
+    pub fn with_lane_change(mut self, params: MobilParameters) -> SimulationBuilder<'a> {
+        self.lane_change = params;
+        self
+    }
+
+    /// Sets whether the vehicles are advanced on multiple threads.
+    pub fn with_parallel(mut self, parallel: bool) -> SimulationBuilder<'a> {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Sets the controllers of the intersections.
+    pub fn with_intersections(mut self, intersections: Intersections) -> SimulationBuilder<'a> {
+        self.intersections = Some(intersections);
+        self
+    }
+
+    /// Sets the events changing the road network during the run.
+    pub fn with_schedule(mut self, schedule: Schedule) -> SimulationBuilder<'a> {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Sets the parking spaces at the destinations.
+    pub fn with_parking(mut self, parking: Parking) -> SimulationBuilder<'a> {
+        self.parking = parking;
+        self
+    }
+
+    /// Lets the generated vehicles follow activity chains.
+    pub fn with_trip_chains(mut self, chains: Option<TripChains>) -> SimulationBuilder<'a> {
+        self.trip_chains = chains;
+        self
+    }
+
+    /// Sets the transit lines running during the simulation.
+    pub fn with_transit(mut self, transit: TransitFeed) -> SimulationBuilder<'a> {
+        self.transit = transit;
+        self
+    }
+
+    /// Repeats the run until the drivers' route choices reach an equilibrium.
+    pub fn with_assignment(mut self, options: Option<AssignmentOptions>) -> SimulationBuilder<'a> {
+        self.assignment = options;
+        self
+    }
+
+    /// Checks the settings, generates the vehicles and creates the simulation.
+    pub fn build(self) -> Result<Simulation<'a>> {
+        let classes = if self.classes.is_empty() {
+            let car = VehicleClass::car();
+            let speeds = car.speed_sampler(
+                &SpeedDistribution::Uniform,
+                DEFAULT_MIN_SPEED,
+                DEFAULT_MAX_SPEED,
+            )?;
+            vec![ClassShare {
+                class: car,
+                share: 1.0,
+                speeds,
+            }]
+        } else {
+            self.classes
+        };
+        let intersections = match self.intersections {
+            Some(intersections) => intersections,
+            None => Intersections::load(self.graph, None)?,
+        };
+        let mut generator = VehicleGenerator::new(
+            self.graph,
+            self.component,
+            self.constraints,
+            classes.clone(),
+        )?
+        .with_seed(self.seed)
+        .with_max_steps(self.max_steps)
+        .with_informed_share(self.informed_share)?
+        .with_trip_chains(self.trip_chains.clone())
+        .with_landmarks(self.landmarks, self.landmark_cache.as_deref())?;
+        let pending = generator.generate_all(reserve_ids(self.num_vehicles), self.num_vehicles)?;
+
+        let mut simulation = Simulation {
+            graph: self.graph,
+            classes,
+            seed: self.seed,
+            informed_share: self.informed_share,
+            rerouting: self.rerouting,
+            lane_change: self.lane_change,
+            parallel: self.parallel,
+            intersections,
+            schedule: self.schedule,
+            parking: self.parking,
+            planned: self.trip_chains.is_some(),
+            transit: self.transit,
+            assignment: self.assignment,
+            pending,
+            traffic: Traffic::new(self.graph),
+            time: 0,
+            report: Report::default(),
+            observers: Vec::new(),
+        };
+        simulation.traffic = simulation.new_traffic();
+        Ok(simulation)
+    }
+}
+
+/// A simulation of generated vehicles and transit lines on a graph, run on a single process.
+/// The generated vehicles enter the road in the first step, the buses at their departures.
+pub struct Simulation<'a> {
+    /// The graph the vehicles drive on.
+    graph: &'a OSMGraph,
+    /// The vehicle classes and their shares.
+    classes: Vec<ClassShare>,
+    /// The seed of the route choices of the traffic assignment.
+    seed: Option<u64>,
+    /// The share of informed drivers.
+    informed_share: f64,
+    /// When informed drivers re-plan their routes.
+    rerouting: ReroutingOptions,
+    /// The parameters of the lane-change model.
+    lane_change: MobilParameters,
+    /// Whether the vehicles are advanced on multiple threads.
+    parallel: bool,
+    /// The controllers of the intersections.
+    intersections: Intersections,
+    /// The events changing the road network.
+    schedule: Schedule,
+    /// The parking spaces at the destinations.
+    parking: Parking,
+    /// Whether vehicles plan their trips between activities.
+    planned: bool,
+    /// The transit lines.
+    transit: TransitFeed,
+    /// The settings of the traffic assignment, if there is one.
+    assignment: Option<AssignmentOptions>,
+    /// The generated vehicles that did not enter the road yet.
+    pending: Vec<Vehicle>,
+    /// The vehicles on the road.
+    traffic: Traffic<'a>,
+    /// The number of steps taken.
+    time: u64,
+    /// Results of the vehicles that finished.
+    report: Report,
+    /// Receive the events of the simulation.
+    observers: Vec<Box<dyn Observer + 'a>>,
+}
+
+impl<'a> Simulation<'a> {
+    /// Starts configuring a simulation on the given graph.
+    pub fn builder(graph: &'a OSMGraph) -> SimulationBuilder<'a> {
+        SimulationBuilder::new(graph)
+    }
+
+    /// Creates the simulation of a scenario on the given graph.
+    pub fn new(graph: &'a OSMGraph, scenario: &Scenario) -> Result<Simulation<'a>> {
+        SimulationBuilder::from_scenario(graph, scenario)?.build()
+    }
+
+    /// Attaches an observer, which receives every following event.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer + 'a>) {
+        self.observers.push(observer);
+    }
+
+    /// Returns the number of steps taken.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Returns the vehicles on the road.
+    pub fn traffic(&self) -> &Traffic<'a> {
+        &self.traffic
+    }
+
+    /// Returns the results of the vehicles that finished so far.
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    /// Returns whether all vehicles and buses finished.
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.traffic.is_empty() && self.transit.is_done(self.time)
+    }
+
+    /// Puts the generated vehicles and the departing buses on the road
+    /// and advances all vehicles by one step.
+    pub fn step(&mut self) -> Result<()> {
+        let departures = self.transit.depart(self.time)?;
+        for v in std::mem::take(&mut self.pending)
+            .into_iter()
+            .chain(departures)
+        {
+            self.observers.on_spawn(self.time, &v);
+            self.observers
+                .on_edge_entry(self.time, &v, (v.prev_id, v.next_id));
+            self.traffic.insert(v);
+        }
+
+        let outcome = self.traffic.step_observed(&mut self.observers)?;
+        self.time += 1;
+        self.report.time = self.time;
+        for v in &outcome.finished {
+            if v.transit.is_some() {
+                self.report.transit.record(v);
+                continue;
+            }
+            self.report.finished += 1;
+            self.report.steps += v.steps;
+            record_vehicle(&mut self.report.classes, v);
+        }
+        // the whole graph is simulated here, so there is nowhere to hand leaving vehicles over to
+        for v in &outcome.left {
+            if v.transit.is_some() {
+                self.report.transit.record(v);
+                continue;
+            }
+            self.report.lost += 1;
+        }
+        Ok(())
+    }
+
+    /// Advances the simulation until the given step or until all vehicles finished.
+    pub fn run_until(&mut self, time: u64) -> Result<()> {
+        while self.time < time && !self.is_done() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Drives the vehicles until all of them finished.
+    /// With a traffic assignment, the simulation is repeated until the route choices
+    /// reach an equilibrium, and the observers receive the events of every run.
+    /// A run in which no vehicle on the road moves for `GRIDLOCK_STEPS` steps is given up,
+    /// and the report is marked as gridlocked.
+    pub fn run(mut self) -> Result<Report> {
+        match self.assignment {
+            Some(options) => self.run_assignment(options),
+            None => {
+                self.run_to_end()?;
+                Ok(self.report)
+            }
+        }
+    }
+
+    // Advances the simulation until all vehicles finished,
+    // or until the vehicles on the road stood still for `GRIDLOCK_STEPS` steps
+    fn run_to_end(&mut self) -> Result<()> {
+        let mut standing = 0;
+        while !self.is_done() {
+            self.step()?;
+            let vehicles = self.traffic.vehicles();
+            if vehicles.iter().any(|v| v.velocity >= STANDSTILL_SPEED) || vehicles.is_empty() {
+                standing = 0;
+                continue;
+            }
+            standing += 1;
+            if standing >= GRIDLOCK_STEPS {
+                log::warn!(
+                    "No vehicle moved for {} steps, giving up with {} vehicle(s) on the road",
+                    standing,
+                    vehicles.len()
+                );
+                let buses = vehicles.iter().filter(|v| v.transit.is_some()).count();
+                self.report.lost += self.pending.len() + self.traffic.len() - buses;
+                self.report.gridlocked = true;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Creates the traffic on the graph, before any vehicle entered it
+    fn new_traffic(&self) -> Traffic<'a> {
+        let traffic = Traffic::new(self.graph)
+            .with_parallel(self.parallel)
+            .with_intersections(self.intersections.clone())
+            .with_schedule(self.schedule.clone())
+            .with_parking(self.parking.clone())
+            .with_lane_change(self.lane_change);
+        let planned = self.planned || !self.schedule.is_empty();
+        match make_router(
+            self.graph,
+            &self.classes,
+            self.informed_share,
+            planned,
+            self.rerouting,
+        ) {
+            Some(router) => traffic.with_router(router),
+            None => traffic,
+        }
+    }
+
+    // Runs the simulation repeatedly, letting the drivers choose their routes from the travel
+    // times of the previous run, until the relative gap is small enough
+    fn run_assignment(mut self, options: AssignmentOptions) -> Result<Report> {
+        let router = Router::new(
+            self.graph,
+            self.classes.iter().map(|c| &c.class),
+            self.rerouting,
+        );
+        let mut assignment = Assignment::new(router, options).with_seed(self.seed);
+        let mut demand = std::mem::take(&mut self.pending);
+        let mut iterations = Vec::new();
+        for iteration in 1..=options.max_iterations.max(1) {
+            self.traffic = self.new_traffic().with_link_times();
+            self.time = 0;
+            self.report = Report::default();
+            self.pending = demand.clone();
+            self.run_to_end()?;
+            if self.report.gridlocked {
+                log::warn!(
+                    "Stopping the assignment after the gridlocked iteration {}",
+                    iteration
+                );
+                break;
+            }
+
+            let times = self.traffic.link_times().cloned().unwrap_or_default();
+            let gap = assignment.iterate(&mut demand, &times);
+            log::info!(
+                "Iteration {}: {} steps, relative gap {:.4}",
+                iteration,
+                self.report.steps,
+                gap
+            );
+            iterations.push(Iteration {
+                iteration,
+                steps: self.report.steps,
+                gap,
+            });
+
+            if gap <= options.convergence_gap {
+                log::info!("Assignment converged after {} iteration(s)", iteration);
+                break;
+            }
+            if iteration == options.max_iterations {
+                log::warn!(
+                    "Assignment did not converge after {} iteration(s)",
+                    iteration
+                );
+            }
+        }
+        self.report.assignment = iterations;
+        Ok(self.report)
+    }
+}
+
+// Creates the router of the informed drivers, of the detours around closures and of the
+// trips between activities, if any of them is needed
+pub(crate) fn make_router<'a>(
+    osm_graph: &'a OSMGraph,
+    classes: &[ClassShare],
+    informed_share: f64,
+    planned: bool,
+    options: ReroutingOptions,
+) -> Option<Router<'a>> {
+    (informed_share > 0.0 || planned)
+        .then(|| Router::new(osm_graph, classes.iter().map(|c| &c.class), options))
+}
+
+// Loads the intersection controllers of the whole graph
+pub(crate) fn load_intersections(
+    osm_graph: &OSMGraph,
+    file: Option<&Path>,
+) -> Result<Intersections> {
+    let intersections = Intersections::load(osm_graph, file)?;
+    if !intersections.is_empty() {
+        log::info!("Controlling {} intersection(s)", intersections.len());
+    }
+    Ok(intersections)
+}
+
+// Loads the parking spaces at the destinations
+pub(crate) fn load_parking(osm_graph: &OSMGraph, file: Option<&Path>) -> Result<Parking> {
+    let parking = Parking::load(osm_graph, file)?;
+    if file.is_some() {
+        log::info!(
+            "Drivers search for parking for up to {}s",
+            parking.max_search
+        );
+    }
+    Ok(parking)
+}
+
+// Loads the transit lines and their timetables
+pub(crate) fn load_transit(
+    osm_graph: &OSMGraph,
+    file: Option<&Path>,
+    classes: &[VehicleClass],
+) -> Result<TransitFeed> {
+    let transit = TransitFeed::load(osm_graph, file, classes)?;
+    if !transit.is_empty() {
+        log::info!(
+            "Running {} trip(s) on {} transit line(s)",
+            transit.trips(),
+            transit.len()
+        );
+    }
+    Ok(transit)
+}
+
+// Loads the activity chains of the generated vehicles
+pub(crate) fn load_trip_chains(file: Option<&Path>) -> Result<Option<TripChains>> {
+    let chains = file.map(TripChains::load).transpose()?;
+    if let Some(file) = file {
+        log::info!("Vehicles follow the activity chains of {}", file.display());
+    }
+    Ok(chains)
+}
+
+// Loads the events changing the road network
+pub(crate) fn load_schedule(
+    osm_graph: &OSMGraph,
+    file: Option<&Path>,
+    events: Vec<Event<Osmid>>,
+) -> Result<Schedule> {
+    let schedule = Schedule::load(osm_graph, file, events)?;
+    if !schedule.is_empty() {
+        log::info!("Scheduled {} event(s)", schedule.len());
+    }
+    Ok(schedule)
+}
@@ -1,16 +1,15 @@
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::prelude::Result;
-
-// Message content interface for inter MPI communication
-pub trait MpiMessageContent<T> {
-    fn to_bytes(data: T) -> Result<Vec<u8>>;
-    fn from_bytes(data: Vec<u8>) -> Result<T>;
+// Creates a random number generator, seeded if a seed is given
+pub fn rng_from_seed(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
 }
 
 // Get a random element from a vector
-pub fn get_random_vector_element<T>(v: &Vec<T>) -> Option<&T> {
-    let mut rng = rand::thread_rng();
+pub fn get_random_vector_element<'a, T, R: Rng>(rng: &mut R, v: &'a [T]) -> Option<&'a T> {
     let random_index = rng.gen_range(0..v.len());
     v.get(random_index)
 }
@@ -18,7 +17,6 @@ pub fn get_random_vector_element<T>(v: &Vec<T>) -> Option<&T> {
 pub const MAX_NUMBER_OF_VEHICLES: usize = usize::MAX / 2;
 
 // Get a random number between min and max, representing velocity in m/s
-pub fn random_velocity(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
+pub fn random_velocity<R: Rng>(rng: &mut R, min: f64, max: f64) -> f64 {
     rng.gen_range(min..=max)
 }
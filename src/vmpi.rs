@@ -1,74 +1,364 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, mem::size_of, time::Instant};
 
 use bincode::{deserialize, serialize};
-use mpi::topology::SystemCommunicator;
-use mpi::traits::*;
+use mpi::{topology::SystemCommunicator, traits::*};
 use serde::{Deserialize, Serialize};
 
-use crate::models::vehicle::Vehicle;
+use crate::graph::osm_graph::NodeId;
+use crate::metrics::{MessageMetrics, RankMetrics};
+use crate::models::{
+    handoff::{Handoff, WireVehicle},
+    traffic::EdgeTail,
+    vehicle::Vehicle,
+};
 use crate::prelude::*;
-use crate::{graph::osm_graph::Osmid, utils::MpiMessageContent};
 
-// Root to leaf vehicle sending tag
-pub const ROOT_LEAF_VEHICLE: i32 = 1;
+/// Version of the message protocol, raised whenever a message changes.
+/// Ranks running binaries with different versions refuse to work together.
+pub const PROTOCOL_VERSION: u32 = 7;
 
-// Leaf to root vehicle sending tag
-pub const LEAF_ROOT_VEHICLE: i32 = 2;
+// Tag of every message between the ranks, the header tells them apart
+const MESSAGE_TAG: i32 = 1;
 
-// Leaf asks root for edge length
-pub const EDGE_LENGTH_REQUEST: i32 = 3;
+pub const ROOT_RANK: i32 = 0;
+
+/// Precedes every message. Its layout must stay the same across protocol versions,
+/// so that the version of a message can be read before the rest of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    /// Protocol version of the sender.
+    pub version: u32,
+    /// Rank of the sender.
+    pub sender: i32,
+    /// Number of messages the sender sent to the receiver before this one.
+    pub sequence: u64,
+}
 
-// Root responds to leaf with edge length
-pub const EDGE_LENGTH_RESPONSE: i32 = 4;
+/// Sent by the root to every leaf at the start of a step.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TickUpdate {
+    /// Vehicles entering the partition of the leaf.
+    pub vehicles: Handoff,
+    /// Last vehicles on the edges other ranks simulate behind the leaf's boundary.
+    pub tails: Vec<EdgeTail>,
+    /// Number of steps taken before this one, keeping scheduled events in sync.
+    pub time: u64,
+}
 
-// Leaf to Root vehicle finishng notification
-pub const LEAF_ROOT_VEHICLE_FINISH: i32 = 5;
+/// Sent by every leaf to the root at the end of a step.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TickReport {
+    /// Vehicles whose route continues in another partition.
+    pub left: Handoff,
+    /// Vehicles that reached their destination or used up their steps.
+    pub finished: Handoff,
+    /// Last vehicles on the edges leading into the leaf's partition.
+    pub tails: Vec<EdgeTail>,
+}
 
-// Root to leaf program termination notification
-pub const ROOT_LEAF_TERMINATE: i32 = 6;
+/// Sent by the root to every leaf before the first step, if the leafs generate the vehicles.
+/// Every leaf generates the vehicles starting in its partition among the `count` vehicles
+/// with the identifiers from `first_id` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Generation {
+    /// Seed shared by the generators of all leafs.
+    pub seed: u64,
+    /// Identifier of the first vehicle.
+    pub first_id: u64,
+    /// Number of vehicles generated by all leafs together.
+    pub count: usize,
+}
 
-pub const ROOT_RANK: i32 = 0;
+/// Every message exchanged between the ranks.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Message {
+    /// Exchanged at start-up, before any other message.
+    Hello,
+    /// Lets the leafs generate the vehicles.
+    Generate(Generation),
+    /// Starts a step on a leaf.
+    TickUpdate(TickUpdate),
+    /// Ends a step on a leaf.
+    TickReport(TickReport),
+    /// Ends the simulation.
+    Terminate,
+    /// Sent by every leaf to the root after the simulation ended.
+    Metrics(RankMetrics),
+}
+
+impl Message {
+    /// Encodes the message behind the header.
+    pub fn encode(&self, header: Header) -> Result<Vec<u8>> {
+        Ok(serialize(&(header, self))?)
+    }
+
+    /// Decodes a message and its header.
+    /// Fails if the message was sent with another protocol version.
+    pub fn decode(bytes: &[u8]) -> Result<(Header, Message)> {
+        let header: Header = deserialize(bytes)
+            .map_err(|err| Error::Protocol(format!("Malformed header: {}", err)))?;
+        if header.version != PROTOCOL_VERSION {
+            return Err(Error::Protocol(format!(
+                "Rank {} speaks protocol version {}, but this binary speaks version {}",
+                header.sender, header.version, PROTOCOL_VERSION
+            )));
+        }
+        deserialize(bytes).map_err(|err| {
+            Error::Protocol(format!(
+                "Malformed message from rank {}: {}",
+                header.sender, err
+            ))
+        })
+    }
+
+    /// Returns the vehicles the message hands over, which are sent next to its encoding.
+    pub fn handoffs_mut(&mut self) -> Vec<&mut Handoff> {
+        match self {
+            Message::TickUpdate(update) => vec![&mut update.vehicles],
+            Message::TickReport(report) => vec![&mut report.left, &mut report.finished],
+            Message::Hello | Message::Generate(_) | Message::Terminate | Message::Metrics(_) => {
+                Vec::new()
+            }
+        }
+    }
+
+    /// Returns the name of the kind of message.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Hello => "Hello",
+            Message::Generate(_) => "Generate",
+            Message::TickUpdate(_) => "TickUpdate",
+            Message::TickReport(_) => "TickReport",
+            Message::Terminate => "Terminate",
+            Message::Metrics(_) => "Metrics",
+        }
+    }
+}
+
+// Numbers the messages per peer, detecting lost or reordered messages
+#[derive(Debug, Default)]
+struct Sequences {
+    sent: HashMap<i32, u64>,
+    received: HashMap<i32, u64>,
+}
+
+impl Sequences {
+    // Returns the sequence number of the next message to the given rank
+    fn next(&mut self, to: i32) -> u64 {
+        let sequence = self.sent.entry(to).or_default();
+        *sequence += 1;
+        *sequence - 1
+    }
+
+    // Checks that the message comes from the given rank and follows its previous one
+    fn check(&mut self, from: i32, header: &Header) -> Result<()> {
+        if header.sender != from {
+            return Err(Error::Protocol(format!(
+                "Expected a message from rank {}, but rank {} sent it",
+                from, header.sender
+            )));
+        }
+        let expected = self.received.entry(from).or_default();
+        if header.sequence != *expected {
+            return Err(Error::Protocol(format!(
+                "Expected message {} from rank {}, but received message {}",
+                expected, from, header.sequence
+            )));
+        }
+        *expected += 1;
+        Ok(())
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct EdgeLengthRequest {
-    pub from: Osmid,
-    pub to: Osmid,
+/// Sends and receives the messages of a rank.
+#[derive(Debug)]
+pub struct Channel {
+    /// All ranks of the job.
+    world: SystemCommunicator,
+    /// The rank of this process.
+    rank: i32,
+    /// Sequence numbers of the messages to and from every other rank.
+    sequences: Sequences,
+    /// The messages sent and received so far.
+    metrics: MessageMetrics,
 }
 
-// Message interface for inter MPI communication
-impl MpiMessageContent<EdgeLengthRequest> for EdgeLengthRequest {
-    fn to_bytes(data: EdgeLengthRequest) -> Result<Vec<u8>> {
-        Ok(serialize(&data)?)
+impl Channel {
+    /// Creates the channel of this process.
+    pub fn new(world: SystemCommunicator) -> Channel {
+        Channel {
+            world,
+            rank: world.rank(),
+            sequences: Sequences::default(),
+            metrics: MessageMetrics::default(),
+        }
+    }
+
+    /// Returns the messages sent and received so far.
+    pub fn metrics(&self) -> &MessageMetrics {
+        &self.metrics
+    }
+
+    /// Sends a message to the given rank, followed by the vehicles it hands over.
+    pub fn send(&mut self, to: i32, mut message: Message) -> Result<()> {
+        let header = Header {
+            version: PROTOCOL_VERSION,
+            sender: self.rank,
+            sequence: self.sequences.next(to),
+        };
+        let bytes = message.encode(header)?;
+        let process = self.world.process_at_rank(to);
+        process.send_with_tag(&bytes[..], MESSAGE_TAG);
+        let mut size = bytes.len();
+        for handoff in message.handoffs_mut() {
+            process.send_with_tag(&handoff.vehicles[..], MESSAGE_TAG);
+            process.send_with_tag(&handoff.routes[..], MESSAGE_TAG);
+            size += handoff_size(handoff);
+        }
+        self.metrics.record_sent(message.kind(), size);
+        Ok(())
+    }
+
+    /// Receives the next message from the given rank.
+    pub fn receive(&mut self, from: i32) -> Result<Message> {
+        let process = self.world.process_at_rank(from);
+        // the other ranks are waited for on the message, the handed over vehicles follow it
+        let start = Instant::now();
+        let (bytes, _) = process.receive_vec_with_tag::<u8>(MESSAGE_TAG);
+        let waiting = start.elapsed();
+        let (header, mut message) = Message::decode(&bytes)?;
+        self.sequences.check(from, &header)?;
+        let mut size = bytes.len();
+        for handoff in message.handoffs_mut() {
+            (handoff.vehicles, _) = process.receive_vec_with_tag::<WireVehicle>(MESSAGE_TAG);
+            (handoff.routes, _) = process.receive_vec_with_tag::<u32>(MESSAGE_TAG);
+            size += handoff_size(handoff);
+        }
+        self.metrics.record_received(size, waiting);
+        Ok(message)
+    }
+
+    /// Exchanges a `Hello` with every other rank, so that binaries speaking different
+    /// protocol versions fail before the simulation starts.
+    /// The leafs greet the root first, then the root answers every leaf.
+    pub fn handshake(&mut self) -> Result<()> {
+        let size = self.world.size();
+        if self.rank == ROOT_RANK {
+            for r in 1..size {
+                self.expect_hello(r)?;
+            }
+            for r in 1..size {
+                self.send(r, Message::Hello)?;
+            }
+        } else {
+            self.send(ROOT_RANK, Message::Hello)?;
+            self.expect_hello(ROOT_RANK)?;
+        }
+        log::debug!(
+            "[{}] Protocol version {} agreed",
+            self.rank,
+            PROTOCOL_VERSION
+        );
+        Ok(())
     }
 
-    fn from_bytes(data: Vec<u8>) -> Result<EdgeLengthRequest> {
-        Ok(deserialize(&data)?)
+    // Receives the `Hello` of the given rank
+    fn expect_hello(&mut self, from: i32) -> Result<()> {
+        match self.receive(from)? {
+            Message::Hello => Ok(()),
+            other => Err(unexpected(from, &other)),
+        }
     }
 }
 
-// Mapping the vehicle to the next corresponsing rank
+// Returns the number of bytes of the vehicles a handoff sends next to its message
+fn handoff_size(handoff: &Handoff) -> usize {
+    handoff.vehicles.len() * size_of::<WireVehicle>() + handoff.routes.len() * size_of::<u32>()
+}
+
+/// Returns the error for a message the receiver does not expect at this point.
+pub fn unexpected(from: i32, message: &Message) -> Error {
+    Error::Protocol(format!(
+        "Unexpected {} message from rank {}",
+        message.kind(),
+        from
+    ))
+}
+
+// Mapping the vehicle to the rank simulating its current edge,
+// which is the rank owning the node the vehicle drives towards
 pub fn map_vehicle_to_rank(
     v: Vehicle,
-    node_to_rank: &HashMap<usize, i32>,
+    node_to_rank: &HashMap<NodeId, i32>,
     rank: i32,
-    world: SystemCommunicator,
+    outbox: &mut HashMap<i32, Vec<Vehicle>>,
 ) -> Result<()> {
     let node = v.next_id;
     let r = match node_to_rank.get(&node) {
         Some(r) => *r,
         None => {
             log::warn!("[{}] No rank found for node={}, {:?}", rank, node, v);
-            return Err(crate::prelude::Error::Generic(String::from(
-                "No rank found for node",
+            return Err(Error::Partition(format!(
+                "No rank simulates node {} of vehicle {}",
+                node, v.id
             )));
         }
     };
 
-    let vb = Vehicle::to_bytes(v).unwrap();
-
-    world
-        .process_at_rank(r)
-        .send_with_tag(&vb[..], ROOT_LEAF_VEHICLE);
-    log::debug!("[{}] Sent vehicle to rank {}", rank, r);
+    outbox.entry(r).or_default().push(v);
+    log::debug!("[{}] Queued vehicle for rank {}", rank, r);
     Ok(())
 }
+
+// Unit tests for the message protocol
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version: u32, sequence: u64) -> Header {
+        Header {
+            version,
+            sender: 2,
+            sequence,
+        }
+    }
+
+    #[test]
+    fn test_messages_round_trip() {
+        let update = Message::TickUpdate(TickUpdate {
+            time: 7,
+            ..TickUpdate::default()
+        });
+        let bytes = update.encode(header(PROTOCOL_VERSION, 3)).unwrap();
+        let (decoded_header, decoded) = Message::decode(&bytes).unwrap();
+        assert_eq!(decoded_header, header(PROTOCOL_VERSION, 3));
+        assert!(matches!(
+            decoded,
+            Message::TickUpdate(TickUpdate { time: 7, .. })
+        ));
+
+        let bytes = Message::Terminate
+            .encode(header(PROTOCOL_VERSION + 1, 0))
+            .unwrap();
+        assert!(matches!(Message::decode(&bytes), Err(Error::Protocol(_))));
+        assert!(matches!(
+            Message::decode(&bytes[..2]),
+            Err(Error::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn test_sequences_detect_lost_messages() {
+        let mut sender = Sequences::default();
+        let mut receiver = Sequences::default();
+        assert_eq!(sender.next(1), 0);
+        assert_eq!(sender.next(1), 1);
+        assert_eq!(sender.next(3), 0);
+
+        assert!(receiver.check(2, &header(PROTOCOL_VERSION, 0)).is_ok());
+        // message 1 got lost
+        assert!(receiver.check(2, &header(PROTOCOL_VERSION, 2)).is_err());
+        // the message comes from another rank than expected
+        assert!(receiver.check(1, &header(PROTOCOL_VERSION, 0)).is_err());
+    }
+}
@@ -3,35 +3,52 @@ extern crate mpi;
 use mpi::traits::*;
 use std::{
     collections::HashMap,
-    path::PathBuf,
-    sync::{Arc, Mutex},
-    thread,
+    path::{Path, PathBuf},
 };
 
-use mpi::{point_to_point::Status, topology::SystemCommunicator, Rank};
-use petgraph::{graphmap::GraphMap, Directed};
-use tokio::task::JoinHandle;
-
 use crate::{
     cli::{self, Cli},
-    graph::osm_graph::OSMGraph,
-    models::graph_input::GraphInput,
+    graph::{
+        cache,
+        input::{get_gi_from_input_file, parse_graph_input, parse_input, read_input},
+        osm_graph::{NodeId, OSMGraph},
+        validation::{self, CleaningOptions},
+    },
+    metrics::{MessageMetrics, RankMetrics, RunMetrics, VehicleMetrics},
     utils::MAX_NUMBER_OF_VEHICLES,
 };
 use crate::{
-    cli::{Parallelism, ThreadRuntime},
-    graph::{
-        get_path_length,
-        osm_graph::{GPartition, Osmid},
+    cli::{Parallelism, Partitioner},
+    config::Scenario,
+    graph::osm_graph::GPartition,
+    models::{
+        assignment::AssignmentOptions,
+        lane_change::MobilParameters,
+        rerouting::ReroutingOptions,
+        speed::SpeedDistribution,
+        traffic::{EdgeTail, Traffic},
+        transit::{TransitFeed, TransitStatistics},
+        vehicle::Vehicle,
+        vehicle_builder::reserve_ids,
+        vehicle_class::{class_shares, log_class_statistics, record_vehicle},
+        vehicle_generator::{TripConstraints, VehicleGenerator},
     },
-    models::vehicle::{Moveable, Vehicle},
     prelude::*,
-    utils::MpiMessageContent,
+    simulation::{
+        load_intersections, load_parking, load_schedule, load_transit, load_trip_chains,
+        make_router, Iteration, Simulation,
+    },
     vmpi::*,
 };
 
+// Name of the file the relative gap per assignment iteration is written to
+const ASSIGNMENT_REPORT_FILE: &str = "assignment.csv";
+
+// Name of the file the stop arrivals of the transit lines are written to
+const TRANSIT_REPORT_FILE: &str = "transit.csv";
+
 // Set up logging
-fn setup_logging(level: cli::LoggingLevel) {
+fn setup_logging(level: cli::LoggingLevel) -> Result<()> {
     let level = match level {
         cli::LoggingLevel::Debug => log::Level::Debug,
         cli::LoggingLevel::Info => log::Level::Info,
@@ -39,197 +56,394 @@ fn setup_logging(level: cli::LoggingLevel) {
         cli::LoggingLevel::Error => log::Level::Error,
     };
 
-    simple_logger::init_with_level(level).unwrap();
+    simple_logger::init_with_level(level)
+        .map_err(|err| Error::Config(format!("Cannot set up logging: {}", err)))
 }
 
-// Converts the fiel from the given path into a GraphInput struct
-pub fn get_gi_from_input_file(input_file: &PathBuf) -> Result<GraphInput> {
-    // open input file
-    let input_file = std::fs::File::open(input_file)?;
-    // read input data for gprah
-    let model: GraphInput = serde_json::from_reader(input_file).unwrap();
-    Ok(model)
+// Writes the JSON graph from the given path into a binary graph cache
+pub fn convert(input_file: &Path, output_file: Option<PathBuf>) -> Result<()> {
+    let start = std::time::Instant::now();
+    let stamp = cache::SourceStamp::of(input_file)
+        .map_err(|err| Error::Input(format!("{}: {}", input_file.display(), err)))?;
+    let data = read_input(input_file)?;
+    let model = parse_graph_input(input_file, &data)?;
+
+    let target = output_file.unwrap_or_else(|| cache::cache_path_for(input_file));
+    let header = cache::write_cache(&model, stamp, cache::checksum(&data), &target)?;
+    log::info!(
+        "Wrote graph cache {} (version {}, {} vertices, {} edges) in {:?} microseconds",
+        target.display(),
+        header.version,
+        model.graph.vertices.len(),
+        model.graph.edges.len(),
+        start.elapsed().as_micros()
+    );
+    Ok(())
 }
 
-// Parses the input file into a OSMGraph
-pub fn parse_input(input_file: &PathBuf) -> Result<OSMGraph> {
-    let model = get_gi_from_input_file(input_file)?;
-    // bootstrap the root graph
-    OSMGraph::new(model.graph)
+// Validates the graph from the given path and optionally writes the cleaned graph
+pub fn validate(
+    input_file: &Path,
+    options: CleaningOptions,
+    output_file: Option<PathBuf>,
+) -> Result<()> {
+    let mut model = get_gi_from_input_file(input_file)?;
+    let (graph, report) = validation::clean(model.graph, options);
+    log::info!("{}", report);
+
+    if let Some(output_file) = output_file {
+        model.size = graph.vertices.len();
+        model.graph = graph;
+        let writer = std::io::BufWriter::new(std::fs::File::create(&output_file)?);
+        serde_json::to_writer(writer, &model)?;
+        log::info!("Wrote cleaned graph to {}", output_file.display());
+    }
+    Ok(())
+}
+
+// Echoes the effective configuration into the output directory, if there is one
+fn write_effective_config(scenario: &Option<Scenario>, output_dir: &Option<PathBuf>) -> Result<()> {
+    if let (Some(scenario), Some(output_dir)) = (scenario, output_dir) {
+        let path = scenario.write_to(output_dir)?;
+        log::info!("Wrote effective configuration to {}", path.display());
+    }
+    Ok(())
 }
 
 /// Entry point for the simulation
 pub async fn run(cli: Cli) -> Result<()> {
+    let scenario = Scenario::from_command(&cli.command);
     match cli.command {
         cli::Commands::GraphParts {
             input_file,
+            config: _,
             parallelism,
             num_vehicles,
             logging_level,
-            thread_runtime,
             mpi,
             error_rate,
             min_speed,
             max_speed,
+            largest_component,
+            component,
+            min_trip_length,
+            max_trip_length,
+            max_attempts,
+            landmarks,
+            landmark_cache,
+            generate_on_leafs,
+            partitioner,
+            seed,
+            duration,
+            output_dir,
+            speed_distribution,
+            speed_mean,
+            speed_std_dev,
+            speed_histogram,
+            class_mix,
+            intersections,
+            informed_share,
+            reroute_interval,
+            congestion_threshold,
+            vehicle_classes,
+            event_file,
+            events,
+            parking,
+            trip_chains,
+            transit,
+            assign,
+            max_iterations,
+            convergence_gap,
+            logit_scale,
+            politeness,
+            lane_change_threshold,
+            safe_deceleration,
+            keep_right_bias,
         } => {
-            setup_logging(logging_level);
+            setup_logging(logging_level)?;
+            let input_file = match input_file {
+                Some(input_file) => input_file,
+                None => {
+                    return Err(Error::Config(String::from(
+                        "No input file given on the command line or in the scenario",
+                    )))
+                }
+            };
+            let cleaning = CleaningOptions { largest_component };
+            let rerouting = ReroutingOptions {
+                interval: reroute_interval,
+                congestion_threshold,
+            };
+            let lane_change = MobilParameters {
+                politeness,
+                threshold: lane_change_threshold,
+                safe_deceleration,
+                keep_right_bias,
+            };
+            let constraints = TripConstraints {
+                min_length: min_trip_length,
+                max_length: max_trip_length,
+                max_attempts,
+            };
+            let speed_distribution = SpeedDistribution::from_kind(
+                speed_distribution,
+                speed_mean,
+                speed_std_dev,
+                speed_histogram,
+            )?;
+            let classes = class_shares(
+                class_mix.as_deref(),
+                &vehicle_classes,
+                &speed_distribution,
+                min_speed,
+                max_speed,
+            )?;
 
             // Avoiding overflows
             if num_vehicles > MAX_NUMBER_OF_VEHICLES {
-                panic!(
-                    "Number of vehicles must be smaller than {}, but is {}!",
+                return Err(Error::Config(format!(
+                    "Number of vehicles must be smaller than {}, but is {}",
                     MAX_NUMBER_OF_VEHICLES, num_vehicles
-                );
+                )));
             }
             // finishing threshold
             let finishing_threshold = ((num_vehicles as f64) * (1.0 - error_rate)) as usize;
 
             if mpi && parallelism == Parallelism::SingleThreaded {
-                panic!("MPI and SingleThreaded are not compatible!");
+                return Err(Error::Config(String::from(
+                    "MPI and SingleThreaded are not compatible",
+                )));
+            }
+            if generate_on_leafs && !mpi {
+                return Err(Error::Config(String::from(
+                    "Generating vehicles on the leafs requires MPI",
+                )));
+            }
+            if mpi && assign.is_some() {
+                return Err(Error::Config(String::from(
+                    "Traffic assignment runs without MPI",
+                )));
             }
 
             if mpi {
                 log::debug!("Running with MPI");
-                let (universe, _) =
-                    mpi::initialize_with_threading(mpi::Threading::Multiple).unwrap();
+                let (universe, _) = mpi::initialize_with_threading(mpi::Threading::Multiple)
+                    .ok_or_else(|| Error::Mpi(String::from("MPI is already initialized")))?;
                 let world = universe.world();
                 let size = world.size();
                 let rank = world.rank();
 
-                if size < 2 {
-                    panic!("Size of MPI_COMM_WORLD must be 2, but is {}!", size);
-                }
-                let partitions: usize = (size - 1).try_into().unwrap();
-                let osm_graph = parse_input(&input_file).unwrap();
-                let my_graph = osm_graph.graph.clone();
-
-                log::debug!(
-                    "[{}] Root Size ({},{})",
-                    rank,
-                    my_graph.node_count(),
-                    my_graph.edge_count()
-                );
+                // a failing rank aborts the whole job, the others would wait for it forever
+                let result = (move || -> Result<()> {
+                    if rank == ROOT_RANK {
+                        write_effective_config(&scenario, &output_dir)?;
+                        // the ranks do not exchange the speeds on their edges
+                        if informed_share > 0.0 {
+                            log::warn!(
+                                "Informed drivers only see the congestion of the partition \
+                                 they are driving in, other edges count with their speed limits"
+                            );
+                        }
+                    }
 
-                log::debug!("[{}] Making {} partition(s)", rank, partitions);
-                let start = std::time::Instant::now();
-                match rank {
-                    ROOT_RANK => {
-                        root_event_loop(
-                            num_vehicles,
-                            finishing_threshold,
-                            world,
-                            size,
-                            rank,
-                            partitions,
-                            &osm_graph,
-                            &my_graph,
-                            min_speed,
-                            max_speed,
-                        )?;
+                    if size < 2 {
+                        return Err(Error::Config(format!(
+                            "Size of MPI_COMM_WORLD must be at least 2, but is {}",
+                            size
+                        )));
                     }
-                    rank_number => {
-                        log::debug!("[{}] Assigning leaf to rank", rank);
-                        let r: usize = rank_number.try_into().unwrap();
-                        let p = osm_graph.partition(partitions, r - 1)?;
-
-                        log::debug!(
-                            "[{}] Rank {} -> Size ({},{})",
-                            rank,
-                            r,
-                            p.graph.node_count(),
-                            p.graph.edge_count()
-                        );
-
-                        let mm = Arc::new(Mutex::new(p));
-                        loop {
-                            let (msg, status) =
-                                world.process_at_rank(ROOT_RANK).receive_vec::<u8>();
-                            log::debug!(
-                                "[{}] Received message from rank {}",
+                    let mut channel = Channel::new(world);
+                    channel.handshake()?;
+                    let partitions = (size - 1) as usize;
+                    let load_start = std::time::Instant::now();
+                    let osm_graph = parse_input(&input_file, cleaning)?;
+                    let graph_load_time = load_start.elapsed();
+
+                    log::debug!(
+                        "[{}] Root Size ({},{})",
+                        rank,
+                        osm_graph.node_count(),
+                        osm_graph.edge_count()
+                    );
+
+                    // the root and the leafs build alike generators from the same seed
+                    let generator = |seed| -> Result<_> {
+                        VehicleGenerator::new(&osm_graph, component, constraints, classes.clone())?
+                            .with_seed(seed)
+                            .with_max_steps(duration)
+                            .with_informed_share(informed_share)?
+                            .with_trip_chains(load_trip_chains(trip_chains.as_deref())?)
+                            .with_landmarks(landmarks, landmark_cache.as_deref())
+                    };
+
+                    log::debug!("[{}] Making {} partition(s)", rank, partitions);
+                    let start = std::time::Instant::now();
+                    // the size of the simulated graph and, on the root, the metrics of the run
+                    let (nodes, edges, metrics) = match rank {
+                        ROOT_RANK => {
+                            let generator = match generate_on_leafs {
+                                true => None,
+                                false => Some(generator(seed)?),
+                            };
+                            let metrics = root_event_loop(
+                                num_vehicles,
+                                finishing_threshold,
+                                &mut channel,
+                                size,
                                 rank,
-                                status.source_rank()
+                                partitioner,
+                                &osm_graph,
+                                generator,
+                                seed,
+                                &load_transit(&osm_graph, transit.as_deref(), &vehicle_classes)?,
+                                &output_dir,
+                            )?;
+                            (
+                                osm_graph.node_count(),
+                                osm_graph.edge_count(),
+                                Some(metrics),
+                            )
+                        }
+                        rank_number => {
+                            log::debug!("[{}] Assigning leaf to rank", rank);
+                            let r = rank_number as usize;
+                            let p = osm_graph.partition(partitioner, partitions, r - 1)?;
+                            let generated = match generate_on_leafs {
+                                true => {
+                                    let generation = match channel.receive(ROOT_RANK)? {
+                                        Message::Generate(generation) => generation,
+                                        other => return Err(unexpected(ROOT_RANK, &other)),
+                                    };
+                                    let node_to_rank = node_ranks(&osm_graph, partitioner, size)?;
+                                    let generated = generator(Some(generation.seed))?
+                                        .generate_where(
+                                            generation.first_id,
+                                            generation.count,
+                                            |origin| node_to_rank.get(&origin) == Some(&rank),
+                                        )?;
+                                    log::debug!(
+                                        "[{}] Generated {} vehicle(s)",
+                                        rank,
+                                        generated.len()
+                                    );
+                                    generated
+                                }
+                                false => Vec::new(),
+                            };
+                            let intersections =
+                                load_intersections(&osm_graph, intersections.as_deref())?;
+                            let schedule =
+                                load_schedule(&osm_graph, event_file.as_deref(), events)?;
+                            let parking = load_parking(&osm_graph, parking.as_deref())?;
+                            parking.check_partition(&p)?;
+                            let router = make_router(
+                                &osm_graph,
+                                &classes,
+                                informed_share,
+                                !schedule.is_empty() || trip_chains.is_some(),
+                                rerouting,
                             );
-                            if process_leaf_event(
-                                parallelism,
-                                thread_runtime,
-                                world,
+
+                            log::debug!(
+                                "[{}] Rank {} -> Size ({},{})",
                                 rank,
-                                &mm,
-                                msg,
-                                status,
-                            ) {
-                                break;
+                                r,
+                                p.node_count(),
+                                p.edge_count()
+                            );
+
+                            let mut traffic = Traffic::new(&p)
+                                .with_parallel(parallelism == Parallelism::MultiThreaded)
+                                .with_intersections(intersections)
+                                .with_schedule(schedule)
+                                .with_parking(parking)
+                                .with_lane_change(lane_change);
+                            if let Some(router) = router {
+                                traffic = traffic.with_router(router);
                             }
+                            // vehicles whose first edge leads into another partition are
+                            // handed over like vehicles leaving the partition
+                            let (generated, departing): (Vec<Vehicle>, Vec<Vehicle>) = generated
+                                .into_iter()
+                                .partition(|v| p.contains_node(v.next_id));
+                            for v in generated {
+                                traffic.insert(v);
+                            }
+                            leaf_event_loop(&mut channel, rank, traffic, departing)?;
+                            (p.node_count(), p.edge_count(), None)
+                        }
+                    };
+                    let end = std::time::Instant::now();
+                    let time = end - start;
+                    log::info!("[{}] Finished in {:?} microseconds", rank, time.as_micros());
+
+                    // the leafs report their metrics to the root, which writes those of the run
+                    let own = RankMetrics::new(rank, nodes, edges, time, channel.metrics().clone());
+                    match metrics {
+                        Some(mut metrics) => {
+                            metrics.graph_load_time = graph_load_time.as_secs_f64();
+                            metrics.wall_time = time.as_secs_f64();
+                            metrics.ranks.push(own);
+                            for r in 1..size {
+                                match channel.receive(r)? {
+                                    Message::Metrics(leaf) => metrics.ranks.push(leaf),
+                                    other => return Err(unexpected(r, &other)),
+                                }
+                            }
+                            report_metrics(&metrics, &output_dir)?;
                         }
+                        None => channel.send(ROOT_RANK, Message::Metrics(own))?,
                     }
-                };
-                let end = std::time::Instant::now();
-                let time = end - start;
-                log::info!("[{}] Finished in {:?} microseconds", rank, time.as_micros());
+                    Ok(())
+                })();
+                if let Err(err) = result {
+                    log::error!("[{}] Aborting: {}", rank, err);
+                    world.abort(1);
+                }
             } else {
                 log::debug!("Running without MPI");
-                let osm_graph = parse_input(&input_file).unwrap();
-                let my_graph = osm_graph.graph.clone();
+                write_effective_config(&scenario, &output_dir)?;
+                let load_start = std::time::Instant::now();
+                let osm_graph = parse_input(&input_file, cleaning)?;
+                let graph_load_time = load_start.elapsed();
 
                 log::debug!(
                     "Root Size ({},{})",
-                    my_graph.node_count(),
-                    my_graph.edge_count()
+                    osm_graph.node_count(),
+                    osm_graph.edge_count()
                 );
 
-                let start = std::time::Instant::now();
-                let mut step_accumulator = 0;
-
-                match parallelism {
-                    Parallelism::SingleThreaded => {
-                        for _ in 0..num_vehicles {
-                            let mut v =
-                                Vehicle::generate_default(&my_graph, min_speed, max_speed).unwrap();
-
-                            v.drive(&osm_graph);
-                            step_accumulator += v.steps;
-                        }
-                    }
-                    Parallelism::MultiThreaded => match thread_runtime {
-                        cli::ThreadRuntime::RustThreads => {
-                            let mut handles = vec![];
-                            for _ in 0..num_vehicles {
-                                let mut v =
-                                    Vehicle::generate_default(&my_graph, min_speed, max_speed)
-                                        .unwrap();
-                                let osm_graph = Arc::new(osm_graph.clone());
-                                let handle = thread::spawn(move || {
-                                    v.drive(&osm_graph);
-                                    v
-                                });
-                                handles.push(handle);
-                            }
-                            for handle in handles {
-                                let v = handle.join().unwrap();
-                                step_accumulator += v.steps;
-                            }
-                        }
-                        cli::ThreadRuntime::Tokio => {
-                            let mut handles = vec![];
-                            for _ in 0..num_vehicles {
-                                let mut v =
-                                    Vehicle::generate_default(&my_graph, min_speed, max_speed)
-                                        .unwrap();
-                                let osm_graph = Arc::new(osm_graph.clone());
-                                let handle = tokio::spawn(async move {
-                                    v.drive(&osm_graph);
-                                    v
-                                });
-                                handles.push(handle);
-                            }
-                            for handle in handles {
-                                let v = handle.await.unwrap();
-                                step_accumulator += v.steps;
-                            }
-                        }
-                    },
-                }
+                let simulation = Simulation::builder(&osm_graph)
+                    .with_vehicles(num_vehicles)
+                    .with_component(component)
+                    .with_trip_constraints(constraints)
+                    .with_landmarks(landmarks, landmark_cache)
+                    .with_classes(classes)
+                    .with_seed(seed)
+                    .with_max_steps(duration)
+                    .with_informed_share(informed_share)
+                    .with_rerouting(rerouting)
+                    .with_lane_change(lane_change)
+                    .with_parallel(parallelism == Parallelism::MultiThreaded)
+                    .with_intersections(load_intersections(&osm_graph, intersections.as_deref())?)
+                    .with_schedule(load_schedule(&osm_graph, event_file.as_deref(), events)?)
+                    .with_parking(load_parking(&osm_graph, parking.as_deref())?)
+                    .with_trip_chains(load_trip_chains(trip_chains.as_deref())?)
+                    .with_transit(load_transit(
+                        &osm_graph,
+                        transit.as_deref(),
+                        &vehicle_classes,
+                    )?)
+                    .with_assignment(assign.map(|method| AssignmentOptions {
+                        method,
+                        max_iterations,
+                        convergence_gap,
+                        logit_scale,
+                    }))
+                    .build()?;
 
+                let start = std::time::Instant::now();
+                let report = simulation.run()?;
                 let end = std::time::Instant::now();
                 let time = end - start;
                 log::info!(
@@ -239,371 +453,303 @@ pub async fn run(cli: Cli) -> Result<()> {
                 );
                 log::info!(
                     "Finished {} vehicles in {} steps",
-                    num_vehicles,
-                    step_accumulator
+                    report.finished,
+                    report.steps
                 );
+                log_class_statistics(ROOT_RANK, &report.classes);
+                report_transit(ROOT_RANK, &osm_graph, &report.transit, &output_dir)?;
+                report_assignment(&report.assignment, &output_dir)?;
+                report_metrics(
+                    &RunMetrics {
+                        graph_load_time: graph_load_time.as_secs_f64(),
+                        wall_time: time.as_secs_f64(),
+                        steps: report.time,
+                        vehicles: VehicleMetrics::new(num_vehicles, report.lost, &report.classes),
+                        ranks: vec![RankMetrics::new(
+                            ROOT_RANK,
+                            osm_graph.node_count(),
+                            osm_graph.edge_count(),
+                            time,
+                            MessageMetrics::default(),
+                        )],
+                    },
+                    &output_dir,
+                )?;
             }
             Ok(())
         }
+        cli::Commands::Convert {
+            input_file,
+            output_file,
+            logging_level,
+        } => {
+            setup_logging(logging_level)?;
+            convert(&input_file, output_file)
+        }
+        cli::Commands::Validate {
+            input_file,
+            largest_component,
+            output_file,
+            logging_level,
+        } => {
+            setup_logging(logging_level)?;
+            validate(
+                &input_file,
+                CleaningOptions { largest_component },
+                output_file,
+            )
+        }
     }
 }
 
 // Root main event loop
-// This is the main event loop for the root process
-// 1. It is responsible for sending vehicles to the leafs and receiving them back
-// 2. It receives edge length requests from the leafs and sends the length back
-// 3. It receives termination notifications from the leafs
-// 4. Once all vehicles are done, it sends termination notifications to the leafs
+// This is the main event loop for the root process. All ranks advance in lock-step:
+// 1. It sends every leaf the vehicles entering its partition and the last vehicles on
+//    the edges behind its boundary, which starts the next step
+// 2. It receives the vehicles that left each partition, the finished vehicles and the
+//    last vehicles on the edges leading into each partition
+// 3. Once enough vehicles are done, it sends termination notifications to the leafs
 //    and then terminates itself
+#[allow(clippy::too_many_arguments)]
 fn root_event_loop(
     num_vehicles: usize,
     finishing_threshold: usize,
-    world: SystemCommunicator,
+    channel: &mut Channel,
     size: i32,
     rank: i32,
-    partitions: usize,
+    partitioner: Partitioner,
     osm_graph: &OSMGraph,
-    my_graph: &GraphMap<Osmid, f64, Directed>,
-    min_speed: f64,
-    max_speed: f64,
-) -> Result<()> {
+    generator: Option<VehicleGenerator>,
+    seed: Option<u64>,
+    transit: &TransitFeed,
+    output_dir: &Option<PathBuf>,
+) -> Result<RunMetrics> {
     let mut finished_vehicle_counter = 0;
+    let mut lost_vehicle_counter = 0;
     let mut step_accumulator = 0;
+    let mut statistics = HashMap::new();
+    let mut transit_statistics = TransitStatistics::default();
+    let mut buses = 0;
     log::debug!("[{}] Creating NodeID->Rank mapping", rank);
-    // create map with nodeID->rank mapping
-    let mut node_to_rank = HashMap::new();
-
-    for r in 1..size {
-        let rr: usize = r.try_into().unwrap();
-        let part = osm_graph.partition(partitions, rr - 1)?;
-
-        for node in part.graph.nodes() {
-            node_to_rank.insert(node, r);
+    let node_to_rank = node_ranks(osm_graph, partitioner, size)?;
+
+    let first_id = reserve_ids(num_vehicles);
+    let mut outbox = HashMap::new();
+    match generator {
+        Some(mut generator) => {
+            log::debug!("[{}] Generating vehicles", rank);
+            for v in generator.generate_all(first_id, num_vehicles)? {
+                if map_vehicle_to_rank(v, &node_to_rank, rank, &mut outbox).is_err() {
+                    log::warn!("[{}] Failed to send vehicle", rank);
+                    lost_vehicle_counter += 1;
+                }
+            }
+            log::debug!("[{}] Generated {} vehicles", rank, num_vehicles);
         }
-    }
-
-    if node_to_rank.len() != my_graph.nodes().len() {
-        panic!(
-            "Node to rank mapping is incomplete! {} != {}",
-            node_to_rank.len(),
-            my_graph.nodes().len()
-        );
-    }
-
-    log::debug!("[{}] Sending vehicles", rank);
-    let mut vehicle_counter = 0;
-    // send vehicles
-    while vehicle_counter < num_vehicles {
-        let v = Vehicle::generate_default(my_graph, min_speed, max_speed).unwrap();
-
-        match map_vehicle_to_rank(v, &node_to_rank, rank, world) {
-            Ok(_) => {}
-            Err(_) => {
-                log::warn!("[{}] Failed to send vehicle", rank);
-                continue;
+        None => {
+            let generation = Generation {
+                seed: seed.unwrap_or_else(rand::random),
+                first_id,
+                count: num_vehicles,
+            };
+            log::debug!(
+                "[{}] Leafs generate vehicles with seed {}",
+                rank,
+                generation.seed
+            );
+            for r in 1..size {
+                channel.send(r, Message::Generate(generation))?;
             }
-        };
-        vehicle_counter += 1;
+        }
     }
-    log::debug!("[{}] Sent {} vehicles to ranks", rank, vehicle_counter,);
-    log::debug!("[{}] Listening for incoming connections", rank);
+
+    let mut tails: Vec<EdgeTail> = Vec::new();
+    let mut time = 0;
     loop {
-        let (msg, status) = world.any_process().receive_vec::<u8>();
-        match status.tag() {
-            LEAF_ROOT_VEHICLE => {
-                log::debug!(
-                    "[{}] Received vehicle from rank {}",
-                    rank,
-                    status.source_rank()
-                );
-                let v = Vehicle::from_bytes(msg).unwrap();
-                match map_vehicle_to_rank(v, &node_to_rank, rank, world) {
-                    Ok(_) => {}
-                    Err(err) => {
-                        log::warn!("[{}] Failed to send vehicle after receive: {:?}", rank, err);
-                        continue;
-                    }
-                }
-            }
-            EDGE_LENGTH_REQUEST => {
-                log::debug!("[{}] Received edge length request len={}", rank, msg.len());
-                let el_req = EdgeLengthRequest::from_bytes(msg).unwrap();
-                log::debug!("[{}] {:?}", rank, el_req);
-                let from = el_req.from;
-                let to = el_req.to;
-
-                let mut edges = my_graph.edges_directed(from, petgraph::Direction::Outgoing);
-
-                log::debug!("[{}] Searching for edge from {} to {}", rank, from, to);
-                let el: f64 = match edges.find(|e| e.1 == to) {
-                    Some(e) => *e.2,
-                    None => {
-                        log::debug!(
-                            "[{}] No edge found for from={} to={}. Recalculating the path.",
-                            rank,
-                            from,
-                            to
-                        );
-                        // NOTE: recalculating the way, and send the distance of the path instead of 0; This is a hack
-                        // The graph and the provided data can be inconsistent, due to inprecise
-                        // annotations in the OSM data
-                        get_path_length(from, to, my_graph.clone())
-                    }
-                };
-                let v = vec![el];
-                log::debug!("[{}] Sending edge length response", rank);
-                world
-                    .process_at_rank(status.source_rank())
-                    .send_with_tag(&v[..], EDGE_LENGTH_RESPONSE);
-                log::debug!("[{}] Sent edge length response", rank);
-            }
-            LEAF_ROOT_VEHICLE_FINISH => {
-                let v = Vehicle::from_bytes(msg).unwrap();
-                finished_vehicle_counter += 1;
-                step_accumulator += v.steps;
-                if finished_vehicle_counter >= finishing_threshold {
-                    log::info!(
-                        "[{}] Finished {} vehicles in {} steps",
-                        rank,
-                        finished_vehicle_counter,
-                        step_accumulator
-                    );
-                    for r in 1..size {
-                        world
-                            .process_at_rank(r)
-                            .send_with_tag(&[1], ROOT_LEAF_TERMINATE);
-                    }
-                    break;
-                }
-            }
-            _ => {
-                log::error!(
-                    "[{}] Received unknown message with unknown tag -> {} -> {:#?}",
-                    rank,
-                    status.tag(),
-                    msg
-                );
+        for bus in transit.depart(time)? {
+            match map_vehicle_to_rank(bus, &node_to_rank, rank, &mut outbox) {
+                Ok(_) => buses += 1,
+                Err(_) => log::warn!("[{}] Failed to send bus", rank),
             }
         }
-    }
-    Ok(())
-}
+        for r in 1..size {
+            let update = TickUpdate {
+                vehicles: outbox.remove(&r).unwrap_or_default().into_iter().collect(),
+                tails: tails
+                    .iter()
+                    .filter(|t| node_to_rank.get(&t.from) == Some(&r))
+                    .copied()
+                    .collect(),
+                time,
+            };
+            channel.send(r, Message::TickUpdate(update))?;
+        }
 
-// Handling of Events the leaf emits
-fn process_leaf_event(
-    parallelism: Parallelism,
-    thread_runtime: ThreadRuntime,
-    world: SystemCommunicator,
-    rank: i32,
-    mm: &Arc<Mutex<OSMGraph>>,
-    msg: Vec<u8>,
-    status: Status,
-) -> bool {
-    match status.tag() {
-        ROOT_LEAF_VEHICLE => {
-            let o_data = Arc::clone(mm);
-            match parallelism {
-                Parallelism::SingleThreaded => {
-                    single_drive(world, rank, &msg, status, o_data);
-                    todo!()
+        time += 1;
+        tails.clear();
+        for r in 1..size {
+            let report = match channel.receive(r)? {
+                Message::TickReport(report) => report,
+                other => return Err(unexpected(r, &other)),
+            };
+            for v in report.finished.into_vehicles()? {
+                if v.transit.is_some() {
+                    buses -= 1;
+                    transit_statistics.record(&v);
+                    continue;
                 }
-                Parallelism::MultiThreaded => {
-                    match thread_runtime {
-                        cli::ThreadRuntime::RustThreads => {
-                            // fire and forget
-                            mpi_drive(world, rank, &msg, status, o_data);
-                        }
-                        cli::ThreadRuntime::Tokio => {
-                            mpi_tokio_drive(world, rank, &msg, status, o_data);
-                        }
+                finished_vehicle_counter += 1;
+                step_accumulator += v.steps;
+                record_vehicle(&mut statistics, &v);
+            }
+            for v in report.left.into_vehicles()? {
+                let is_bus = v.transit.is_some();
+                if let Err(err) = map_vehicle_to_rank(v, &node_to_rank, rank, &mut outbox) {
+                    log::warn!("[{}] Failed to send vehicle after receive: {:?}", rank, err);
+                    if is_bus {
+                        buses -= 1;
+                    } else {
+                        lost_vehicle_counter += 1;
                     }
                 }
             }
+            tails.extend(report.tails);
         }
-        ROOT_LEAF_TERMINATE => {
-            log::debug!("[{}] Received termination notification", rank);
-            return true;
-        }
-        // proxy edge length response
-        EDGE_LENGTH_RESPONSE => {
-            log::debug!("[{}] Received edge length response", rank);
-            world
-                .this_process()
-                .send_with_tag(&msg[..], EDGE_LENGTH_RESPONSE);
-        }
-        _ => {
-            log::error!(
-                "[{}] Received unknown message with unknown tag {}->{} from {}",
+
+        let transit_done = buses == 0 && transit.is_done(time);
+        if transit_done
+            && (finished_vehicle_counter >= finishing_threshold
+                || finished_vehicle_counter + lost_vehicle_counter >= num_vehicles)
+        {
+            log::info!(
+                "[{}] Finished {} vehicles in {} steps",
                 rank,
-                status.tag(),
-                msg.len(),
-                status.source_rank()
+                finished_vehicle_counter,
+                step_accumulator
             );
+            log_class_statistics(rank, &statistics);
+            report_transit(rank, osm_graph, &transit_statistics, output_dir)?;
+            for r in 1..size {
+                channel.send(r, Message::Terminate)?;
+            }
+            return Ok(RunMetrics {
+                steps: time,
+                vehicles: VehicleMetrics::new(num_vehicles, lost_vehicle_counter, &statistics),
+                ..RunMetrics::default()
+            });
         }
     }
-    false
 }
 
-// Processes a vehicle
-fn single_drive(
-    world: SystemCommunicator,
-    rank: i32,
-    msg: &[u8],
-    status: Status,
-    o_data: Arc<Mutex<OSMGraph>>,
-) -> bool {
-    let msg = msg.to_owned();
-    let lock = o_data.lock().unwrap();
-    let cont = process_vehicle(world, rank, &lock, msg, status);
-    match cont {
-        Ok(cont) => cont,
-        Err(err) => {
-            log::error!("[{}] Error while processing vehicle: {:?}", rank, err);
-            false
+// Maps every node to the rank simulating it
+fn node_ranks(
+    osm_graph: &OSMGraph,
+    partitioner: Partitioner,
+    size: i32,
+) -> Result<HashMap<NodeId, i32>> {
+    let partitions = (size - 1) as usize;
+    let mut node_to_rank = HashMap::new();
+
+    for r in 1..size {
+        let part = osm_graph.partition(partitioner, partitions, r as usize - 1)?;
+
+        for node in part.nodes() {
+            node_to_rank.insert(node, r);
         }
     }
-}
 
-// Processes a vehicle asyncronously
-fn mpi_drive(
-    world: SystemCommunicator,
-    rank: i32,
-    msg: &[u8],
-    status: Status,
-    o_data: Arc<Mutex<OSMGraph>>,
-) {
-    let msg = msg.to_owned();
-
-    thread::spawn(move || {
-        let lock = match o_data.lock() {
-            Ok(lock) => lock,
-            Err(err) => {
-                log::error!("[{}] Error while locking data: {:?}", rank, err);
-                return false;
-            }
-        };
-        let cont = process_vehicle(world, rank, &lock, msg, status);
-        match cont {
-            Ok(cont) => cont,
-            Err(err) => {
-                log::error!("[{}] Error while processing vehicle: {:?}", rank, err);
-                false
-            }
-        }
-    });
+    if node_to_rank.len() != osm_graph.node_count() {
+        return Err(Error::Partition(format!(
+            "The partitions cover {} of {} nodes",
+            node_to_rank.len(),
+            osm_graph.node_count()
+        )));
+    }
+    Ok(node_to_rank)
 }
 
-// Processes a vehicle asyncronously using tokio
-fn mpi_tokio_drive(
-    world: SystemCommunicator,
-    rank: i32,
-    msg: &[u8],
-    status: Status,
-    o_data: Arc<Mutex<OSMGraph>>,
-) -> JoinHandle<bool> {
-    let msg = msg.to_owned();
-
-    let result = std::panic::catch_unwind(|| {
-        tokio::spawn(async move {
-            let lock_result = o_data.lock();
-            let lock = match lock_result {
-                Ok(guard) => guard,
-                Err(poisoned) => poisoned.into_inner(),
-            };
-            let cont = process_vehicle(world, rank, &lock, msg.clone(), status);
-            match cont {
-                Ok(cont) => cont,
-                Err(err) => {
-                    log::error!("[{}] Error while processing vehicle: {:?}", rank, err);
-                    false
-                }
-            }
-        })
-    });
-
-    match result {
-        Ok(join_handle) => join_handle,
-        Err(_) => {
-            log::error!("Error while spawning tokio task");
-            tokio::spawn(async { false })
-        }
+// Writes the metrics of the run to the output directory, if there is one
+fn report_metrics(metrics: &RunMetrics, output_dir: &Option<PathBuf>) -> Result<()> {
+    if let Some(output_dir) = output_dir {
+        let path = metrics.write_to(output_dir)?;
+        log::info!("Wrote run metrics to {}", path.display());
     }
+    Ok(())
 }
 
-// Receives a vehicle, processes it and sends it to the next rank
-fn process_vehicle(
-    world: SystemCommunicator,
-    rank: Rank,
-    part: &OSMGraph,
-    msg: Vec<u8>,
-    status: Status,
-) -> Result<bool> {
-    let mut v = Vehicle::from_bytes(msg).unwrap();
-    log::debug!(
-        "[{}] Received vehicle from rank {} ID {}",
-        rank,
-        status.source_rank(),
-        v.id
-    );
-
-    // vehicle is done
-    if v.is_parked || v.prev_id == v.next_id {
-        log::debug!("[{}] - 1 Vehicle {} is done driving", rank, v.id);
-        return Ok(true);
+// Logs the schedule adherence of the transit lines and writes every stop arrival
+// to the output directory, if there is one
+fn report_transit(
+    rank: i32,
+    graph: &OSMGraph,
+    statistics: &TransitStatistics,
+    output_dir: &Option<PathBuf>,
+) -> Result<()> {
+    if statistics.is_empty() {
+        return Ok(());
     }
+    statistics.log(rank);
+    if let Some(output_dir) = output_dir {
+        std::fs::create_dir_all(output_dir)?;
+        let path = output_dir.join(TRANSIT_REPORT_FILE);
+        std::fs::write(&path, statistics.to_csv(graph))?;
+        log::info!("Wrote transit report to {}", path.display());
+    }
+    Ok(())
+}
 
-    v.marked_for_deletion = false;
-
-    // ask root for edge length
-    let el_req = EdgeLengthRequest {
-        from: v.prev_id,
-        to: v.next_id,
+// Writes the relative gap of every run of the traffic assignment to the output directory,
+// if there is one
+fn report_assignment(iterations: &[Iteration], output_dir: &Option<PathBuf>) -> Result<()> {
+    let output_dir = match output_dir {
+        Some(output_dir) if !iterations.is_empty() => output_dir,
+        _ => return Ok(()),
     };
-    let buf = EdgeLengthRequest::to_bytes(el_req.clone()).unwrap();
-    log::debug!(
-        "[{}] Sending edge length request, {:?} @ {:?}",
-        rank,
-        el_req,
-        v
-    );
-    world
-        .process_at_rank(ROOT_RANK)
-        .send_with_tag(&buf[..], EDGE_LENGTH_REQUEST);
-
-    // get edge length
-    let (el_msg, _) = world
-        .this_process()
-        .receive_vec_with_tag::<f64>(EDGE_LENGTH_RESPONSE);
-
-    v.delta += el_msg[0];
-
-    log::debug!(
-        "[{}] Vehicle {} is driving from {} to {}",
-        rank,
-        v.id,
-        v.prev_id,
-        v.next_id
-    );
+    let mut report = String::from("iteration,steps,gap\n");
+    for i in iterations {
+        report.push_str(&format!("{},{},{}\n", i.iteration, i.steps, i.gap));
+    }
+    std::fs::create_dir_all(output_dir)?;
+    let path = output_dir.join(ASSIGNMENT_REPORT_FILE);
+    std::fs::write(&path, report)?;
+    log::info!("Wrote assignment report to {}", path.display());
+    Ok(())
+}
 
+// Leaf main event loop
+// Every step, the leaf puts the vehicles it received on the road, advances all vehicles
+// on its partition and reports the vehicles that left the partition or finished.
+// The departing vehicles are reported as having left the partition in the first step.
+fn leaf_event_loop(
+    channel: &mut Channel,
+    rank: i32,
+    mut traffic: Traffic,
+    mut departing: Vec<Vehicle>,
+) -> Result<()> {
     loop {
-        log::debug!("[{}] Stepping...", v.id);
-        if v.is_parked {
-            // v is done
-            log::debug!("[{}] Vehicle {} is done driving", rank, v.id);
-            // create buffer containing the number 1
-            world
-                .process_at_rank(ROOT_RANK)
-                .send_with_tag(&Vehicle::to_bytes(v).unwrap()[..], LEAF_ROOT_VEHICLE_FINISH);
-            break;
-        } else if v.marked_for_deletion {
-            log::debug!("[{}] Sending vehicle {} to root", rank, v.id);
-            // send vehicle to root
-            world
-                .process_at_rank(ROOT_RANK)
-                .send_with_tag(&Vehicle::to_bytes(v).unwrap()[..], LEAF_ROOT_VEHICLE);
-            break;
+        let update = match channel.receive(ROOT_RANK)? {
+            Message::TickUpdate(update) => update,
+            Message::Terminate => {
+                log::debug!("[{}] Received termination notification", rank);
+                return Ok(());
+            }
+            other => return Err(unexpected(ROOT_RANK, &other)),
+        };
+
+        traffic.set_time(update.time);
+        traffic.set_remote_tails(update.tails);
+        for v in update.vehicles.into_vehicles()? {
+            log::debug!("[{}] Received vehicle {}", rank, v.id);
+            traffic.insert(v);
         }
-        v.step(part);
+
+        log::debug!("[{}] {} vehicle(s) on the road", rank, traffic.len());
+        let outcome = traffic.step()?;
+        let report = TickReport {
+            left: departing.drain(..).chain(outcome.left).collect(),
+            finished: outcome.finished.into_iter().collect(),
+            tails: traffic.boundary_tails(),
+        };
+        channel.send(ROOT_RANK, Message::TickReport(report))?;
     }
-    Ok(false)
 }
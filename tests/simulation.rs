@@ -0,0 +1,158 @@
+// Integration tests for the simulation API
+use std::{cell::RefCell, rc::Rc};
+
+use traffic_sim::{
+    config::Scenario,
+    models::{
+        graph_input::{Edge, Graph},
+        intersection::{Controller, Intersections, Phase},
+    },
+    simulation::GRIDLOCK_STEPS,
+    NodeId, OSMGraph, Observer, Simulation, Vehicle,
+};
+
+// A two-way ring of 8 nodes with 100m edges
+fn ring() -> OSMGraph {
+    let edge = |from, to| Edge {
+        from,
+        to,
+        length: 100.0,
+        max_speed: String::from("50"),
+        name: String::new(),
+        osm_id: String::new(),
+        highway: Some(String::from("residential")),
+        lanes: None,
+        lanes_forward: None,
+        lanes_backward: None,
+        reversed: false,
+    };
+    let edges = (1..=8)
+        .flat_map(|i| {
+            let next = i % 8 + 1;
+            [edge(i, next), edge(next, i)]
+        })
+        .collect();
+    OSMGraph::new(Graph {
+        vertices: Vec::new(),
+        edges,
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_seeded_runs_are_reproducible() {
+    let graph = ring();
+    let run = || {
+        Simulation::builder(&graph)
+            .with_vehicles(10)
+            .with_seed(Some(7))
+            .build()
+            .unwrap()
+            .run()
+            .unwrap()
+    };
+
+    let report = run();
+    assert_eq!(report.classes["car"].vehicles, 10);
+    assert_eq!(report.classes["car"].finished, 10);
+    assert_eq!((report.finished, report.lost), (10, 0));
+    assert!(report.steps > 0);
+    assert_eq!(run().steps, report.steps);
+}
+
+// Counts the events of a simulation
+#[derive(Debug, Default)]
+struct Counts {
+    spawns: usize,
+    entries: usize,
+    exits: usize,
+    arrivals: usize,
+    steps: u64,
+}
+
+struct Counter(Rc<RefCell<Counts>>);
+
+impl Observer for Counter {
+    fn on_spawn(&mut self, _time: u64, _vehicle: &Vehicle) {
+        self.0.borrow_mut().spawns += 1;
+    }
+
+    fn on_edge_entry(&mut self, _time: u64, _vehicle: &Vehicle, _edge: (NodeId, NodeId)) {
+        self.0.borrow_mut().entries += 1;
+    }
+
+    fn on_edge_exit(&mut self, _time: u64, _vehicle: &Vehicle, _edge: (NodeId, NodeId)) {
+        self.0.borrow_mut().exits += 1;
+    }
+
+    fn on_arrival(&mut self, _time: u64, _vehicle: &Vehicle) {
+        self.0.borrow_mut().arrivals += 1;
+    }
+
+    fn on_step(&mut self, _time: u64) {
+        self.0.borrow_mut().steps += 1;
+    }
+}
+
+#[test]
+fn test_observers_receive_every_event() {
+    let graph = ring();
+    let scenario = Scenario {
+        num_vehicles: Some(10),
+        seed: Some(7),
+        ..Scenario::default()
+    };
+    let counts = Rc::new(RefCell::new(Counts::default()));
+    let mut simulation = Simulation::new(&graph, &scenario).unwrap();
+    simulation.add_observer(Box::new(Counter(counts.clone())));
+
+    simulation.run_until(3).unwrap();
+    assert_eq!(simulation.time(), 3);
+    assert_eq!(counts.borrow().spawns, 10);
+    assert_eq!(counts.borrow().steps, 3);
+    assert_eq!(simulation.traffic().len(), 10);
+
+    while !simulation.is_done() {
+        simulation.step().unwrap();
+    }
+    let counts = counts.borrow();
+    assert_eq!(counts.arrivals, 10);
+    // every vehicle left each edge it entered
+    assert_eq!(counts.entries, counts.exits);
+    assert_eq!(counts.steps, simulation.time());
+    assert_eq!(simulation.report().classes["car"].finished, 10);
+}
+
+#[test]
+fn test_gridlocked_run_is_given_up() {
+    let graph = ring();
+    // node 1 never turns green, so every vehicle driving through it waits forever
+    let mut intersections = Intersections::default();
+    intersections.insert(
+        &graph,
+        graph.node(1).unwrap(),
+        Controller::Signal {
+            phases: vec![Phase {
+                approaches: Vec::new(),
+                green: 60.0,
+            }],
+            clearance: 0.0,
+            offset: 0.0,
+        },
+    );
+    let report = Simulation::builder(&graph)
+        .with_vehicles(20)
+        .with_seed(Some(7))
+        .with_intersections(intersections)
+        .build()
+        .unwrap()
+        .run()
+        .unwrap();
+
+    assert!(report.gridlocked);
+    assert!(report.classes["car"].finished < 20);
+    // the vehicles still waiting at the signal are lost
+    assert!(report.lost > 0);
+    assert_eq!(report.finished + report.lost, 20);
+    assert!(report.time >= GRIDLOCK_STEPS && report.time < 2 * GRIDLOCK_STEPS);
+}